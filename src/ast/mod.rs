@@ -1,4 +1,5 @@
-use crate::token::Token;
+use crate::token::{Span, Token, TokenType};
+use std::fmt;
 
 // Define a unified Node enum
 #[derive(Debug, Clone)]
@@ -9,12 +10,15 @@ pub enum Node {
 }
 
 // Define Statement enum
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum Statement {
   ForgeStatement(ForgeStatement),
   IgniteStatement(IgniteStatement),
   ExpressionStatement(ExpressionStatement),
   BlockStatement(BlockStatement),
+  BreakStatement(BreakStatement),
+  ContinueStatement(ContinueStatement),
 }
 
 // Define Expression enum
@@ -28,79 +32,573 @@ pub enum Expression {
   IfExpression(IfExpression),
   FunctionLiteral(FunctionLiteral),
   CallExpression(CallExpression),
+  StringLiteral(StringLiteral),
+  ArrayLiteral(ArrayLiteral),
+  IndexExpression(IndexExpression),
+  HashLiteral(HashLiteral),
+  WhileExpression(WhileExpression),
+  ForInExpression(ForInExpression),
+  AssignExpression(AssignExpression),
 }
 
-// The existing structs remain mostly the same
+// Every node records the `Span` of source code it was parsed from, from the
+// start of its first token to the end of its last one.
+#[derive(Debug, Clone, Default)]
 pub struct Program {
   pub statements: Vec<Statement>,
 }
 
+#[derive(Debug, Clone)]
 pub struct ForgeStatement {
   pub token: Token,
+  pub span: Span,
   pub name: Identifier,
-  pub value: Option<Expression>,
+  pub value: Option<Box<Expression>>,
+}
+
+impl ForgeStatement {
+  /// Whether the binding was declared with `flux`, and so can be assigned
+  /// to later. Bindings declared with `forge` can't.
+  pub fn is_mutable(&self) -> bool {
+    self.token.token_type == TokenType::Flux
+  }
 }
 
+#[derive(Debug, Clone)]
 pub struct Identifier {
   pub token: Token,
+  pub span: Span,
   pub value: String,
 }
 
+#[derive(Debug, Clone)]
 pub struct IgniteStatement {
   pub token: Token,
+  pub span: Span,
   pub return_value: Option<Box<Expression>>,
 }
 
+#[derive(Debug, Clone)]
 pub struct ExpressionStatement {
   pub token: Token,
+  pub span: Span,
   pub expression: Option<Box<Expression>>,
 }
 
+#[derive(Debug, Clone)]
 pub struct IntegerLiteral {
   pub token: Token,
+  pub span: Span,
   pub value: i64,
 }
 
+#[derive(Debug, Clone)]
+pub struct StringLiteral {
+  pub token: Token,
+  pub span: Span,
+  pub value: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArrayLiteral {
+  pub token: Token,
+  pub span: Span,
+  pub elements: Vec<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexExpression {
+  pub token: Token,
+  pub span: Span,
+  pub left: Option<Box<Expression>>,
+  pub index: Option<Box<Expression>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HashLiteral {
+  pub token: Token,
+  pub span: Span,
+  /// Key and value expressions, in source order.
+  pub pairs: Vec<(Expression, Expression)>,
+}
+
+#[derive(Debug, Clone)]
 pub struct PrefixExpression {
   pub token: Token,
+  pub span: Span,
   pub operator: String,
   pub right: Option<Box<Expression>>,
 }
 
+#[derive(Debug, Clone)]
 pub struct InfixExpression {
   pub token: Token,
+  pub span: Span,
   pub left: Option<Box<Expression>>,
   pub operator: String,
   pub right: Option<Box<Expression>>,
 }
 
+impl InfixExpression {
+  /// Whether the operator is `&&` or `||`, which only evaluate their right
+  /// side when the left one doesn't decide the result.
+  pub fn is_logical(&self) -> bool {
+    matches!(self.token.token_type, TokenType::And | TokenType::Or)
+  }
+}
+
+#[derive(Debug, Clone)]
 pub struct Boolean {
   pub token: Token,
+  pub span: Span,
   pub value: bool,
 }
 
+#[derive(Debug, Clone)]
 pub struct BlockStatement {
   pub token: Token,
-  pub statements: Vec<Box<Statement>>,
+  pub span: Span,
+  pub statements: Vec<Statement>,
 }
 
+#[derive(Debug, Clone)]
 pub struct IfExpression {
   pub token: Token,
+  pub span: Span,
   pub condition: Option<Box<Expression>>,
   pub consequence: Option<BlockStatement>,
   pub alternative: Option<BlockStatement>,
 }
 
+#[derive(Debug, Clone)]
+pub struct WhileExpression {
+  pub token: Token,
+  pub span: Span,
+  pub condition: Option<Box<Expression>>,
+  pub body: Option<BlockStatement>,
+}
+
+/// `for (variable in iterable) { body }`, which runs `body` once for every
+/// element, character, key or number `iterable` holds.
+#[derive(Debug, Clone)]
+pub struct ForInExpression {
+  pub token: Token,
+  pub span: Span,
+  pub variable: Identifier,
+  pub iterable: Option<Box<Expression>>,
+  pub body: Option<BlockStatement>,
+}
+
+/// `target = value`, or a compound form like `target += value` that applies
+/// the operator to the current value first. Arrays and hashes are values,
+/// so assigning to an element rebinds the name they are stored under to an
+/// updated copy.
+#[derive(Debug, Clone)]
+pub struct AssignExpression {
+  pub token: Token,
+  pub span: Span,
+  pub target: Box<Expression>,
+  pub operator: String,
+  pub value: Option<Box<Expression>>,
+}
+
+impl AssignExpression {
+  /// The infix operator a compound assignment applies, e.g. `+` for `+=`.
+  pub fn compound_operator(&self) -> Option<&str> {
+    self.operator.strip_suffix('=').filter(|op| !op.is_empty())
+  }
+}
+
+/// Something that can be assigned to: a name, or an element reached by
+/// indexing into one, like `grid[y][x]`.
+#[derive(Debug)]
+pub struct Place<'a> {
+  pub name: &'a Identifier,
+  /// The indices applied to the name, outermost first.
+  pub indices: Vec<&'a Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakStatement {
+  pub token: Token,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContinueStatement {
+  pub token: Token,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone)]
 pub struct FunctionLiteral {
   pub token: Token,
+  pub span: Span,
   pub parameters: Vec<Identifier>,
   pub body: Option<BlockStatement>,
 }
 
+#[derive(Debug, Clone)]
 pub struct CallExpression {
   pub token: Token,
+  pub span: Span,
   pub function: Option<Box<Expression>>,
-  pub arguments: Vec<Box<Expression>>,
+  pub arguments: Vec<Expression>,
+}
+
+impl Program {
+  pub fn new() -> Self {
+    Self { statements: vec![] }
+  }
+
+  pub fn token_literal(&self) -> &str {
+    match self.statements.first() {
+      Some(stmt) => stmt.token_literal(),
+      None => "",
+    }
+  }
+}
+
+impl Node {
+  pub fn token_literal(&self) -> &str {
+    match self {
+      Node::Program(program) => program.token_literal(),
+      Node::Statement(stmt) => stmt.token_literal(),
+      Node::Expression(exp) => exp.token_literal(),
+    }
+  }
+}
+
+impl Statement {
+  pub fn span(&self) -> Span {
+    match self {
+      Statement::ForgeStatement(stmt) => stmt.span,
+      Statement::IgniteStatement(stmt) => stmt.span,
+      Statement::ExpressionStatement(stmt) => stmt.span,
+      Statement::BlockStatement(stmt) => stmt.span,
+      Statement::BreakStatement(stmt) => stmt.span,
+      Statement::ContinueStatement(stmt) => stmt.span,
+    }
+  }
+
+  pub fn token_literal(&self) -> &str {
+    match self {
+      Statement::ForgeStatement(stmt) => &stmt.token.literal,
+      Statement::IgniteStatement(stmt) => &stmt.token.literal,
+      Statement::ExpressionStatement(stmt) => &stmt.token.literal,
+      Statement::BlockStatement(stmt) => &stmt.token.literal,
+      Statement::BreakStatement(stmt) => &stmt.token.literal,
+      Statement::ContinueStatement(stmt) => &stmt.token.literal,
+    }
+  }
+}
+
+impl Expression {
+  /// Returns the place this expression refers to, or `None` when it can't
+  /// be assigned to.
+  pub fn as_place(&self) -> Option<Place<'_>> {
+    match self {
+      Expression::Identifier(name) => Some(Place {
+        name,
+        indices: vec![],
+      }),
+      Expression::IndexExpression(exp) => {
+        let mut place = exp.left.as_deref()?.as_place()?;
+        place.indices.push(exp.index.as_deref()?);
+        Some(place)
+      }
+      _ => None,
+    }
+  }
+
+  pub fn span(&self) -> Span {
+    match self {
+      Expression::Identifier(exp) => exp.span,
+      Expression::IntegerLiteral(exp) => exp.span,
+      Expression::PrefixExpression(exp) => exp.span,
+      Expression::InfixExpression(exp) => exp.span,
+      Expression::Boolean(exp) => exp.span,
+      Expression::IfExpression(exp) => exp.span,
+      Expression::FunctionLiteral(exp) => exp.span,
+      Expression::CallExpression(exp) => exp.span,
+      Expression::StringLiteral(exp) => exp.span,
+      Expression::ArrayLiteral(exp) => exp.span,
+      Expression::IndexExpression(exp) => exp.span,
+      Expression::HashLiteral(exp) => exp.span,
+      Expression::WhileExpression(exp) => exp.span,
+      Expression::ForInExpression(exp) => exp.span,
+      Expression::AssignExpression(exp) => exp.span,
+    }
+  }
+
+  pub fn token_literal(&self) -> &str {
+    match self {
+      Expression::Identifier(exp) => &exp.token.literal,
+      Expression::IntegerLiteral(exp) => &exp.token.literal,
+      Expression::PrefixExpression(exp) => &exp.token.literal,
+      Expression::InfixExpression(exp) => &exp.token.literal,
+      Expression::Boolean(exp) => &exp.token.literal,
+      Expression::IfExpression(exp) => &exp.token.literal,
+      Expression::FunctionLiteral(exp) => &exp.token.literal,
+      Expression::CallExpression(exp) => &exp.token.literal,
+      Expression::StringLiteral(exp) => &exp.token.literal,
+      Expression::ArrayLiteral(exp) => &exp.token.literal,
+      Expression::IndexExpression(exp) => &exp.token.literal,
+      Expression::HashLiteral(exp) => &exp.token.literal,
+      Expression::WhileExpression(exp) => &exp.token.literal,
+      Expression::ForInExpression(exp) => &exp.token.literal,
+      Expression::AssignExpression(exp) => &exp.token.literal,
+    }
+  }
+}
+
+/// Writes an optional child node, printing nothing when the parser could not
+/// produce it.
+fn write_option<T: fmt::Display>(
+  f: &mut fmt::Formatter,
+  node: &Option<T>,
+) -> fmt::Result {
+  match node {
+    Some(node) => write!(f, "{}", node),
+    None => Ok(()),
+  }
+}
+
+impl fmt::Display for Node {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Node::Program(program) => write!(f, "{}", program),
+      Node::Statement(stmt) => write!(f, "{}", stmt),
+      Node::Expression(exp) => write!(f, "{}", exp),
+    }
+  }
+}
+
+impl fmt::Display for Program {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for stmt in &self.statements {
+      write!(f, "{}", stmt)?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for Statement {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Statement::ForgeStatement(stmt) => write!(f, "{}", stmt),
+      Statement::IgniteStatement(stmt) => write!(f, "{}", stmt),
+      Statement::ExpressionStatement(stmt) => write!(f, "{}", stmt),
+      Statement::BlockStatement(stmt) => write!(f, "{}", stmt),
+      Statement::BreakStatement(stmt) => write!(f, "{}", stmt),
+      Statement::ContinueStatement(stmt) => write!(f, "{}", stmt),
+    }
+  }
+}
+
+impl fmt::Display for Expression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Expression::Identifier(exp) => write!(f, "{}", exp),
+      Expression::IntegerLiteral(exp) => write!(f, "{}", exp),
+      Expression::PrefixExpression(exp) => write!(f, "{}", exp),
+      Expression::InfixExpression(exp) => write!(f, "{}", exp),
+      Expression::Boolean(exp) => write!(f, "{}", exp),
+      Expression::IfExpression(exp) => write!(f, "{}", exp),
+      Expression::FunctionLiteral(exp) => write!(f, "{}", exp),
+      Expression::CallExpression(exp) => write!(f, "{}", exp),
+      Expression::StringLiteral(exp) => write!(f, "{}", exp),
+      Expression::ArrayLiteral(exp) => write!(f, "{}", exp),
+      Expression::IndexExpression(exp) => write!(f, "{}", exp),
+      Expression::HashLiteral(exp) => write!(f, "{}", exp),
+      Expression::WhileExpression(exp) => write!(f, "{}", exp),
+      Expression::ForInExpression(exp) => write!(f, "{}", exp),
+      Expression::AssignExpression(exp) => write!(f, "{}", exp),
+    }
+  }
+}
+
+impl fmt::Display for ForgeStatement {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {} = ", self.token.literal, self.name)?;
+    write_option(f, &self.value)?;
+    write!(f, ";")
+  }
+}
+
+impl fmt::Display for IgniteStatement {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} ", self.token.literal)?;
+    write_option(f, &self.return_value)?;
+    write!(f, ";")
+  }
+}
+
+impl fmt::Display for ExpressionStatement {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write_option(f, &self.expression)
+  }
+}
+
+impl fmt::Display for BlockStatement {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for stmt in &self.statements {
+      write!(f, "{}", stmt)?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for Identifier {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.value)
+  }
+}
+
+impl fmt::Display for IntegerLiteral {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.token.literal)
+  }
+}
+
+// Prints the literal back in source form, re-escaping what the lexer
+// unescaped.
+impl fmt::Display for StringLiteral {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in self.value.chars() {
+      match ch {
+        '\n' => write!(f, "\\n")?,
+        '\t' => write!(f, "\\t")?,
+        '"' => write!(f, "\\\"")?,
+        '\\' => write!(f, "\\\\")?,
+        _ => write!(f, "{}", ch)?,
+      }
+    }
+    write!(f, "\"")
+  }
+}
+
+impl fmt::Display for ArrayLiteral {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let elements: Vec<String> =
+      self.elements.iter().map(|e| e.to_string()).collect();
+    write!(f, "[{}]", elements.join(", "))
+  }
+}
+
+impl fmt::Display for IndexExpression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "(")?;
+    write_option(f, &self.left)?;
+    write!(f, "[")?;
+    write_option(f, &self.index)?;
+    write!(f, "])")
+  }
+}
+
+impl fmt::Display for HashLiteral {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let pairs: Vec<String> = self
+      .pairs
+      .iter()
+      .map(|(key, value)| format!("{}: {}", key, value))
+      .collect();
+    write!(f, "{{{}}}", pairs.join(", "))
+  }
+}
+
+impl fmt::Display for PrefixExpression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "({}", self.operator)?;
+    write_option(f, &self.right)?;
+    write!(f, ")")
+  }
+}
+
+impl fmt::Display for InfixExpression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "(")?;
+    write_option(f, &self.left)?;
+    write!(f, " {} ", self.operator)?;
+    write_option(f, &self.right)?;
+    write!(f, ")")
+  }
+}
+
+impl fmt::Display for Boolean {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.token.literal)
+  }
+}
+
+impl fmt::Display for IfExpression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "if ")?;
+    write_option(f, &self.condition)?;
+    write!(f, " ")?;
+    write_option(f, &self.consequence)?;
+    if let Some(alternative) = &self.alternative {
+      write!(f, "else {}", alternative)?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for WhileExpression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "while ")?;
+    write_option(f, &self.condition)?;
+    write!(f, " ")?;
+    write_option(f, &self.body)
+  }
+}
+
+impl fmt::Display for ForInExpression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "for {} in ", self.variable)?;
+    write_option(f, &self.iterable)?;
+    write!(f, " ")?;
+    write_option(f, &self.body)
+  }
+}
+
+impl fmt::Display for AssignExpression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "({} {} ", self.target, self.operator)?;
+    write_option(f, &self.value)?;
+    write!(f, ")")
+  }
+}
+
+impl fmt::Display for BreakStatement {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{};", self.token.literal)
+  }
+}
+
+impl fmt::Display for ContinueStatement {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{};", self.token.literal)
+  }
+}
+
+impl fmt::Display for FunctionLiteral {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let params: Vec<String> =
+      self.parameters.iter().map(|p| p.to_string()).collect();
+    write!(f, "{}({})", self.token.literal, params.join(", "))?;
+    write_option(f, &self.body)
+  }
+}
+
+impl fmt::Display for CallExpression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let args: Vec<String> =
+      self.arguments.iter().map(|a| a.to_string()).collect();
+    write_option(f, &self.function)?;
+    write!(f, "({})", args.join(", "))
+  }
 }
 
 #[cfg(test)]
@@ -111,28 +609,22 @@ mod tests {
   #[test]
   fn test_string_value() {
     let program = Program {
-      statements: vec![Box::new(ForgeStatement {
-        token: Token {
-          token_type: TokenType::Forge,
-          literal: String::from("forge"),
-        },
+      statements: vec![Statement::ForgeStatement(ForgeStatement {
+        token: Token::new(TokenType::Forge, String::from("forge")),
+        span: Span::default(),
         name: Identifier {
-          token: Token {
-            token_type: TokenType::Ident,
-            literal: String::from("myForge"),
-          },
+          token: Token::new(TokenType::Ident, String::from("myForge")),
+          span: Span::default(),
           value: String::from("myForge"),
         },
-        value: Some(Box::new(Identifier {
-          token: Token {
-            token_type: TokenType::Ident,
-            literal: String::from("anotherForge"),
-          },
+        value: Some(Box::new(Expression::Identifier(Identifier {
+          token: Token::new(TokenType::Ident, String::from("anotherForge")),
+          span: Span::default(),
           value: String::from("anotherForge"),
-        })),
+        }))),
       })],
     };
 
-    assert_eq!(program.string(), "forge myForge = anotherForge;");
+    assert_eq!(program.to_string(), "forge myForge = anotherForge;");
   }
 }
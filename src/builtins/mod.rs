@@ -0,0 +1,369 @@
+use crate::object::{Builtin, Object, RuntimeError, ARRAY_OBJ, HASH_OBJ};
+use std::{
+  io::{self, Write},
+  rc::Rc,
+};
+
+/// The registry of builtin functions. Identifiers that aren't bound in the
+/// environment are looked up here by name.
+pub const BUILTINS: &[Builtin] = &[
+  Builtin {
+    name: "len",
+    function: len,
+  },
+  Builtin {
+    name: "puts",
+    function: puts,
+  },
+  Builtin {
+    name: "type",
+    function: type_of,
+  },
+  Builtin {
+    name: "str",
+    function: str,
+  },
+  Builtin {
+    name: "int",
+    function: int,
+  },
+  Builtin {
+    name: "first",
+    function: first,
+  },
+  Builtin {
+    name: "last",
+    function: last,
+  },
+  Builtin {
+    name: "rest",
+    function: rest,
+  },
+  Builtin {
+    name: "push",
+    function: push,
+  },
+  Builtin {
+    name: "keys",
+    function: keys,
+  },
+  Builtin {
+    name: "values",
+    function: values,
+  },
+];
+
+/// Finds the builtin called `name`. Bindings in the environment shadow
+/// builtins, so this is only consulted once a lookup there has failed.
+pub fn lookup(name: &str) -> Option<Builtin> {
+  BUILTINS
+    .iter()
+    .find(|builtin| builtin.name == name)
+    .copied()
+}
+
+/// Returns the number of characters in a string, or of elements in an array
+/// or hash.
+fn len(args: &[Object]) -> Object {
+  if let Some(err) = check_arity(args, 1) {
+    return err;
+  }
+
+  match &args[0] {
+    Object::String(value) => Object::Integer(value.chars().count() as i64),
+    Object::Array(elements) => Object::Integer(elements.len() as i64),
+    Object::Hash(pairs) => Object::Integer(pairs.len() as i64),
+    arg => new_error(format!(
+      "argument to `len` not supported, got {}",
+      arg.type_name()
+    )),
+  }
+}
+
+/// Prints each argument on its own line and returns `DarkSide`. Failing to
+/// write, e.g. because whatever read the output has gone away, is an error.
+fn puts(args: &[Object]) -> Object {
+  match write_lines(&mut io::stdout().lock(), args) {
+    Ok(()) => Object::DarkSide,
+    Err(err) => new_error(format!("could not write to stdout: {}", err)),
+  }
+}
+
+fn write_lines(out: &mut impl Write, args: &[Object]) -> io::Result<()> {
+  for arg in args {
+    writeln!(out, "{}", arg.inspect())?;
+  }
+  out.flush()
+}
+
+/// Returns the type name of its argument as a string, e.g. `"INTEGER"`.
+fn type_of(args: &[Object]) -> Object {
+  if let Some(err) = check_arity(args, 1) {
+    return err;
+  }
+
+  Object::String(args[0].type_name().to_owned())
+}
+
+/// Converts its argument to a string, the way the REPL would show it.
+fn str(args: &[Object]) -> Object {
+  if let Some(err) = check_arity(args, 1) {
+    return err;
+  }
+
+  Object::String(args[0].inspect())
+}
+
+/// Converts a string, boolean or integer to an integer.
+fn int(args: &[Object]) -> Object {
+  if let Some(err) = check_arity(args, 1) {
+    return err;
+  }
+
+  match &args[0] {
+    Object::Integer(value) => Object::Integer(*value),
+    Object::Boolean(value) => Object::Integer(*value as i64),
+    Object::String(value) => match value.trim().parse() {
+      Ok(value) => Object::Integer(value),
+      Err(_) => {
+        new_error(format!("could not convert {:?} to an integer", value))
+      }
+    },
+    arg => new_error(format!(
+      "argument to `int` not supported, got {}",
+      arg.type_name()
+    )),
+  }
+}
+
+/// Returns the first element of an array, or `DarkSide` when it is empty.
+fn first(args: &[Object]) -> Object {
+  match array_argument("first", args) {
+    Ok(elements) => elements.first().cloned().unwrap_or(Object::DarkSide),
+    Err(err) => err,
+  }
+}
+
+/// Returns the last element of an array, or `DarkSide` when it is empty.
+fn last(args: &[Object]) -> Object {
+  match array_argument("last", args) {
+    Ok(elements) => elements.last().cloned().unwrap_or(Object::DarkSide),
+    Err(err) => err,
+  }
+}
+
+/// Returns a new array with every element but the first, or `DarkSide` when
+/// the array is empty.
+fn rest(args: &[Object]) -> Object {
+  match array_argument("rest", args) {
+    Ok([]) => Object::DarkSide,
+    Ok([_, rest @ ..]) => Object::Array(Rc::new(rest.to_vec())),
+    Err(err) => err,
+  }
+}
+
+/// Returns a new array with the second argument appended. The original array
+/// is left untouched.
+fn push(args: &[Object]) -> Object {
+  if let Some(err) = check_arity(args, 2) {
+    return err;
+  }
+
+  match array_argument("push", &args[..1]) {
+    Ok(elements) => {
+      let mut elements = elements.to_vec();
+      elements.push(args[1].clone());
+      Object::Array(Rc::new(elements))
+    }
+    Err(err) => err,
+  }
+}
+
+/// Returns the keys of a hash as an array, in a stable order.
+fn keys(args: &[Object]) -> Object {
+  match hash_argument("keys", args) {
+    Ok(pairs) => {
+      Object::Array(Rc::new(pairs.into_iter().map(|p| p.0).collect()))
+    }
+    Err(err) => err,
+  }
+}
+
+/// Returns the values of a hash as an array, in the same order as `keys`.
+fn values(args: &[Object]) -> Object {
+  match hash_argument("values", args) {
+    Ok(pairs) => {
+      Object::Array(Rc::new(pairs.into_iter().map(|p| p.1).collect()))
+    }
+    Err(err) => err,
+  }
+}
+
+/// Checks that a builtin expecting a single hash got one, and returns its
+/// pairs sorted by key.
+fn hash_argument(
+  name: &str,
+  args: &[Object],
+) -> Result<Vec<(Object, Object)>, Object> {
+  if let Some(err) = check_arity(args, 1) {
+    return Err(err);
+  }
+
+  match &args[0] {
+    Object::Hash(pairs) => {
+      let mut pairs: Vec<_> = pairs.iter().collect();
+      pairs.sort_by(|a, b| a.0.cmp(b.0));
+      Ok(
+        pairs
+          .into_iter()
+          .map(|(_, pair)| (pair.key.clone(), pair.value.clone()))
+          .collect(),
+      )
+    }
+    arg => Err(new_error(format!(
+      "argument to `{}` must be {}, got {}",
+      name,
+      HASH_OBJ,
+      arg.type_name()
+    ))),
+  }
+}
+
+/// Checks that a builtin expecting a single array got one.
+fn array_argument<'a>(
+  name: &str,
+  args: &'a [Object],
+) -> Result<&'a [Object], Object> {
+  if let Some(err) = check_arity(args, 1) {
+    return Err(err);
+  }
+
+  match &args[0] {
+    Object::Array(elements) => Ok(elements),
+    arg => Err(new_error(format!(
+      "argument to `{}` must be {}, got {}",
+      name,
+      ARRAY_OBJ,
+      arg.type_name()
+    ))),
+  }
+}
+
+fn check_arity(args: &[Object], want: usize) -> Option<Object> {
+  if args.len() == want {
+    return None;
+  }
+
+  Some(new_error(format!(
+    "wrong number of arguments: want={}, got={}",
+    want,
+    args.len()
+  )))
+}
+
+fn new_error(message: String) -> Object {
+  Object::Error(Box::new(RuntimeError::new(message)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn call(name: &str, args: &[Object]) -> Object {
+    let builtin = lookup(name).expect("builtin not registered");
+    (builtin.function)(args)
+  }
+
+  fn string(value: &str) -> Object {
+    Object::String(value.to_owned())
+  }
+
+  #[test]
+  fn test_lookup() {
+    for builtin in BUILTINS {
+      assert_eq!(lookup(builtin.name), Some(*builtin));
+    }
+    assert_eq!(lookup("nope"), None);
+  }
+
+  #[test]
+  fn test_conversions() {
+    let tests = vec![
+      ("type", vec![Object::Integer(1)], string("INTEGER")),
+      ("type", vec![string("a")], string("STRING")),
+      ("type", vec![Object::DarkSide], string("DARK_SIDE")),
+      ("str", vec![Object::Integer(-12)], string("-12")),
+      ("str", vec![Object::Boolean(true)], string("true")),
+      ("str", vec![string("a")], string("a")),
+      ("int", vec![string(" 42 ")], Object::Integer(42)),
+      ("int", vec![string("-7")], Object::Integer(-7)),
+      ("int", vec![Object::Boolean(true)], Object::Integer(1)),
+      ("int", vec![Object::Integer(3)], Object::Integer(3)),
+      (
+        "puts",
+        vec![string("hi"), Object::Integer(1)],
+        Object::DarkSide,
+      ),
+    ];
+
+    for (name, args, expected) in tests {
+      assert_eq!(call(name, &args), expected, "{}({:?})", name, args);
+    }
+  }
+
+  #[test]
+  fn test_errors() {
+    let tests = vec![
+      ("type", vec![], "wrong number of arguments: want=1, got=0"),
+      (
+        "str",
+        vec![Object::Integer(1), Object::Integer(2)],
+        "wrong number of arguments: want=1, got=2",
+      ),
+      (
+        "int",
+        vec![string("4x")],
+        r#"could not convert "4x" to an integer"#,
+      ),
+      (
+        "int",
+        vec![Object::DarkSide],
+        "argument to `int` not supported, got DARK_SIDE",
+      ),
+      (
+        "keys",
+        vec![Object::Integer(1)],
+        "argument to `keys` must be HASH, got INTEGER",
+      ),
+      ("values", vec![], "wrong number of arguments: want=1, got=0"),
+    ];
+
+    for (name, args, expected) in tests {
+      match call(name, &args) {
+        Object::Error(err) => assert_eq!(err.message, expected),
+        obj => panic!("no error object returned. got={:?}", obj),
+      }
+    }
+  }
+
+  #[test]
+  fn test_write_lines() {
+    let mut out = vec![];
+    write_lines(&mut out, &[string("hi"), Object::Integer(1)]).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "hi\n1\n");
+
+    // A closed pipe, like the one `saber run script.sbr | head -1` leaves.
+    struct ClosedPipe;
+    impl Write for ClosedPipe {
+      fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+      }
+      fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+      }
+    }
+
+    let err = write_lines(&mut ClosedPipe, &[string("hi")]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+  }
+}
@@ -0,0 +1,219 @@
+use saber::Engine;
+use std::{path::PathBuf, slice::Iter};
+
+pub const USAGE: &str = "Usage: saber [COMMAND]
+
+Commands:
+  repl           Start the interactive prompt (default)
+  run <file>     Run a Saber script, or a compiled `.sbc` file
+  build <file>   Compile a Saber script to bytecode
+  disasm <file>  Print the bytecode of a script or a `.sbc` file
+  help           Print this message
+
+Options for `run`:
+  --engine <evaluator|vm>  Choose the backend that runs a script
+
+Options for `build`:
+  -o <file>  Where to write the bytecode (default: the script's name with
+             a `.sbc` extension)";
+
+/// What the user asked the `saber` binary to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+  Repl,
+  Run { path: PathBuf, engine: Engine },
+  Build { input: PathBuf, output: PathBuf },
+  Disasm { path: PathBuf },
+  Help,
+}
+
+impl Command {
+  /// Parses the command line arguments, without the program name.
+  pub fn parse(args: &[String]) -> Result<Self, String> {
+    let mut args = args.iter();
+
+    let command = match args.next().map(String::as_str) {
+      None | Some("repl") => Self::Repl,
+      Some("run") => return Self::parse_run(args),
+      Some("build") => return Self::parse_build(args),
+      Some("disasm") => match args.next() {
+        Some(path) => Self::Disasm {
+          path: PathBuf::from(path),
+        },
+        None => return Err("missing file path for `disasm`".to_owned()),
+      },
+      Some("help" | "-h" | "--help") => Self::Help,
+      Some(other) => return Err(format!("unknown command `{}`", other)),
+    };
+
+    match args.next() {
+      Some(extra) => Err(format!("unexpected argument `{}`", extra)),
+      None => Ok(command),
+    }
+  }
+
+  fn parse_run(mut args: Iter<String>) -> Result<Self, String> {
+    let mut path = None;
+    let mut engine = Engine::default();
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--engine" => engine = parse_engine(args.next())?,
+        option if option.starts_with("--") => {
+          return Err(format!("unknown option `{}`", option))
+        }
+        _ if path.is_none() => path = Some(PathBuf::from(arg)),
+        extra => return Err(format!("unexpected argument `{}`", extra)),
+      }
+    }
+
+    match path {
+      Some(path) => Ok(Self::Run { path, engine }),
+      None => Err("missing script path for `run`".to_owned()),
+    }
+  }
+
+  fn parse_build(mut args: Iter<String>) -> Result<Self, String> {
+    let mut input = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "-o" => match args.next() {
+          Some(path) => output = Some(PathBuf::from(path)),
+          None => return Err("missing output path for `-o`".to_owned()),
+        },
+        option if option.starts_with('-') => {
+          return Err(format!("unknown option `{}`", option))
+        }
+        _ if input.is_none() => input = Some(PathBuf::from(arg)),
+        extra => return Err(format!("unexpected argument `{}`", extra)),
+      }
+    }
+
+    let input = match input {
+      Some(input) => input,
+      None => return Err("missing script path for `build`".to_owned()),
+    };
+    let output = output.unwrap_or_else(|| input.with_extension("sbc"));
+    Ok(Self::Build { input, output })
+  }
+}
+
+fn parse_engine(name: Option<&String>) -> Result<Engine, String> {
+  match name.map(String::as_str) {
+    Some("evaluator") => Ok(Engine::Evaluator),
+    Some("vm") => Ok(Engine::Vm),
+    Some(other) => Err(format!(
+      "unknown engine `{}`, expected `evaluator` or `vm`",
+      other
+    )),
+    None => Err("missing engine name for `--engine`".to_owned()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(args: &[&str]) -> Result<Command, String> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    Command::parse(&args)
+  }
+
+  #[test]
+  fn test_parse_commands() {
+    assert_eq!(parse(&[]), Ok(Command::Repl));
+    assert_eq!(parse(&["repl"]), Ok(Command::Repl));
+    assert_eq!(
+      parse(&["run", "path/to/file.sbr"]),
+      Ok(Command::Run {
+        path: PathBuf::from("path/to/file.sbr"),
+        engine: Engine::Evaluator
+      })
+    );
+    assert_eq!(
+      parse(&["run", "--engine", "vm", "file.sbr"]),
+      Ok(Command::Run {
+        path: PathBuf::from("file.sbr"),
+        engine: Engine::Vm
+      })
+    );
+    assert_eq!(
+      parse(&["run", "file.sbr", "--engine", "evaluator"]),
+      Ok(Command::Run {
+        path: PathBuf::from("file.sbr"),
+        engine: Engine::Evaluator
+      })
+    );
+    assert_eq!(
+      parse(&["build", "foo.sbr", "-o", "out/foo.sbc"]),
+      Ok(Command::Build {
+        input: PathBuf::from("foo.sbr"),
+        output: PathBuf::from("out/foo.sbc")
+      })
+    );
+    assert_eq!(
+      parse(&["build", "dir/foo.sbr"]),
+      Ok(Command::Build {
+        input: PathBuf::from("dir/foo.sbr"),
+        output: PathBuf::from("dir/foo.sbc")
+      })
+    );
+    assert_eq!(
+      parse(&["disasm", "foo.sbc"]),
+      Ok(Command::Disasm {
+        path: PathBuf::from("foo.sbc")
+      })
+    );
+    assert_eq!(parse(&["--help"]), Ok(Command::Help));
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    assert_eq!(
+      parse(&["run"]),
+      Err("missing script path for `run`".to_owned())
+    );
+    assert_eq!(
+      parse(&["compile"]),
+      Err("unknown command `compile`".to_owned())
+    );
+    assert_eq!(
+      parse(&["build"]),
+      Err("missing script path for `build`".to_owned())
+    );
+    assert_eq!(
+      parse(&["build", "foo.sbr", "-o"]),
+      Err("missing output path for `-o`".to_owned())
+    );
+    assert_eq!(
+      parse(&["disasm"]),
+      Err("missing file path for `disasm`".to_owned())
+    );
+    assert_eq!(
+      parse(&["disasm", "a.sbc", "b.sbc"]),
+      Err("unexpected argument `b.sbc`".to_owned())
+    );
+    assert_eq!(
+      parse(&["repl", "extra"]),
+      Err("unexpected argument `extra`".to_owned())
+    );
+    assert_eq!(
+      parse(&["run", "a.sbr", "b.sbr"]),
+      Err("unexpected argument `b.sbr`".to_owned())
+    );
+    assert_eq!(
+      parse(&["run", "a.sbr", "--engine", "jit"]),
+      Err("unknown engine `jit`, expected `evaluator` or `vm`".to_owned())
+    );
+    assert_eq!(
+      parse(&["run", "a.sbr", "--engine"]),
+      Err("missing engine name for `--engine`".to_owned())
+    );
+    assert_eq!(
+      parse(&["run", "--fast", "a.sbr"]),
+      Err("unknown option `--fast`".to_owned())
+    );
+  }
+}
@@ -0,0 +1,433 @@
+use crate::token::Span;
+use std::{convert::TryFrom, fmt};
+
+/// A sequence of encoded instructions. Each one is an opcode byte followed by
+/// its operands in big-endian order.
+pub type Instructions = Vec<u8>;
+
+/// Every instruction the virtual machine understands.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+  /// Pushes the constant at the operand index of the constant pool.
+  Constant,
+  /// Discards the top of the stack.
+  Pop,
+  Add,
+  Sub,
+  Mul,
+  Div,
+  Equal,
+  NotEqual,
+  LessThan,
+  GreaterThan,
+  Minus,
+  Bang,
+  True,
+  False,
+  DarkSide,
+  /// Jumps to the operand offset when the popped condition is not truthy.
+  JumpNotTruthy,
+  Jump,
+  GetGlobal,
+  /// Declares the global at the operand slot, immutably.
+  SetGlobal,
+  GetLocal,
+  /// Declares the local at the operand slot, immutably.
+  SetLocal,
+  GetBuiltin,
+  GetFree,
+  /// Collects the operand count of elements from the stack into an array.
+  Array,
+  /// Collects the operand count of key/value pairs from the stack into a
+  /// hash.
+  Hash,
+  Index,
+  /// Calls the function below the operand count of arguments.
+  Call,
+  ReturnValue,
+  /// Wraps the function prototype at the operand constant index in a closure.
+  Closure,
+  /// Like `Call`, but the result is returned from the current function, so
+  /// a call to a closure reuses the current frame instead of pushing one.
+  TailCall,
+  /// Builds a range from the two integers on top of the stack.
+  Range,
+  /// Replaces the value on top of the stack with what a `for` loop walks
+  /// through, followed by the index of the next element, starting at 0.
+  Iterable,
+  /// Pushes the next element of the loop set up by `Iterable` and advances
+  /// its index, or jumps to the operand offset once there are none left.
+  IterNext,
+  /// Like `SetGlobal`, but fails when the global hasn't been declared yet or
+  /// is immutable.
+  AssignGlobal,
+  /// Assigns to a captured variable, failing when it is immutable.
+  SetFree,
+  /// Pushes the element reached by indexing the value below the top operand
+  /// count of values with each of them in turn, leaving them all in place.
+  IndexPath,
+  /// Pops a value, the operand count of indices and the container below
+  /// them, and pushes the value followed by a copy of the container with the
+  /// element the indices reach replaced by the value.
+  SetIndex,
+  LessEqual,
+  GreaterEqual,
+  Mod,
+  Pow,
+  /// Assigns to a local, failing when it is immutable.
+  AssignLocal,
+  /// Like `SetGlobal`, but the global can be assigned to afterwards.
+  SetMutableGlobal,
+  /// Like `SetLocal`, but the local can be assigned to afterwards.
+  SetMutableLocal,
+}
+
+/// Every opcode, indexed by its byte value.
+const OPCODES: [Opcode; 44] = [
+  Opcode::Constant,
+  Opcode::Pop,
+  Opcode::Add,
+  Opcode::Sub,
+  Opcode::Mul,
+  Opcode::Div,
+  Opcode::Equal,
+  Opcode::NotEqual,
+  Opcode::LessThan,
+  Opcode::GreaterThan,
+  Opcode::Minus,
+  Opcode::Bang,
+  Opcode::True,
+  Opcode::False,
+  Opcode::DarkSide,
+  Opcode::JumpNotTruthy,
+  Opcode::Jump,
+  Opcode::GetGlobal,
+  Opcode::SetGlobal,
+  Opcode::GetLocal,
+  Opcode::SetLocal,
+  Opcode::GetBuiltin,
+  Opcode::GetFree,
+  Opcode::Array,
+  Opcode::Hash,
+  Opcode::Index,
+  Opcode::Call,
+  Opcode::ReturnValue,
+  Opcode::Closure,
+  Opcode::TailCall,
+  Opcode::Range,
+  Opcode::Iterable,
+  Opcode::IterNext,
+  Opcode::AssignGlobal,
+  Opcode::SetFree,
+  Opcode::IndexPath,
+  Opcode::SetIndex,
+  Opcode::LessEqual,
+  Opcode::GreaterEqual,
+  Opcode::Mod,
+  Opcode::Pow,
+  Opcode::AssignLocal,
+  Opcode::SetMutableGlobal,
+  Opcode::SetMutableLocal,
+];
+
+impl TryFrom<u8> for Opcode {
+  type Error = u8;
+
+  fn try_from(byte: u8) -> Result<Self, Self::Error> {
+    OPCODES.get(byte as usize).copied().ok_or(byte)
+  }
+}
+
+/// The `Definition` struct describes how an opcode is encoded.
+///
+/// # Fields
+///
+/// * `name` - The mnemonic used when printing the instruction.
+///
+/// * `operand_widths` - The size in bytes of each operand.
+#[derive(Debug, PartialEq)]
+pub struct Definition {
+  pub name: &'static str,
+  pub operand_widths: &'static [usize],
+}
+
+impl Opcode {
+  pub fn definition(self) -> Definition {
+    let (name, operand_widths): (&'static str, &'static [usize]) = match self {
+      Opcode::Constant => ("OpConstant", &[2]),
+      Opcode::Pop => ("OpPop", &[]),
+      Opcode::Add => ("OpAdd", &[]),
+      Opcode::Sub => ("OpSub", &[]),
+      Opcode::Mul => ("OpMul", &[]),
+      Opcode::Div => ("OpDiv", &[]),
+      Opcode::Equal => ("OpEqual", &[]),
+      Opcode::NotEqual => ("OpNotEqual", &[]),
+      Opcode::LessThan => ("OpLessThan", &[]),
+      Opcode::GreaterThan => ("OpGreaterThan", &[]),
+      Opcode::Minus => ("OpMinus", &[]),
+      Opcode::Bang => ("OpBang", &[]),
+      Opcode::True => ("OpTrue", &[]),
+      Opcode::False => ("OpFalse", &[]),
+      Opcode::DarkSide => ("OpDarkSide", &[]),
+      Opcode::JumpNotTruthy => ("OpJumpNotTruthy", &[2]),
+      Opcode::Jump => ("OpJump", &[2]),
+      Opcode::GetGlobal => ("OpGetGlobal", &[2]),
+      Opcode::SetGlobal => ("OpSetGlobal", &[2]),
+      Opcode::GetLocal => ("OpGetLocal", &[1]),
+      Opcode::SetLocal => ("OpSetLocal", &[1]),
+      Opcode::GetBuiltin => ("OpGetBuiltin", &[1]),
+      Opcode::GetFree => ("OpGetFree", &[1]),
+      Opcode::Array => ("OpArray", &[2]),
+      Opcode::Hash => ("OpHash", &[2]),
+      Opcode::Index => ("OpIndex", &[]),
+      Opcode::Call => ("OpCall", &[1]),
+      Opcode::ReturnValue => ("OpReturnValue", &[]),
+      Opcode::Closure => ("OpClosure", &[2]),
+      Opcode::TailCall => ("OpTailCall", &[1]),
+      Opcode::Range => ("OpRange", &[]),
+      Opcode::Iterable => ("OpIterable", &[]),
+      Opcode::IterNext => ("OpIterNext", &[2]),
+      Opcode::AssignGlobal => ("OpAssignGlobal", &[2]),
+      Opcode::SetFree => ("OpSetFree", &[1]),
+      Opcode::IndexPath => ("OpIndexPath", &[1]),
+      Opcode::SetIndex => ("OpSetIndex", &[1]),
+      Opcode::LessEqual => ("OpLessEqual", &[]),
+      Opcode::GreaterEqual => ("OpGreaterEqual", &[]),
+      Opcode::Mod => ("OpMod", &[]),
+      Opcode::Pow => ("OpPow", &[]),
+      Opcode::AssignLocal => ("OpAssignLocal", &[1]),
+      Opcode::SetMutableGlobal => ("OpSetMutableGlobal", &[2]),
+      Opcode::SetMutableLocal => ("OpSetMutableLocal", &[1]),
+    };
+
+    Definition {
+      name,
+      operand_widths,
+    }
+  }
+}
+
+/// Encodes a single instruction. Operands that don't fit their width are
+/// truncated, so callers must check the limits first.
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+  let def = op.definition();
+  let mut instruction = vec![op as u8];
+
+  for (operand, width) in operands.iter().zip(def.operand_widths) {
+    match width {
+      2 => instruction.extend_from_slice(&(*operand as u16).to_be_bytes()),
+      1 => instruction.push(*operand as u8),
+      _ => unreachable!("unsupported operand width {}", width),
+    }
+  }
+
+  instruction
+}
+
+/// Decodes the operands of an instruction whose opcode has already been read.
+/// Returns the operands and the number of bytes they took up, or `None` when
+/// `ins` is too short to hold them.
+pub fn read_operands(
+  def: &Definition,
+  ins: &[u8],
+) -> Option<(Vec<usize>, usize)> {
+  let mut operands = Vec::with_capacity(def.operand_widths.len());
+  let mut offset = 0;
+
+  for width in def.operand_widths {
+    let bytes = ins.get(offset..offset + width)?;
+    operands.push(match width {
+      2 => read_u16(bytes) as usize,
+      _ => bytes[0] as usize,
+    });
+    offset += width;
+  }
+
+  Some((operands, offset))
+}
+
+pub fn read_u16(ins: &[u8]) -> u16 {
+  u16::from_be_bytes([ins[0], ins[1]])
+}
+
+/// Returns the span of the instruction at `offset`, given the spans of each
+/// instruction keyed by their offset and sorted.
+pub fn span_at(positions: &[(usize, Span)], offset: usize) -> Option<Span> {
+  let index = positions.partition_point(|(start, _)| *start <= offset);
+  Some(positions.get(index.checked_sub(1)?)?.1)
+}
+
+/// Displays an instruction stream one instruction per line, as its offset,
+/// optionally the source line it came from, its mnemonic and its operands:
+///
+/// ```text
+/// 0000    1 OpConstant 0
+/// 0003    1 OpPop
+/// ```
+///
+/// Bytes that don't decode end the listing with a note rather than an error,
+/// so broken bytecode can be inspected too.
+pub struct Disassembly<'a> {
+  instructions: &'a [u8],
+  positions: Option<&'a [(usize, Span)]>,
+}
+
+impl<'a> Disassembly<'a> {
+  pub fn new(instructions: &'a [u8]) -> Self {
+    Self {
+      instructions,
+      positions: None,
+    }
+  }
+
+  /// Also prints the source line of each instruction, looked up in
+  /// `positions` the way `span_at` does.
+  pub fn with_positions(mut self, positions: &'a [(usize, Span)]) -> Self {
+    self.positions = Some(positions);
+    self
+  }
+}
+
+impl fmt::Display for Disassembly<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut offset = 0;
+
+    while offset < self.instructions.len() {
+      write!(f, "{:04} ", offset)?;
+      if let Some(positions) = self.positions {
+        match span_at(positions, offset) {
+          Some(span) => write!(f, "{:>4} ", span.start.line)?,
+          None => write!(f, "   ? ")?,
+        }
+      }
+
+      let byte = self.instructions[offset];
+      let def = match Opcode::try_from(byte) {
+        Ok(op) => op.definition(),
+        Err(byte) => return writeln!(f, "<unknown opcode {}>", byte),
+      };
+      let (operands, read) =
+        match read_operands(&def, &self.instructions[offset + 1..]) {
+          Some(decoded) => decoded,
+          None => return writeln!(f, "{} <truncated operands>", def.name),
+        };
+
+      write!(f, "{}", def.name)?;
+      for operand in operands {
+        write!(f, " {}", operand)?;
+      }
+      writeln!(f)?;
+
+      offset += 1 + read;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_make() {
+    let tests = vec![
+      (
+        Opcode::Constant,
+        vec![65534],
+        vec![Opcode::Constant as u8, 255, 254],
+      ),
+      (Opcode::Add, vec![], vec![Opcode::Add as u8]),
+      (
+        Opcode::GetLocal,
+        vec![255],
+        vec![Opcode::GetLocal as u8, 255],
+      ),
+    ];
+
+    for (op, operands, expected) in tests {
+      assert_eq!(make(op, &operands), expected);
+    }
+  }
+
+  #[test]
+  fn test_read_operands() {
+    let tests = vec![
+      (Opcode::Constant, vec![65535], 2),
+      (Opcode::GetLocal, vec![255], 1),
+      (Opcode::Pop, vec![], 0),
+    ];
+
+    for (op, operands, bytes_read) in tests {
+      let instruction = make(op, &operands);
+      let def = op.definition();
+
+      assert_eq!(
+        read_operands(&def, &instruction[1..]),
+        Some((operands, bytes_read))
+      );
+    }
+    assert_eq!(read_operands(&Opcode::Jump.definition(), &[0]), None);
+  }
+
+  #[test]
+  fn test_opcode_round_trip() {
+    for (byte, op) in OPCODES.iter().enumerate() {
+      assert_eq!(*op as u8, byte as u8);
+      assert_eq!(Opcode::try_from(byte as u8), Ok(*op));
+    }
+    assert_eq!(
+      Opcode::try_from(OPCODES.len() as u8),
+      Err(OPCODES.len() as u8)
+    );
+  }
+
+  #[test]
+  fn test_disassembly() {
+    let instructions = [
+      make(Opcode::Add, &[]),
+      make(Opcode::GetLocal, &[1]),
+      make(Opcode::Constant, &[2]),
+      make(Opcode::Constant, &[65535]),
+    ]
+    .concat();
+
+    let expected = "0000 OpAdd
+0001 OpGetLocal 1
+0003 OpConstant 2
+0006 OpConstant 65535
+";
+    assert_eq!(Disassembly::new(&instructions).to_string(), expected);
+
+    let broken = [make(Opcode::Pop, &[]), vec![200], make(Opcode::Pop, &[])];
+    assert_eq!(
+      Disassembly::new(&broken.concat()).to_string(),
+      "0000 OpPop\n0001 <unknown opcode 200>\n"
+    );
+    assert_eq!(
+      Disassembly::new(&[Opcode::Jump as u8, 0]).to_string(),
+      "0000 OpJump <truncated operands>\n"
+    );
+  }
+
+  #[test]
+  fn test_disassembly_with_positions() {
+    use crate::token::Position;
+
+    let span = |line| Span {
+      start: Position { line, column: 1 },
+      end: Position { line, column: 2 },
+    };
+    let instructions =
+      [make(Opcode::True, &[]), make(Opcode::Pop, &[])].concat();
+    let positions = [(0, span(3)), (1, span(12))];
+
+    assert_eq!(
+      Disassembly::new(&instructions)
+        .with_positions(&positions)
+        .to_string(),
+      "0000    3 OpTrue\n0001   12 OpPop\n"
+    );
+  }
+}
@@ -0,0 +1,1791 @@
+use crate::{
+  ast::{
+    AssignExpression, BlockStatement, Expression, ForInExpression,
+    FunctionLiteral, Identifier, InfixExpression, Program, Statement,
+    WhileExpression,
+  },
+  builtins::BUILTINS,
+  code::{make, read_u16, Instructions, Opcode},
+  object::{Capture, CompiledFunction, Object},
+  token::Span,
+};
+use std::{collections::HashMap, convert::TryFrom, fmt, mem, rc::Rc};
+
+const MAX_LOCALS: usize = u8::MAX as usize + 1;
+const MAX_FREE: usize = u8::MAX as usize + 1;
+const MAX_ARGUMENTS: usize = u8::MAX as usize;
+const MAX_INDICES: usize = u8::MAX as usize;
+const MAX_U16_OPERAND: usize = u16::MAX as usize;
+
+/// Where a name lives at runtime, which decides the instruction that loads
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolScope {
+  Global,
+  Local,
+  Builtin,
+  Free,
+}
+
+/// A name the compiler knows about. `mutable` tells whether its latest
+/// declaration allows assignments, which the virtual machine checks when
+/// they run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+  pub name: String,
+  pub scope: SymbolScope,
+  pub index: usize,
+  pub mutable: bool,
+}
+
+/// The `SymbolTable` struct tracks the names defined in one function, or at
+/// the top level of a program.
+///
+/// # Fields
+///
+/// * `outer` - The table of the enclosing function.
+///
+/// * `store` - The symbols resolved or defined in this scope.
+///
+/// * `num_definitions` - How many slots the scope needs.
+///
+/// * `captures` - Where each free variable of the scope comes from, in `GetFree` index order.
+///
+/// * `free` - The name of each free variable, in the same order.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+  outer: Option<Box<SymbolTable>>,
+  store: HashMap<String, Symbol>,
+  num_definitions: usize,
+  captures: Vec<Capture>,
+  free: Vec<String>,
+}
+
+impl SymbolTable {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn new_enclosed(outer: SymbolTable) -> Self {
+    Self {
+      outer: Some(Box::new(outer)),
+      ..Self::default()
+    }
+  }
+
+  /// Defines an immutable `name` in this scope. Like `forge` in the
+  /// evaluator, defining a name twice in the same scope reuses its slot.
+  pub fn define(&mut self, name: &str) -> Symbol {
+    self.declare(name, false)
+  }
+
+  /// Defines `name` like `define` does, recording whether it can be
+  /// assigned to. Declaring a name again replaces that.
+  pub fn declare(&mut self, name: &str, mutable: bool) -> Symbol {
+    let scope = match self.outer {
+      Some(_) => SymbolScope::Local,
+      None => SymbolScope::Global,
+    };
+
+    let index = match self.store.get(name) {
+      Some(symbol) if symbol.scope == scope => symbol.index,
+      _ => {
+        self.num_definitions += 1;
+        self.num_definitions - 1
+      }
+    };
+
+    let symbol = Symbol {
+      name: name.to_owned(),
+      scope,
+      index,
+      mutable,
+    };
+    self.store.insert(name.to_owned(), symbol.clone());
+    symbol
+  }
+
+  /// Looks `name` up through the enclosing scopes. Locals of an enclosing
+  /// function become free variables of this one.
+  pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+    if let Some(symbol) = self.store.get(name) {
+      return Some(symbol.clone());
+    }
+
+    let symbol = self.outer.as_mut()?.resolve(name)?;
+    match symbol.scope {
+      SymbolScope::Global | SymbolScope::Builtin => Some(symbol),
+      SymbolScope::Local | SymbolScope::Free => Some(self.define_free(symbol)),
+    }
+  }
+
+  /// Defines an immutable `name` in the outermost, global scope.
+  pub fn define_global(&mut self, name: &str) -> Symbol {
+    match &mut self.outer {
+      Some(outer) => outer.define_global(name),
+      None => self.define(name),
+    }
+  }
+
+  /// Returns the names of the global slots, in slot order.
+  pub fn global_names(&self) -> Vec<String> {
+    if let Some(outer) = &self.outer {
+      return outer.global_names();
+    }
+
+    let mut names = vec![String::new(); self.num_definitions];
+    for symbol in self.store.values() {
+      names[symbol.index] = symbol.name.clone();
+    }
+    names
+  }
+
+  /// Returns the names of the local slots of this scope, in slot order.
+  pub fn local_names(&self) -> Vec<String> {
+    let mut names = vec![String::new(); self.num_definitions];
+    for symbol in self.store.values() {
+      if symbol.scope == SymbolScope::Local {
+        names[symbol.index] = symbol.name.clone();
+      }
+    }
+    names
+  }
+
+  fn define_free(&mut self, original: Symbol) -> Symbol {
+    self.captures.push(match original.scope {
+      SymbolScope::Local => Capture::Local(original.index),
+      _ => Capture::Free(original.index),
+    });
+    self.free.push(original.name.clone());
+
+    let symbol = Symbol {
+      scope: SymbolScope::Free,
+      index: self.captures.len() - 1,
+      ..original
+    };
+    self.store.insert(symbol.name.clone(), symbol.clone());
+    symbol
+  }
+}
+
+/// A compiled program, ready to be run by the virtual machine.
+///
+/// # Fields
+///
+/// * `main` - The top level of the program, compiled as a function without parameters.
+///
+/// * `constants` - The constant pool shared by every function.
+///
+/// * `globals` - The name of each global slot, for error messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+  pub main: Rc<CompiledFunction>,
+  pub constants: Vec<Object>,
+  pub globals: Vec<String>,
+}
+
+/// Lists the globals, the constant pool, the main function and then every
+/// function prototype in the pool, which covers nested functions too since
+/// they are constants of their own.
+impl fmt::Display for Bytecode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if !self.globals.is_empty() {
+      writeln!(f, "== globals ==")?;
+      for (index, name) in self.globals.iter().enumerate() {
+        writeln!(f, "{:04} {}", index, name)?;
+      }
+      writeln!(f)?;
+    }
+
+    if !self.constants.is_empty() {
+      writeln!(f, "== constants ==")?;
+      for (index, constant) in self.constants.iter().enumerate() {
+        writeln!(f, "{:04} {}", index, constant.inspect_nested())?;
+      }
+      writeln!(f)?;
+    }
+
+    writeln!(f, "== main ==")?;
+    write!(f, "{}", self.main.disassemble())?;
+
+    for (index, constant) in self.constants.iter().enumerate() {
+      if let Object::CompiledFunction(function) = constant {
+        writeln!(f)?;
+        write_prototype(f, index, function)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+fn write_prototype(
+  f: &mut fmt::Formatter,
+  index: usize,
+  function: &CompiledFunction,
+) -> fmt::Result {
+  writeln!(
+    f,
+    "== constant {}: weave {} ==",
+    index,
+    function.name.as_deref().unwrap_or("<anonymous>")
+  )?;
+  writeln!(
+    f,
+    "parameters: {}, locals: {}",
+    function.parameters.len(),
+    function.locals.len()
+  )?;
+
+  if !function.captures.is_empty() {
+    let captures: Vec<String> = function
+      .captures
+      .iter()
+      .map(|capture| match capture {
+        Capture::Local(index) => format!("local {}", index),
+        Capture::Free(index) => format!("free {}", index),
+      })
+      .collect();
+    writeln!(f, "captures: {}", captures.join(", "))?;
+  }
+
+  write!(f, "{}", function.disassemble())
+}
+
+/// A program that parsed but can't be encoded, e.g. because it exceeds one
+/// of the limits of the instruction format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+  pub message: String,
+  pub span: Span,
+  /// Another location that explains the error, and a label for it.
+  pub related: Option<(Span, String)>,
+}
+
+impl fmt::Display for CompileError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.span, self.message)
+  }
+}
+
+/// The code of the function being compiled.
+///
+/// # Fields
+///
+/// * `instructions` - The bytecode emitted so far.
+///
+/// * `positions` - The span of each instruction, keyed by its offset.
+///
+/// * `stack_depth` - How many values the code emitted so far leaves on the stack.
+///
+/// * `loops` - The loops around the code being compiled, innermost last.
+#[derive(Debug, Default)]
+struct CompilationScope {
+  instructions: Instructions,
+  positions: Vec<(usize, Span)>,
+  stack_depth: usize,
+  loops: Vec<Loop>,
+}
+
+/// A loop being compiled, which `break` and `continue` jump out of.
+///
+/// # Fields
+///
+/// * `start` - Where `continue` jumps to.
+///
+/// * `depth` - The stack depth the body starts at. Values above it are left over from enclosing expressions and are dropped before jumping.
+///
+/// * `breaks` - The jump of every `break`, patched to the end of the loop once it is known.
+#[derive(Debug)]
+struct Loop {
+  start: usize,
+  depth: usize,
+  breaks: Vec<usize>,
+}
+
+/// The `Compiler` struct lowers a `Program` to bytecode. The symbol table and
+/// constant pool outlive a single `compile` call, so a program can be
+/// compiled and run piece by piece, as the REPL does.
+#[derive(Debug, Default)]
+pub struct Compiler {
+  constants: Vec<Object>,
+  symbol_table: SymbolTable,
+  scopes: Vec<CompilationScope>,
+}
+
+impl Compiler {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn compile(
+    &mut self,
+    program: &Program,
+  ) -> Result<Bytecode, CompileError> {
+    self.scopes.push(CompilationScope::default());
+
+    let span = program
+      .statements
+      .last()
+      .map_or(Span::default(), Statement::span);
+    let result = self
+      .compile_statements(&program.statements, span)
+      .and_then(|_| self.emit(Opcode::ReturnValue, &[], span));
+
+    // A failure can leave us inside a nested function, so unwind back to
+    // the global scope before reporting it.
+    while let Some(outer) = self.symbol_table.outer.take() {
+      self.symbol_table = *outer;
+    }
+    let scope = self.scopes.drain(..).next().unwrap_or_default();
+    result?;
+
+    Ok(Bytecode {
+      main: Rc::new(CompiledFunction {
+        instructions: scope.instructions,
+        locals: vec![],
+        parameters: vec![],
+        captures: vec![],
+        free: vec![],
+        positions: scope.positions,
+        name: None,
+      }),
+      constants: self.constants.clone(),
+      globals: self.symbol_table.global_names(),
+    })
+  }
+
+  /// Defines a global, e.g. for a value handed in by an embedder, and
+  /// returns its slot.
+  pub fn define_global(&mut self, name: &str) -> usize {
+    self.symbol_table.define_global(name).index
+  }
+
+  /// Compiles a sequence of statements so that they leave exactly one value
+  /// on the stack: the value of the last one, or `DarkSide`.
+  fn compile_statements(
+    &mut self,
+    statements: &[Statement],
+    span: Span,
+  ) -> Result<(), CompileError> {
+    if statements.is_empty() {
+      return self.emit(Opcode::DarkSide, &[], span);
+    }
+
+    for (i, stmt) in statements.iter().enumerate() {
+      self.compile_statement(stmt, i == statements.len() - 1)?;
+    }
+
+    Ok(())
+  }
+
+  /// Compiles `stmt`, leaving its value on the stack only when `keep` is set.
+  fn compile_statement(
+    &mut self,
+    stmt: &Statement,
+    keep: bool,
+  ) -> Result<(), CompileError> {
+    match stmt {
+      Statement::ExpressionStatement(stmt) => {
+        self
+          .compile_optional_expression(stmt.expression.as_deref(), stmt.span)?;
+        if !keep {
+          self.emit(Opcode::Pop, &[], stmt.span)?;
+        }
+      }
+      Statement::IgniteStatement(stmt) => {
+        self.compile_optional_expression(
+          stmt.return_value.as_deref(),
+          stmt.span,
+        )?;
+        self.emit(Opcode::ReturnValue, &[], stmt.span)?;
+        self.assume_value(keep);
+      }
+      Statement::BlockStatement(block) => {
+        self.compile_statements(&block.statements, block.span)?;
+        if !keep {
+          self.emit(Opcode::Pop, &[], block.span)?;
+        }
+      }
+      Statement::ForgeStatement(stmt) => {
+        let symbol = match stmt.value.as_deref() {
+          // Define the name first so the function can call itself.
+          Some(Expression::FunctionLiteral(lit)) => {
+            let symbol = self.define(&stmt.name, stmt.is_mutable())?;
+            self.compile_function_literal(lit, Some(&stmt.name.value))?;
+            symbol
+          }
+          value => {
+            self.compile_optional_expression(value, stmt.span)?;
+            self.define(&stmt.name, stmt.is_mutable())?
+          }
+        };
+
+        self.store_symbol(&symbol, stmt.name.span)?;
+        if keep {
+          self.emit(Opcode::DarkSide, &[], stmt.span)?;
+        }
+      }
+      Statement::BreakStatement(stmt) => {
+        let position = self.emit_loop_jump(0, stmt.span)?;
+        self.current_loop(stmt.span)?.breaks.push(position);
+        self.assume_value(keep);
+      }
+      Statement::ContinueStatement(stmt) => {
+        let start = self.current_loop(stmt.span)?.start;
+        self.emit_loop_jump(start, stmt.span)?;
+        self.assume_value(keep);
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Code after a jump out of a block never runs, but the rest of the block
+  /// is compiled as if the statement had left its value like any other.
+  fn assume_value(&mut self, keep: bool) {
+    if keep {
+      self.current_scope().stack_depth += 1;
+    }
+  }
+
+  fn compile_optional_expression(
+    &mut self,
+    exp: Option<&Expression>,
+    span: Span,
+  ) -> Result<(), CompileError> {
+    match exp {
+      Some(exp) => self.compile_expression(exp),
+      None => self.emit(Opcode::DarkSide, &[], span),
+    }
+  }
+
+  fn compile_expression(
+    &mut self,
+    exp: &Expression,
+  ) -> Result<(), CompileError> {
+    let span = exp.span();
+
+    match exp {
+      Expression::IntegerLiteral(lit) => {
+        let index = self.add_constant(Object::Integer(lit.value), span)?;
+        self.emit(Opcode::Constant, &[index], span)
+      }
+      Expression::StringLiteral(lit) => {
+        let index =
+          self.add_constant(Object::String(lit.value.clone()), span)?;
+        self.emit(Opcode::Constant, &[index], span)
+      }
+      Expression::Boolean(lit) => match lit.value {
+        true => self.emit(Opcode::True, &[], span),
+        false => self.emit(Opcode::False, &[], span),
+      },
+      Expression::PrefixExpression(exp) => {
+        self.compile_optional_expression(exp.right.as_deref(), span)?;
+        match exp.operator.as_str() {
+          "!" => self.emit(Opcode::Bang, &[], span),
+          "-" => self.emit(Opcode::Minus, &[], span),
+          operator => Err(CompileError {
+            message: format!("unknown operator: {}", operator),
+            span,
+            related: None,
+          }),
+        }
+      }
+      Expression::InfixExpression(exp) if exp.is_logical() => {
+        self.compile_logical_expression(exp)
+      }
+      Expression::InfixExpression(exp) => {
+        self.compile_optional_expression(exp.left.as_deref(), span)?;
+        self.compile_optional_expression(exp.right.as_deref(), span)?;
+        match infix_opcode(&exp.operator) {
+          Some(op) => self.emit(op, &[], span),
+          None => Err(CompileError {
+            message: format!("unknown operator: {}", exp.operator),
+            span,
+            related: None,
+          }),
+        }
+      }
+      Expression::IfExpression(exp) => {
+        self.compile_optional_expression(exp.condition.as_deref(), span)?;
+        let jump_not_truthy = self.emit_jump(Opcode::JumpNotTruthy, span)?;
+
+        match &exp.consequence {
+          Some(block) => {
+            self.compile_statements(&block.statements, block.span)?
+          }
+          None => self.emit(Opcode::DarkSide, &[], span)?,
+        }
+        let jump = self.emit_jump(Opcode::Jump, span)?;
+
+        // Only one branch runs, so both start from the same stack depth.
+        self.current_scope().stack_depth -= 1;
+        self.patch_jump(jump_not_truthy, span)?;
+        match &exp.alternative {
+          Some(block) => {
+            self.compile_statements(&block.statements, block.span)?
+          }
+          None => self.emit(Opcode::DarkSide, &[], span)?,
+        }
+        self.patch_jump(jump, span)
+      }
+      Expression::Identifier(ident) => {
+        let symbol = self.resolve(ident)?;
+        self.load_symbol(&symbol, span)
+      }
+      Expression::FunctionLiteral(lit) => {
+        self.compile_function_literal(lit, None)
+      }
+      Expression::CallExpression(call) => {
+        self.compile_optional_expression(call.function.as_deref(), span)?;
+        if call.arguments.len() > MAX_ARGUMENTS {
+          return Err(CompileError {
+            message: format!(
+              "too many arguments in call: at most {} are allowed",
+              MAX_ARGUMENTS
+            ),
+            span,
+            related: None,
+          });
+        }
+        for arg in &call.arguments {
+          self.compile_expression(arg)?;
+        }
+        self.emit(Opcode::Call, &[call.arguments.len()], span)
+      }
+      Expression::ArrayLiteral(lit) => {
+        for element in &lit.elements {
+          self.compile_expression(element)?;
+        }
+        let len = self.check_u16(lit.elements.len(), "array elements", span)?;
+        self.emit(Opcode::Array, &[len], span)
+      }
+      Expression::HashLiteral(lit) => {
+        for (key, value) in &lit.pairs {
+          self.compile_expression(key)?;
+          self.compile_expression(value)?;
+        }
+        let len = self.check_u16(lit.pairs.len(), "hash pairs", span)?;
+        self.emit(Opcode::Hash, &[len], span)
+      }
+      Expression::IndexExpression(exp) => {
+        self.compile_optional_expression(exp.left.as_deref(), span)?;
+        self.compile_optional_expression(exp.index.as_deref(), span)?;
+        self.emit(Opcode::Index, &[], span)
+      }
+      Expression::WhileExpression(exp) => self.compile_while_expression(exp),
+      Expression::ForInExpression(exp) => self.compile_for_in_expression(exp),
+      Expression::AssignExpression(exp) => self.compile_assign_expression(exp),
+    }
+  }
+
+  /// Compiles `&&` and `||` into jumps, so the right side is skipped when
+  /// the left one decides the result. `compile_boolean` turns the right side
+  /// into the boolean the evaluator gives.
+  fn compile_logical_expression(
+    &mut self,
+    exp: &InfixExpression,
+  ) -> Result<(), CompileError> {
+    let span = exp.span;
+    let is_and = exp.operator == "&&";
+
+    self.compile_optional_expression(exp.left.as_deref(), span)?;
+    let jump_not_truthy = self.emit_jump(Opcode::JumpNotTruthy, span)?;
+    if is_and {
+      self.compile_boolean(exp.right.as_deref(), span)?;
+    } else {
+      self.emit(Opcode::True, &[], span)?;
+    }
+    let jump = self.emit_jump(Opcode::Jump, span)?;
+
+    // Only one of the two paths runs, like the branches of an `if`.
+    self.current_scope().stack_depth -= 1;
+    self.patch_jump(jump_not_truthy, span)?;
+    if is_and {
+      self.emit(Opcode::False, &[], span)?;
+    } else {
+      self.compile_boolean(exp.right.as_deref(), span)?;
+    }
+    self.patch_jump(jump, span)
+  }
+
+  /// Compiles `exp` and negates it twice, which turns any value into whether
+  /// it is truthy.
+  fn compile_boolean(
+    &mut self,
+    exp: Option<&Expression>,
+    span: Span,
+  ) -> Result<(), CompileError> {
+    self.compile_optional_expression(exp, span)?;
+    self.emit(Opcode::Bang, &[], span)?;
+    self.emit(Opcode::Bang, &[], span)
+  }
+
+  /// Evaluates in the same order as the evaluator: the target's indices,
+  /// then the element they reach for a compound assignment, then the value.
+  /// The assignment leaves the stored value on the stack.
+  fn compile_assign_expression(
+    &mut self,
+    exp: &AssignExpression,
+  ) -> Result<(), CompileError> {
+    let span = exp.span;
+    let place = exp.target.as_place().ok_or_else(|| CompileError {
+      message: format!("invalid assignment target: {}", exp.target),
+      span,
+      related: None,
+    })?;
+    let symbol = self.resolve(place.name)?;
+    if symbol.scope == SymbolScope::Builtin {
+      return Err(CompileError {
+        message: format!("cannot assign to builtin: {}", symbol.name),
+        span,
+        related: None,
+      });
+    }
+    let num_indices = place.indices.len();
+    if num_indices > MAX_INDICES {
+      return Err(CompileError {
+        message: format!(
+          "too many indices in assignment: at most {} are allowed",
+          MAX_INDICES
+        ),
+        span,
+        related: None,
+      });
+    }
+
+    if num_indices > 0 || exp.compound_operator().is_some() {
+      self.load_symbol(&symbol, span)?;
+    }
+    for index in &place.indices {
+      self.compile_expression(index)?;
+    }
+    if let Some(operator) = exp.compound_operator() {
+      if num_indices > 0 {
+        self.emit(Opcode::IndexPath, &[num_indices], span)?;
+      }
+      self.compile_optional_expression(exp.value.as_deref(), span)?;
+      let op = infix_opcode(operator).ok_or_else(|| CompileError {
+        message: format!("unknown operator: {}", exp.operator),
+        span,
+        related: None,
+      })?;
+      self.emit(op, &[], span)?;
+    } else {
+      self.compile_optional_expression(exp.value.as_deref(), span)?;
+    }
+
+    if num_indices > 0 {
+      self.emit(Opcode::SetIndex, &[num_indices], span)?;
+      self.assign_symbol(&symbol, span)
+    } else {
+      self.assign_symbol(&symbol, span)?;
+      self.load_symbol(&symbol, span)
+    }
+  }
+
+  /// A `while` loop checks its condition before every pass and leaves
+  /// `DarkSide` once it is done.
+  fn compile_while_expression(
+    &mut self,
+    exp: &WhileExpression,
+  ) -> Result<(), CompileError> {
+    let span = exp.span;
+    let start = self.current_scope().instructions.len();
+
+    self.compile_optional_expression(exp.condition.as_deref(), span)?;
+    let exit = self.emit_jump(Opcode::JumpNotTruthy, span)?;
+
+    self.compile_loop_body(
+      start,
+      exp.body.as_ref().map(|b| &b.statements[..]),
+      span,
+    )?;
+    self.patch_jump(exit, span)?;
+    self.emit(Opcode::DarkSide, &[], span)
+  }
+
+  /// A `for` loop keeps the sequence and the index of the next element on the
+  /// stack while it runs, and stores each element in the loop variable.
+  fn compile_for_in_expression(
+    &mut self,
+    exp: &ForInExpression,
+  ) -> Result<(), CompileError> {
+    let span = exp.span;
+    let iterable_span = exp.iterable.as_ref().map_or(span, |e| e.span());
+
+    self.compile_optional_expression(exp.iterable.as_deref(), span)?;
+    self.emit(Opcode::Iterable, &[], iterable_span)?;
+
+    let start = self.current_scope().instructions.len();
+    let exit = self.emit_jump(Opcode::IterNext, span)?;
+    let symbol = self.define(&exp.variable, false)?;
+    self.store_symbol(&symbol, exp.variable.span)?;
+
+    self.compile_loop_body(
+      start,
+      exp.body.as_ref().map(|b| &b.statements[..]),
+      span,
+    )?;
+    self.patch_jump(exit, span)?;
+    self.emit(Opcode::Pop, &[], span)?;
+    self.emit(Opcode::Pop, &[], span)?;
+    self.emit(Opcode::DarkSide, &[], span)
+  }
+
+  /// Compiles the body of a loop whose next pass starts at `start`, then
+  /// patches every `break` in it to jump past the body.
+  fn compile_loop_body(
+    &mut self,
+    start: usize,
+    statements: Option<&[Statement]>,
+    span: Span,
+  ) -> Result<(), CompileError> {
+    let depth = self.current_scope().stack_depth;
+    self.current_scope().loops.push(Loop {
+      start,
+      depth,
+      breaks: vec![],
+    });
+
+    let result = self
+      .compile_statements(statements.unwrap_or_default(), span)
+      .and_then(|_| self.emit(Opcode::Pop, &[], span))
+      .and_then(|_| self.emit(Opcode::Jump, &[start], span));
+
+    let finished = self.current_scope().loops.pop().expect("no loop");
+    result?;
+    for position in finished.breaks {
+      self.patch_jump(position, span)?;
+    }
+    Ok(())
+  }
+
+  /// Emits the jump of a `break` or `continue` to `target` and returns its
+  /// offset. Values that enclosing expressions left above the depth the loop
+  /// body started at are popped first.
+  fn emit_loop_jump(
+    &mut self,
+    target: usize,
+    span: Span,
+  ) -> Result<usize, CompileError> {
+    let depth = self.current_loop(span)?.depth;
+    let stack_depth = self.current_scope().stack_depth;
+    for _ in depth..stack_depth {
+      self.emit(Opcode::Pop, &[], span)?;
+    }
+
+    let position = self.current_scope().instructions.len();
+    self.emit(Opcode::Jump, &[target], span)?;
+    // The enclosing expressions are still compiled as if the values were
+    // there, even though the rest of them never runs.
+    self.current_scope().stack_depth = stack_depth;
+    Ok(position)
+  }
+
+  fn current_loop(&mut self, span: Span) -> Result<&mut Loop, CompileError> {
+    self
+      .current_scope()
+      .loops
+      .last_mut()
+      .ok_or_else(|| CompileError {
+        message: "`break` or `continue` outside of a loop".to_owned(),
+        span,
+        related: None,
+      })
+  }
+
+  fn compile_function_literal(
+    &mut self,
+    lit: &FunctionLiteral,
+    name: Option<&str>,
+  ) -> Result<(), CompileError> {
+    self.enter_scope();
+
+    let result = self.compile_function_body(lit);
+    let (scope, table) = self.leave_scope();
+    result?;
+
+    let mut instructions = scope.instructions;
+    mark_tail_calls(&mut instructions);
+
+    let function = CompiledFunction {
+      instructions,
+      locals: table.local_names(),
+      parameters: lit.parameters.iter().map(|param| param.span).collect(),
+      captures: table.captures,
+      free: table.free,
+      positions: scope.positions,
+      name: name.map(str::to_owned),
+    };
+    if function.captures.len() > MAX_FREE {
+      return Err(CompileError {
+        message: format!(
+          "too many captured variables: at most {} are allowed",
+          MAX_FREE
+        ),
+        span: lit.span,
+        related: None,
+      });
+    }
+
+    let index = self
+      .add_constant(Object::CompiledFunction(Rc::new(function)), lit.span)?;
+    self.emit(Opcode::Closure, &[index], lit.span)
+  }
+
+  fn compile_function_body(
+    &mut self,
+    lit: &FunctionLiteral,
+  ) -> Result<(), CompileError> {
+    for param in &lit.parameters {
+      self.define(param, false)?;
+    }
+
+    let (statements, span) = match &lit.body {
+      Some(body) => (&body.statements[..], body.span),
+      None => (&[][..], lit.span),
+    };
+
+    // A call evaluates its whole body in one scope, so every name the body
+    // declares is defined up front. That way functions can call each other
+    // regardless of the order they appear in, and a closure made before a
+    // declaration captures the local rather than a global of the same name.
+    let mut names = vec![];
+    declared_names(statements, &mut names);
+    for (name, mutable) in names {
+      self.define(name, mutable)?;
+    }
+
+    self.compile_statements(statements, span)?;
+    self.emit(Opcode::ReturnValue, &[], span)
+  }
+
+  fn define(
+    &mut self,
+    ident: &Identifier,
+    mutable: bool,
+  ) -> Result<Symbol, CompileError> {
+    let symbol = self.symbol_table.declare(&ident.value, mutable);
+    self.check_symbol(symbol, ident.span)
+  }
+
+  /// Resolves a name the way the evaluator looks it up: bindings first, then
+  /// builtins. Names that aren't known yet become globals, which report
+  /// "identifier not found" if they are still unset when read.
+  fn resolve(&mut self, ident: &Identifier) -> Result<Symbol, CompileError> {
+    if let Some(symbol) = self.symbol_table.resolve(&ident.value) {
+      return Ok(symbol);
+    }
+
+    if let Some(index) = BUILTINS.iter().position(|b| b.name == ident.value) {
+      return Ok(Symbol {
+        name: ident.value.clone(),
+        scope: SymbolScope::Builtin,
+        index,
+        mutable: false,
+      });
+    }
+
+    let symbol = self.symbol_table.define_global(&ident.value);
+    self.check_symbol(symbol, ident.span)
+  }
+
+  fn check_symbol(
+    &self,
+    symbol: Symbol,
+    span: Span,
+  ) -> Result<Symbol, CompileError> {
+    let (limit, what) = match symbol.scope {
+      SymbolScope::Global => (MAX_U16_OPERAND + 1, "global variables"),
+      _ => (MAX_LOCALS, "local variables in one function"),
+    };
+
+    if symbol.index >= limit {
+      return Err(CompileError {
+        message: format!("too many {}: at most {} are allowed", what, limit),
+        span,
+        related: None,
+      });
+    }
+    Ok(symbol)
+  }
+
+  fn load_symbol(
+    &mut self,
+    symbol: &Symbol,
+    span: Span,
+  ) -> Result<(), CompileError> {
+    let op = match symbol.scope {
+      SymbolScope::Global => Opcode::GetGlobal,
+      SymbolScope::Local => Opcode::GetLocal,
+      SymbolScope::Builtin => Opcode::GetBuiltin,
+      SymbolScope::Free => Opcode::GetFree,
+    };
+    self.emit(op, &[symbol.index], span)
+  }
+
+  /// Declares `symbol` with the value on top of the stack. `span` is the
+  /// declared name, which an assignment to an immutable binding points back
+  /// to.
+  fn store_symbol(
+    &mut self,
+    symbol: &Symbol,
+    span: Span,
+  ) -> Result<(), CompileError> {
+    let op = match (symbol.scope, symbol.mutable) {
+      (SymbolScope::Global, false) => Opcode::SetGlobal,
+      (SymbolScope::Global, true) => Opcode::SetMutableGlobal,
+      (_, false) => Opcode::SetLocal,
+      (_, true) => Opcode::SetMutableLocal,
+    };
+    self.emit(op, &[symbol.index], span)
+  }
+
+  /// Stores the top of the stack in an existing binding, unlike
+  /// `store_symbol` which defines it.
+  fn assign_symbol(
+    &mut self,
+    symbol: &Symbol,
+    span: Span,
+  ) -> Result<(), CompileError> {
+    match symbol.scope {
+      SymbolScope::Global => {
+        self.emit(Opcode::AssignGlobal, &[symbol.index], span)
+      }
+      SymbolScope::Local => {
+        self.emit(Opcode::AssignLocal, &[symbol.index], span)
+      }
+      SymbolScope::Free => self.emit(Opcode::SetFree, &[symbol.index], span),
+      SymbolScope::Builtin => unreachable!("builtins can't be assigned to"),
+    }
+  }
+
+  fn add_constant(
+    &mut self,
+    obj: Object,
+    span: Span,
+  ) -> Result<usize, CompileError> {
+    self.constants.push(obj);
+    self.check_u16(self.constants.len() - 1, "constants", span)
+  }
+
+  fn check_u16(
+    &self,
+    value: usize,
+    what: &str,
+    span: Span,
+  ) -> Result<usize, CompileError> {
+    if value > MAX_U16_OPERAND {
+      return Err(CompileError {
+        message: format!(
+          "too many {}: at most {} are allowed",
+          what,
+          MAX_U16_OPERAND + 1
+        ),
+        span,
+        related: None,
+      });
+    }
+    Ok(value)
+  }
+
+  fn emit(
+    &mut self,
+    op: Opcode,
+    operands: &[usize],
+    span: Span,
+  ) -> Result<(), CompileError> {
+    let scope = self.current_scope();
+    let position = scope.instructions.len();
+
+    scope.instructions.extend(make(op, operands));
+    scope.positions.push((position, span));
+    scope.stack_depth = scope
+      .stack_depth
+      .checked_add_signed(stack_effect(op, operands))
+      .expect("popped more values than were pushed");
+    Ok(())
+  }
+
+  /// Emits a jump with a placeholder target and returns its offset, to be
+  /// fixed up by `patch_jump`.
+  fn emit_jump(
+    &mut self,
+    op: Opcode,
+    span: Span,
+  ) -> Result<usize, CompileError> {
+    let position = self.current_scope().instructions.len();
+    self.emit(op, &[0], span)?;
+    Ok(position)
+  }
+
+  /// Points the jump at `position` to the next instruction to be emitted.
+  fn patch_jump(
+    &mut self,
+    position: usize,
+    span: Span,
+  ) -> Result<(), CompileError> {
+    let target = self.current_scope().instructions.len();
+    let target =
+      self.check_u16(target, "bytes of bytecode in one function", span)?;
+
+    let scope = self.current_scope();
+    scope.instructions[position + 1..position + 3]
+      .copy_from_slice(&(target as u16).to_be_bytes());
+    Ok(())
+  }
+
+  fn current_scope(&mut self) -> &mut CompilationScope {
+    self.scopes.last_mut().expect("no compilation scope")
+  }
+
+  fn enter_scope(&mut self) {
+    self.scopes.push(CompilationScope::default());
+    let outer = mem::take(&mut self.symbol_table);
+    self.symbol_table = SymbolTable::new_enclosed(outer);
+  }
+
+  fn leave_scope(&mut self) -> (CompilationScope, SymbolTable) {
+    let scope = self.scopes.pop().expect("no compilation scope");
+    let outer = self
+      .symbol_table
+      .outer
+      .take()
+      .expect("left the global scope");
+    let table = mem::replace(&mut self.symbol_table, *outer);
+    (scope, table)
+  }
+}
+
+fn infix_opcode(operator: &str) -> Option<Opcode> {
+  let op = match operator {
+    "+" => Opcode::Add,
+    "-" => Opcode::Sub,
+    "*" => Opcode::Mul,
+    "/" => Opcode::Div,
+    "%" => Opcode::Mod,
+    "**" => Opcode::Pow,
+    "==" => Opcode::Equal,
+    "!=" => Opcode::NotEqual,
+    "<" => Opcode::LessThan,
+    ">" => Opcode::GreaterThan,
+    "<=" => Opcode::LessEqual,
+    ">=" => Opcode::GreaterEqual,
+    ".." => Opcode::Range,
+    _ => return None,
+  };
+  Some(op)
+}
+
+/// How many values running `op` leaves on the stack, less the ones it takes
+/// off. Jumps that only sometimes push count as if they fall through.
+fn stack_effect(op: Opcode, operands: &[usize]) -> isize {
+  let count = || operands.first().map_or(0, |count| *count as isize);
+
+  match op {
+    Opcode::Constant
+    | Opcode::True
+    | Opcode::False
+    | Opcode::DarkSide
+    | Opcode::GetGlobal
+    | Opcode::GetLocal
+    | Opcode::GetBuiltin
+    | Opcode::GetFree
+    | Opcode::Closure
+    | Opcode::Iterable
+    | Opcode::IterNext
+    | Opcode::IndexPath => 1,
+    Opcode::Minus | Opcode::Bang | Opcode::Jump => 0,
+    Opcode::Pop
+    | Opcode::Add
+    | Opcode::Sub
+    | Opcode::Mul
+    | Opcode::Div
+    | Opcode::Mod
+    | Opcode::Pow
+    | Opcode::Equal
+    | Opcode::NotEqual
+    | Opcode::LessThan
+    | Opcode::GreaterThan
+    | Opcode::LessEqual
+    | Opcode::GreaterEqual
+    | Opcode::Range
+    | Opcode::JumpNotTruthy
+    | Opcode::SetGlobal
+    | Opcode::SetMutableGlobal
+    | Opcode::AssignGlobal
+    | Opcode::SetLocal
+    | Opcode::SetMutableLocal
+    | Opcode::AssignLocal
+    | Opcode::SetFree
+    | Opcode::Index
+    | Opcode::ReturnValue => -1,
+    Opcode::Array => 1 - count(),
+    Opcode::Hash => 1 - 2 * count(),
+    // The callee and its arguments are replaced by the result.
+    Opcode::Call | Opcode::TailCall => -count(),
+    // The value is pushed back, followed by the updated container.
+    Opcode::SetIndex => -count(),
+  }
+}
+
+/// Collects the names `statements` declare with `forge`, `flux` or as a `for`
+/// loop variable, including in nested blocks but not in nested functions.
+fn declared_names<'a>(
+  statements: &'a [Statement],
+  names: &mut Vec<(&'a Identifier, bool)>,
+) {
+  for stmt in statements {
+    match stmt {
+      Statement::ForgeStatement(stmt) => {
+        names.push((&stmt.name, stmt.is_mutable()));
+        declared_names_in(stmt.value.as_deref(), names);
+      }
+      Statement::IgniteStatement(stmt) => {
+        declared_names_in(stmt.return_value.as_deref(), names)
+      }
+      Statement::ExpressionStatement(stmt) => {
+        declared_names_in(stmt.expression.as_deref(), names)
+      }
+      Statement::BlockStatement(block) => {
+        declared_names(&block.statements, names)
+      }
+      Statement::BreakStatement(_) | Statement::ContinueStatement(_) => {}
+    }
+  }
+}
+
+fn declared_names_in<'a>(
+  exp: Option<&'a Expression>,
+  names: &mut Vec<(&'a Identifier, bool)>,
+) {
+  let block = |block: &'a Option<BlockStatement>, names: &mut Vec<_>| {
+    if let Some(block) = block {
+      declared_names(&block.statements, names);
+    }
+  };
+
+  match exp {
+    None
+    | Some(
+      Expression::Identifier(_)
+      | Expression::IntegerLiteral(_)
+      | Expression::Boolean(_)
+      | Expression::StringLiteral(_)
+      | Expression::FunctionLiteral(_),
+    ) => {}
+    Some(Expression::PrefixExpression(exp)) => {
+      declared_names_in(exp.right.as_deref(), names)
+    }
+    Some(Expression::InfixExpression(exp)) => {
+      declared_names_in(exp.left.as_deref(), names);
+      declared_names_in(exp.right.as_deref(), names);
+    }
+    Some(Expression::IfExpression(exp)) => {
+      declared_names_in(exp.condition.as_deref(), names);
+      block(&exp.consequence, names);
+      block(&exp.alternative, names);
+    }
+    Some(Expression::CallExpression(exp)) => {
+      declared_names_in(exp.function.as_deref(), names);
+      for arg in &exp.arguments {
+        declared_names_in(Some(arg), names);
+      }
+    }
+    Some(Expression::ArrayLiteral(lit)) => {
+      for element in &lit.elements {
+        declared_names_in(Some(element), names);
+      }
+    }
+    Some(Expression::IndexExpression(exp)) => {
+      declared_names_in(exp.left.as_deref(), names);
+      declared_names_in(exp.index.as_deref(), names);
+    }
+    Some(Expression::HashLiteral(lit)) => {
+      for (key, value) in &lit.pairs {
+        declared_names_in(Some(key), names);
+        declared_names_in(Some(value), names);
+      }
+    }
+    Some(Expression::WhileExpression(exp)) => {
+      declared_names_in(exp.condition.as_deref(), names);
+      block(&exp.body, names);
+    }
+    Some(Expression::ForInExpression(exp)) => {
+      declared_names_in(exp.iterable.as_deref(), names);
+      names.push((&exp.variable, false));
+      block(&exp.body, names);
+    }
+    Some(Expression::AssignExpression(exp)) => {
+      declared_names_in(Some(&exp.target), names);
+      declared_names_in(exp.value.as_deref(), names);
+    }
+  }
+}
+
+/// Turns every `Call` whose result is returned straight away into a
+/// `TailCall`. Jumps are followed, since a call ending one branch of an `if`
+/// jumps over the other branch before returning.
+fn mark_tail_calls(instructions: &mut [u8]) {
+  let opcode_at = |offset: usize| {
+    instructions
+      .get(offset)
+      .and_then(|byte| Opcode::try_from(*byte).ok())
+  };
+
+  let mut calls = vec![];
+  let mut offset = 0;
+  while let Some(op) = opcode_at(offset) {
+    let width: usize = op.definition().operand_widths.iter().sum();
+    let mut next = offset + 1 + width;
+
+    if op == Opcode::Call {
+      // Only forward jumps are followed, so the chain always ends.
+      loop {
+        match opcode_at(next) {
+          Some(Opcode::Jump) if next + 2 < instructions.len() => {
+            let target = read_u16(&instructions[next + 1..]) as usize;
+            if target <= next {
+              break;
+            }
+            next = target;
+          }
+          Some(Opcode::ReturnValue) => {
+            calls.push(offset);
+            break;
+          }
+          _ => break,
+        }
+      }
+    }
+
+    offset += 1 + width;
+  }
+
+  for offset in calls {
+    instructions[offset] = Opcode::TailCall as u8;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{lexer::Lexer, parser::Parser};
+
+  fn compile(input: &str) -> Bytecode {
+    let mut p = Parser::new(Lexer::new(input.to_owned()));
+    let program = p.parse_program().unwrap();
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+
+    Compiler::new().compile(&program).unwrap()
+  }
+
+  fn concat(instructions: Vec<Instructions>) -> Instructions {
+    instructions.concat()
+  }
+
+  fn compiled_function(obj: &Object) -> &CompiledFunction {
+    match obj {
+      Object::CompiledFunction(function) => function,
+      obj => panic!("constant is not a CompiledFunction. got={:?}", obj),
+    }
+  }
+
+  #[test]
+  fn test_integer_arithmetic() {
+    let bytecode = compile("1 + 2; 3 < 4");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        make(Opcode::Constant, &[0]),
+        make(Opcode::Constant, &[1]),
+        make(Opcode::Add, &[]),
+        make(Opcode::Pop, &[]),
+        make(Opcode::Constant, &[2]),
+        make(Opcode::Constant, &[3]),
+        make(Opcode::LessThan, &[]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+    assert_eq!(
+      bytecode.constants,
+      vec![
+        Object::Integer(1),
+        Object::Integer(2),
+        Object::Integer(3),
+        Object::Integer(4),
+      ]
+    );
+
+    let bytecode = compile("1 % 2 <= 3 ** 4 ** 5 >= 6");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        make(Opcode::Constant, &[0]),
+        make(Opcode::Constant, &[1]),
+        make(Opcode::Mod, &[]),
+        make(Opcode::Constant, &[2]),
+        make(Opcode::Constant, &[3]),
+        make(Opcode::Constant, &[4]),
+        make(Opcode::Pow, &[]),
+        make(Opcode::Pow, &[]),
+        make(Opcode::LessEqual, &[]),
+        make(Opcode::Constant, &[5]),
+        make(Opcode::GreaterEqual, &[]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+  }
+
+  #[test]
+  fn test_conditionals() {
+    let bytecode = compile("if (true) { 10 }; 3333;");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        // 0000
+        make(Opcode::True, &[]),
+        // 0001
+        make(Opcode::JumpNotTruthy, &[10]),
+        // 0004
+        make(Opcode::Constant, &[0]),
+        // 0007
+        make(Opcode::Jump, &[11]),
+        // 0010
+        make(Opcode::DarkSide, &[]),
+        // 0011
+        make(Opcode::Pop, &[]),
+        // 0012
+        make(Opcode::Constant, &[1]),
+        // 0015
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+  }
+
+  #[test]
+  fn test_logical_operators() {
+    let bytecode = compile("true && false");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        // 0000
+        make(Opcode::True, &[]),
+        // 0001
+        make(Opcode::JumpNotTruthy, &[10]),
+        // 0004
+        make(Opcode::False, &[]),
+        // 0005
+        make(Opcode::Bang, &[]),
+        // 0006
+        make(Opcode::Bang, &[]),
+        // 0007
+        make(Opcode::Jump, &[11]),
+        // 0010
+        make(Opcode::False, &[]),
+        // 0011
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+
+    let bytecode = compile("true || false");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        // 0000
+        make(Opcode::True, &[]),
+        // 0001
+        make(Opcode::JumpNotTruthy, &[8]),
+        // 0004
+        make(Opcode::True, &[]),
+        // 0005
+        make(Opcode::Jump, &[11]),
+        // 0008
+        make(Opcode::False, &[]),
+        // 0009
+        make(Opcode::Bang, &[]),
+        // 0010
+        make(Opcode::Bang, &[]),
+        // 0011
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+  }
+
+  #[test]
+  fn test_global_forge_statements() {
+    let bytecode = compile("forge one = 1; forge two = one; two;");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        make(Opcode::Constant, &[0]),
+        make(Opcode::SetGlobal, &[0]),
+        make(Opcode::GetGlobal, &[0]),
+        make(Opcode::SetGlobal, &[1]),
+        make(Opcode::GetGlobal, &[1]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+    assert_eq!(bytecode.globals, vec!["one", "two"]);
+  }
+
+  #[test]
+  fn test_functions_and_closures() {
+    let bytecode = compile(
+      "
+      forge adder = weave(a) {
+        weave(b) { a + b + len }
+      };
+      ",
+    );
+
+    let inner = compiled_function(&bytecode.constants[0]);
+    assert_eq!(
+      inner.instructions,
+      concat(vec![
+        make(Opcode::GetFree, &[0]),
+        make(Opcode::GetLocal, &[0]),
+        make(Opcode::Add, &[]),
+        make(Opcode::GetBuiltin, &[0]),
+        make(Opcode::Add, &[]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+    assert_eq!(inner.captures, vec![Capture::Local(0)]);
+    assert_eq!(inner.parameters.len(), 1);
+
+    let outer = compiled_function(&bytecode.constants[1]);
+    assert_eq!(
+      outer.instructions,
+      concat(vec![
+        make(Opcode::Closure, &[0]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+    assert_eq!(outer.name.as_deref(), Some("adder"));
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        make(Opcode::Closure, &[1]),
+        make(Opcode::SetGlobal, &[0]),
+        make(Opcode::DarkSide, &[]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+  }
+
+  #[test]
+  fn test_tail_calls() {
+    let bytecode = compile(
+      "
+      weave(f) { f(1) };
+      weave(f) { if (true) { f() } else { 1 } };
+      weave(f) { f() + 1 };
+      f();
+      ",
+    );
+
+    let function = compiled_function(&bytecode.constants[1]);
+    assert_eq!(
+      function.instructions,
+      concat(vec![
+        make(Opcode::GetLocal, &[0]),
+        make(Opcode::Constant, &[0]),
+        make(Opcode::TailCall, &[1]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+
+    // A call ending the consequence jumps over the alternative to return.
+    let function = compiled_function(&bytecode.constants[3]);
+    assert_eq!(
+      function.instructions,
+      concat(vec![
+        make(Opcode::True, &[]),
+        make(Opcode::JumpNotTruthy, &[11]),
+        make(Opcode::GetLocal, &[0]),
+        make(Opcode::TailCall, &[0]),
+        make(Opcode::Jump, &[14]),
+        make(Opcode::Constant, &[2]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+
+    let function = compiled_function(&bytecode.constants[5]);
+    assert_eq!(function.instructions[2], Opcode::Call as u8);
+
+    // The top level is left alone: it only runs once.
+    assert_eq!(
+      bytecode.main.instructions[bytecode.main.instructions.len() - 3],
+      Opcode::Call as u8
+    );
+  }
+
+  #[test]
+  fn test_symbol_table_resolves_free_variables() {
+    let mut global = SymbolTable::new();
+    global.define("a");
+    let mut first = SymbolTable::new_enclosed(global);
+    first.define("b");
+    let mut second = SymbolTable::new_enclosed(first);
+    second.define("c");
+
+    let resolved: Vec<(SymbolScope, usize)> = ["a", "b", "c"]
+      .iter()
+      .map(|name| {
+        let symbol = second.resolve(name).unwrap();
+        (symbol.scope, symbol.index)
+      })
+      .collect();
+
+    assert_eq!(
+      resolved,
+      vec![
+        (SymbolScope::Global, 0),
+        (SymbolScope::Free, 0),
+        (SymbolScope::Local, 0),
+      ]
+    );
+    assert_eq!(second.captures, vec![Capture::Local(0)]);
+    assert_eq!(second.resolve("d"), None);
+  }
+
+  #[test]
+  fn test_positions() {
+    let bytecode = compile("forge a = 1;\na + true");
+
+    let lines: Vec<usize> = bytecode
+      .main
+      .positions
+      .iter()
+      .map(|(_, span)| span.start.line)
+      .collect();
+    assert_eq!(lines, vec![1, 1, 2, 2, 2, 2]);
+    assert_eq!(bytecode.main.span_at(6).map(|s| s.start.column), Some(1));
+  }
+
+  #[test]
+  fn test_bytecode_display() {
+    let bytecode = compile(
+      "forge n = 1;
+forge add = weave(x) {
+  weave(y) { x + y + n }
+};
+add(\"a\")",
+    );
+
+    let expected = r#"== globals ==
+0000 n
+0001 add
+
+== constants ==
+0000 1
+0001 compiled weave <anonymous>
+0002 compiled weave add
+0003 "a"
+
+== main ==
+0000    1 OpConstant 0
+0003    1 OpSetGlobal 0
+0006    2 OpClosure 2
+0009    2 OpSetGlobal 1
+0012    5 OpGetGlobal 1
+0015    5 OpConstant 3
+0018    5 OpCall 1
+0020    5 OpReturnValue
+
+== constant 1: weave <anonymous> ==
+parameters: 1, locals: 1
+captures: local 0
+0000    3 OpGetFree 0
+0002    3 OpGetLocal 0
+0004    3 OpAdd
+0005    3 OpGetGlobal 0
+0008    3 OpAdd
+0009    3 OpReturnValue
+
+== constant 2: weave add ==
+parameters: 1, locals: 1
+0000    3 OpClosure 1
+0003    2 OpReturnValue
+"#;
+    assert_eq!(bytecode.to_string(), expected);
+  }
+
+  #[test]
+  fn test_loops() {
+    let bytecode = compile("while (true) { break; }");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        // 0000
+        make(Opcode::True, &[]),
+        // 0001
+        make(Opcode::JumpNotTruthy, &[11]),
+        // 0004
+        make(Opcode::Jump, &[11]),
+        // 0007
+        make(Opcode::Pop, &[]),
+        // 0008
+        make(Opcode::Jump, &[0]),
+        // 0011
+        make(Opcode::DarkSide, &[]),
+        // 0012
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+
+    // The array element under construction is dropped before leaving.
+    let bytecode = compile("for (x in y) { [x, if (true) { continue; }]; }");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        // 0000
+        make(Opcode::GetGlobal, &[0]),
+        // 0003
+        make(Opcode::Iterable, &[]),
+        // 0004
+        make(Opcode::IterNext, &[32]),
+        // 0007
+        make(Opcode::SetGlobal, &[1]),
+        // 0010
+        make(Opcode::GetGlobal, &[1]),
+        // 0013
+        make(Opcode::True, &[]),
+        // 0014
+        make(Opcode::JumpNotTruthy, &[24]),
+        // 0017
+        make(Opcode::Pop, &[]),
+        // 0018
+        make(Opcode::Jump, &[4]),
+        // 0021
+        make(Opcode::Jump, &[25]),
+        // 0024
+        make(Opcode::DarkSide, &[]),
+        // 0025
+        make(Opcode::Array, &[2]),
+        // 0028
+        make(Opcode::Pop, &[]),
+        // 0029
+        make(Opcode::Jump, &[4]),
+        // 0032
+        make(Opcode::Pop, &[]),
+        // 0033
+        make(Opcode::Pop, &[]),
+        // 0034
+        make(Opcode::DarkSide, &[]),
+        // 0035
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+  }
+
+  #[test]
+  fn test_assignments() {
+    let bytecode = compile("flux x = 1; x += 2");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        make(Opcode::Constant, &[0]),
+        make(Opcode::SetMutableGlobal, &[0]),
+        make(Opcode::GetGlobal, &[0]),
+        make(Opcode::Constant, &[1]),
+        make(Opcode::Add, &[]),
+        make(Opcode::AssignGlobal, &[0]),
+        make(Opcode::GetGlobal, &[0]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+
+    let bytecode = compile("weave(p) { flux a = p; weave() { a[0][1] = 2 } }");
+    let inner = compiled_function(&bytecode.constants[3]);
+
+    assert_eq!(
+      inner.instructions,
+      concat(vec![
+        make(Opcode::GetFree, &[0]),
+        make(Opcode::Constant, &[0]),
+        make(Opcode::Constant, &[1]),
+        make(Opcode::Constant, &[2]),
+        make(Opcode::SetIndex, &[2]),
+        make(Opcode::SetFree, &[0]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+
+    let bytecode = compile("weave(p) { flux n = p; n = 1 }");
+    let function = compiled_function(&bytecode.constants[1]);
+
+    assert_eq!(
+      function.instructions,
+      concat(vec![
+        make(Opcode::GetLocal, &[0]),
+        make(Opcode::SetMutableLocal, &[1]),
+        make(Opcode::Constant, &[0]),
+        make(Opcode::AssignLocal, &[1]),
+        make(Opcode::GetLocal, &[1]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+
+    let bytecode = compile("flux a = [1]; a[0] *= 3;");
+
+    assert_eq!(
+      bytecode.main.instructions,
+      concat(vec![
+        make(Opcode::Constant, &[0]),
+        make(Opcode::Array, &[1]),
+        make(Opcode::SetMutableGlobal, &[0]),
+        make(Opcode::GetGlobal, &[0]),
+        make(Opcode::Constant, &[1]),
+        make(Opcode::IndexPath, &[1]),
+        make(Opcode::Constant, &[2]),
+        make(Opcode::Mul, &[]),
+        make(Opcode::SetIndex, &[1]),
+        make(Opcode::AssignGlobal, &[0]),
+        make(Opcode::ReturnValue, &[]),
+      ])
+    );
+  }
+
+  #[test]
+  fn test_compile_errors() {
+    let args = vec!["1"; MAX_ARGUMENTS + 1].join(", ");
+    let input = format!("len({})", args);
+    let mut p = Parser::new(Lexer::new(input));
+    let program = p.parse_program().unwrap();
+    let mut compiler = Compiler::new();
+
+    let err = compiler.compile(&program).unwrap_err();
+    assert_eq!(
+      err.message,
+      "too many arguments in call: at most 255 are allowed"
+    );
+    assert!(compiler.symbol_table.outer.is_none());
+  }
+
+  #[test]
+  fn test_assign_to_immutable_binding_compiles() {
+    // Whether a binding is mutable is only known once its declaration has
+    // run, so the virtual machine checks assignments, like the evaluator.
+    for input in [
+      "forge x = 1;\nx = 2",
+      "if (false) { forge y = 1; y = 2 }; 5",
+      "weave() { forge a = 1; a = 2 }; 7",
+      "forge f = weave(p) {\n  p = 1\n};",
+    ] {
+      compile(input);
+    }
+  }
+}
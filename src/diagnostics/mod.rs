@@ -0,0 +1,535 @@
+use crate::{
+  compiler::CompileError,
+  object::{CallSite, RuntimeError},
+  parser::{ParseError, ParseErrorKind},
+  token::{Span, TokenType},
+};
+
+pub const UNEXPECTED_TOKEN: &str = "E0001";
+pub const MISSING_EXPRESSION: &str = "E0002";
+pub const INVALID_INTEGER: &str = "E0003";
+pub const ILLEGAL_TOKEN: &str = "E0004";
+pub const OUTSIDE_LOOP: &str = "E0005";
+pub const INVALID_ASSIGNMENT_TARGET: &str = "E0006";
+pub const RUNTIME_ERROR: &str = "E0100";
+pub const COMPILE_ERROR: &str = "E0200";
+
+/// The `Diagnostic` struct describes a single error in a piece of source code,
+/// in a form that can be rendered for the user.
+///
+/// # Fields
+///
+/// * `code` - A stable identifier for the kind of error, e.g. `E0001`.
+///
+/// * `message` - What went wrong, shown in the header line.
+///
+/// * `span` - The region of source code the error points at.
+///
+/// * `label` - A short note printed next to the caret underline.
+///
+/// * `secondary` - Another region of source code that explains the error, underlined with dashes, and its label.
+///
+/// * `hint` - A suggestion printed below the source snippet.
+///
+/// * `notes` - Extra context printed last, one per line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+  pub code: &'static str,
+  pub message: String,
+  pub span: Option<Span>,
+  pub label: Option<String>,
+  pub secondary: Option<(Span, String)>,
+  pub hint: Option<String>,
+  pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+  pub fn new(code: &'static str, message: String, span: Option<Span>) -> Self {
+    Self {
+      code,
+      message,
+      span,
+      label: None,
+      secondary: None,
+      hint: None,
+      notes: vec![],
+    }
+  }
+
+  pub fn with_label(mut self, label: String) -> Self {
+    self.label = Some(label);
+    self
+  }
+
+  pub fn with_secondary(mut self, span: Span, label: String) -> Self {
+    self.secondary = Some((span, label));
+    self
+  }
+
+  pub fn with_hint(mut self, hint: String) -> Self {
+    self.hint = Some(hint);
+    self
+  }
+
+  pub fn with_note(mut self, note: String) -> Self {
+    self.notes.push(note);
+    self
+  }
+
+  /// Renders the diagnostic against `source`, which `file_name` is used to
+  /// refer to:
+  ///
+  /// ```text
+  /// error[E0001]: expected `)`, found end of input
+  ///  --> script.sbr:1:7
+  ///   |
+  /// 1 | (1 + 2
+  ///   |       ^ expected `)` here
+  /// ```
+  ///
+  /// A secondary span is shown the same way, underlined with `-`, along with
+  /// the line it is on.
+  pub fn render(&self, source: &str, file_name: &str) -> String {
+    let mut out = format!("error[{}]: {}\n", self.code, self.message);
+
+    let span = match self.span {
+      Some(span) => span,
+      None => {
+        out.push_str(&format!(" --> {}\n", file_name));
+        if let Some(hint) = &self.hint {
+          out.push_str(&format!(" = hint: {}\n", hint));
+        }
+        self.render_notes(&mut out, "");
+        return out;
+      }
+    };
+
+    // Without the source, e.g. when running compiled bytecode, the location
+    // is all we can show.
+    if source_line(source, span).is_none() {
+      out.push_str(&format!(" --> {}:{}\n", file_name, span.start));
+      self.render_notes(&mut out, "");
+      return out;
+    }
+
+    let mut annotations = vec![(span, '^', self.label.as_deref())];
+    if let Some((span, label)) = &self.secondary {
+      if source_line(source, *span).is_some() {
+        annotations.push((*span, '-', Some(label.as_str())));
+      }
+    }
+    // Stable, so the primary annotation comes first on a shared line.
+    annotations.sort_by_key(|(span, ..)| span.start.line);
+
+    let width = annotations
+      .iter()
+      .map(|(span, ..)| span.start.line.to_string().len())
+      .max()
+      .unwrap_or(1);
+    let gutter = " ".repeat(width);
+
+    out.push_str(&format!("{}--> {}:{}\n", gutter, file_name, span.start));
+    out.push_str(&format!("{} |\n", gutter));
+
+    let mut previous_line = None;
+    for (span, mark, label) in annotations {
+      let line = source_line(source, span).unwrap_or_default();
+      let line_number = span.start.line;
+      if previous_line != Some(line_number) {
+        let skipped =
+          matches!(previous_line, Some(previous) if line_number > previous + 1);
+        if skipped {
+          out.push_str("...\n");
+        }
+        out.push_str(&format!("{:<width$} | {}\n", line_number, line));
+        previous_line = Some(line_number);
+      }
+
+      let start = span.start.column.max(1);
+      let end = if span.end.line == span.start.line {
+        span.end.column
+      } else {
+        line.chars().count() + 1
+      };
+      let marks = mark.to_string().repeat(end.saturating_sub(start).max(1));
+      let padding = " ".repeat(start - 1);
+      match label {
+        Some(label) => out
+          .push_str(&format!("{} | {}{} {}\n", gutter, padding, marks, label)),
+        None => out.push_str(&format!("{} | {}{}\n", gutter, padding, marks)),
+      }
+    }
+    if let Some(hint) = &self.hint {
+      out.push_str(&format!("{} = hint: {}\n", gutter, hint));
+    }
+    self.render_notes(&mut out, &gutter);
+
+    out
+  }
+
+  fn render_notes(&self, out: &mut String, gutter: &str) {
+    for note in &self.notes {
+      out.push_str(&format!("{} = note: {}\n", gutter, note));
+    }
+  }
+}
+
+/// The line of `source` that `span` starts on.
+fn source_line(source: &str, span: Span) -> Option<&str> {
+  source.lines().nth(span.start.line.saturating_sub(1))
+}
+
+/// Renders every diagnostic for a file, separated by blank lines and followed
+/// by a summary when there is more than one.
+pub fn render_all(
+  diagnostics: &[Diagnostic],
+  source: &str,
+  file_name: &str,
+) -> String {
+  let rendered: Vec<String> = diagnostics
+    .iter()
+    .map(|diagnostic| diagnostic.render(source, file_name))
+    .collect();
+  let mut out = rendered.join("\n");
+
+  if diagnostics.len() > 1 {
+    out.push_str(&format!(
+      "\nerror: aborting due to {} previous errors\n",
+      diagnostics.len()
+    ));
+  }
+
+  out
+}
+
+impl From<&ParseError> for Diagnostic {
+  fn from(err: &ParseError) -> Self {
+    let diagnostic =
+      |code| Diagnostic::new(code, err.message.clone(), Some(err.span));
+
+    match &err.kind {
+      ParseErrorKind::UnexpectedToken { expected, found } => {
+        let diagnostic = diagnostic(UNEXPECTED_TOKEN)
+          .with_label(format!("expected {} here", expected.describe()));
+        match (expected, found) {
+          (TokenType::Assign, _) => diagnostic
+            .with_hint("a binding looks like `forge name = value;`".to_owned()),
+          (
+            TokenType::Rparen | TokenType::Rbrace | TokenType::Rbracket,
+            TokenType::Eof,
+          ) => diagnostic.with_hint(format!(
+            "the input ended before the closing {} was found",
+            expected.describe()
+          )),
+          _ => diagnostic,
+        }
+      }
+      ParseErrorKind::MissingExpression(_) => diagnostic(MISSING_EXPRESSION)
+        .with_label("expected an expression here".to_owned()),
+      ParseErrorKind::InvalidInteger => diagnostic(INVALID_INTEGER)
+        .with_label("integer literal out of range".to_owned())
+        .with_hint(format!(
+          "integers must be between {} and {}",
+          i64::MIN,
+          i64::MAX
+        )),
+      ParseErrorKind::IllegalToken(literal) => {
+        let diagnostic = diagnostic(ILLEGAL_TOKEN)
+          .with_label("not valid Saber syntax".to_owned());
+        if literal.starts_with('"') {
+          diagnostic.with_hint(
+            "strings end with `\"` and only support the escapes \\n, \\t, \\\", \\\\ and \\u{...}"
+              .to_owned(),
+          )
+        } else {
+          diagnostic
+        }
+      }
+      ParseErrorKind::OutsideLoop(_) => diagnostic(OUTSIDE_LOOP)
+        .with_label("not inside a `while` or `for` loop".to_owned())
+        .with_hint(
+          "loops don't reach into the `weave` functions defined in them"
+            .to_owned(),
+        ),
+      ParseErrorKind::InvalidAssignmentTarget => {
+        diagnostic(INVALID_ASSIGNMENT_TARGET)
+          .with_label("cannot be assigned to".to_owned())
+          .with_hint(
+            "only names and their elements, like `x` or `grid[y][x]`, can be assigned to"
+              .to_owned(),
+          )
+      }
+    }
+  }
+}
+
+impl From<&CompileError> for Diagnostic {
+  fn from(err: &CompileError) -> Self {
+    let diagnostic =
+      Diagnostic::new(COMPILE_ERROR, err.message.clone(), Some(err.span));
+    match &err.related {
+      Some((span, label)) => diagnostic.with_secondary(*span, label.clone()),
+      None => diagnostic,
+    }
+  }
+}
+
+/// How many lines of a call trace are shown before the rest is summed up.
+const MAX_TRACE_NOTES: usize = 16;
+
+impl From<&RuntimeError> for Diagnostic {
+  fn from(err: &RuntimeError) -> Self {
+    let mut diagnostic =
+      Diagnostic::new(RUNTIME_ERROR, err.message.clone(), err.span);
+    if let Some((span, label)) = err.related.as_deref() {
+      diagnostic = diagnostic.with_secondary(*span, label.clone());
+    }
+
+    // Recursion repeats the same call over and over, so runs of identical
+    // calls are folded into one line.
+    let mut calls: Vec<(&CallSite, usize)> = vec![];
+    for call in &err.trace {
+      match calls.last_mut() {
+        Some((last, repeats)) if *last == call => *repeats += 1,
+        _ => calls.push((call, 0)),
+      }
+    }
+
+    for (call, repeats) in calls.iter().take(MAX_TRACE_NOTES) {
+      let function = call.function.as_deref().unwrap_or("<anonymous>");
+      let mut note = match call.span {
+        Some(span) => format!("in {}, called at {}", function, span.start),
+        None => format!("in {}", function),
+      };
+      if *repeats > 0 {
+        note.push_str(&format!(" (repeated {} more times)", repeats));
+      }
+      diagnostic = diagnostic.with_note(note);
+    }
+    if calls.len() > MAX_TRACE_NOTES {
+      let hidden: usize = calls[MAX_TRACE_NOTES..]
+        .iter()
+        .map(|(_, repeats)| repeats + 1)
+        .sum();
+      diagnostic =
+        diagnostic.with_note(format!("... and {} more calls", hidden));
+    }
+
+    diagnostic
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    compiler::Compiler, environment::Environment, evaluator::eval_program,
+    interpreter::DEFAULT_RECURSION_LIMIT, lexer::Lexer, object::Object,
+    parser::Parser, token::Position, vm,
+  };
+  use std::{cell::RefCell, rc::Rc};
+
+  fn parse_diagnostics(input: &str) -> Vec<Diagnostic> {
+    let mut p = Parser::new(Lexer::new(input.to_owned()));
+    p.parse_program().unwrap();
+    p.errors.iter().map(Diagnostic::from).collect()
+  }
+
+  #[test]
+  fn test_render_parse_error() {
+    let input = "forge x = (1 + 2;";
+    let diagnostics = parse_diagnostics(input);
+
+    assert_eq!(
+      diagnostics[0].render(input, "script.sbr"),
+      "error[E0001]: expected `)`, found `;`
+ --> script.sbr:1:17
+  |
+1 | forge x = (1 + 2;
+  |                 ^ expected `)` here
+"
+    );
+  }
+
+  #[test]
+  fn test_render_with_hint() {
+    let input = "forge a = 1;\nforge b 2;";
+    let diagnostics = parse_diagnostics(input);
+
+    assert_eq!(
+      diagnostics[0].render(input, "script.sbr"),
+      "error[E0001]: expected `=`, found an integer
+ --> script.sbr:2:9
+  |
+2 | forge b 2;
+  |         ^ expected `=` here
+  = hint: a binding looks like `forge name = value;`
+"
+    );
+  }
+
+  #[test]
+  fn test_render_multiple_errors() {
+    let input = "forge = 1;\n)";
+    let diagnostics = parse_diagnostics(input);
+
+    assert_eq!(diagnostics.len(), 3);
+    let rendered = render_all(&diagnostics, input, "script.sbr");
+
+    assert_eq!(rendered.matches("error[").count(), 3);
+    assert!(rendered.contains(" --> script.sbr:2:1\n"));
+    assert!(rendered.contains(
+      "error[E0002]: expected an expression, found `)`
+ --> script.sbr:2:1
+  |
+2 | )
+  | ^ expected an expression here
+"
+    ));
+    assert!(rendered.ends_with("error: aborting due to 3 previous errors\n"));
+  }
+
+  #[test]
+  fn test_render_runtime_error() {
+    let input = "forge a = 1;\nforge b = a + true;";
+    let mut p = Parser::new(Lexer::new(input.to_owned()));
+    let program = p.parse_program().unwrap();
+    let env = Rc::new(RefCell::new(Environment::new()));
+
+    let err = match eval_program(&program, &env) {
+      Object::Error(err) => *err,
+      obj => panic!("no error object returned. got={:?}", obj),
+    };
+
+    assert_eq!(
+      Diagnostic::from(&err).render(input, "script.sbr"),
+      "error[E0100]: type mismatch: INTEGER + BOOLEAN
+ --> script.sbr:2:11
+  |
+2 | forge b = a + true;
+  |           ^^^^^^^^
+"
+    );
+  }
+
+  #[test]
+  fn test_render_without_source() {
+    let err = RuntimeError {
+      message: "division by zero".to_owned(),
+      span: Some(Span::new(
+        Position { line: 3, column: 5 },
+        Position {
+          line: 3,
+          column: 10,
+        },
+      )),
+      related: None,
+      trace: vec![],
+    };
+
+    assert_eq!(
+      Diagnostic::from(&err).render("", "script.sbc"),
+      "error[E0100]: division by zero\n --> script.sbc:3:5\n"
+    );
+  }
+
+  #[test]
+  fn test_render_call_trace() {
+    let call = |function: &str, line| CallSite {
+      function: Some(function.to_owned()),
+      span: Some(Span::new(Position::new(line, 3), Position::new(line, 7))),
+    };
+    let mut err =
+      RuntimeError::new("maximum recursion depth exceeded".to_owned());
+    err.trace = vec![call("f", 2), call("f", 2), call("f", 2), call("g", 5)];
+    err.trace.push(CallSite {
+      function: None,
+      span: None,
+    });
+
+    assert_eq!(
+      Diagnostic::from(&err).render("", "script.sbr"),
+      "error[E0100]: maximum recursion depth exceeded
+ --> script.sbr
+ = note: in f, called at 2:3 (repeated 2 more times)
+ = note: in g, called at 5:3
+ = note: in <anonymous>
+"
+    );
+
+    err.trace = (0..40).map(|line| call("f", line)).collect();
+    let diagnostic = Diagnostic::from(&err);
+    assert_eq!(diagnostic.notes.len(), MAX_TRACE_NOTES + 1);
+    assert_eq!(diagnostic.notes[MAX_TRACE_NOTES], "... and 24 more calls");
+  }
+
+  #[test]
+  fn test_render_illegal_string() {
+    let input = r#"forge s = "a\qb";"#;
+    let diagnostics = parse_diagnostics(input);
+
+    assert_eq!(
+      diagnostics[0].render(input, "script.sbr"),
+      r#"error[E0004]: illegal token "a\qb"
+ --> script.sbr:1:11
+  |
+1 | forge s = "a\qb";
+  |           ^^^^^^ not valid Saber syntax
+  = hint: strings end with `"` and only support the escapes \n, \t, \", \\ and \u{...}
+"#
+    );
+  }
+
+  #[test]
+  fn test_render_outside_loop() {
+    let input = "forge f = weave() {\n  break;\n};";
+    let diagnostics = parse_diagnostics(input);
+
+    assert_eq!(
+      diagnostics[0].render(input, "script.sbr"),
+      "error[E0005]: `break` outside of a loop
+ --> script.sbr:2:3
+  |
+2 |   break;
+  |   ^^^^^ not inside a `while` or `for` loop
+  = hint: loops don't reach into the `weave` functions defined in them
+"
+    );
+  }
+
+  #[test]
+  fn test_render_immutable_assignment() {
+    let input =
+      "forge total = 0;\nforge add = weave(n) {\n  total += n;\n};\nadd(1);";
+    let mut p = Parser::new(Lexer::new(input.to_owned()));
+    let program = p.parse_program().unwrap();
+    let env = Rc::new(RefCell::new(Environment::new()));
+
+    let err = match eval_program(&program, &env) {
+      Object::Error(err) => *err,
+      obj => panic!("no error object returned. got={:?}", obj),
+    };
+    let expected = "cannot assign to immutable binding: total
+ --> script.sbr:3:3
+  |
+1 | forge total = 0;
+  |       ----- declared immutable here
+...
+3 |   total += n;
+  |   ^^^^^^^^^^
+";
+
+    assert_eq!(
+      Diagnostic::from(&err).render(input, "script.sbr"),
+      format!("error[E0100]: {}", expected)
+    );
+
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let err =
+      vm::run(&bytecode, &mut vec![], DEFAULT_RECURSION_LIMIT).unwrap_err();
+    assert_eq!(
+      Diagnostic::from(&err).render(input, "script.sbr"),
+      format!("error[E0100]: {}", expected)
+    );
+  }
+}
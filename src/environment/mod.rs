@@ -0,0 +1,182 @@
+use crate::{object::Object, token::Span};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// Shared, mutable handle to an `Environment`, so that nested scopes can
+/// keep a reference to the scope they were created in.
+pub type Env = Rc<RefCell<Environment>>;
+
+/// The `Environment` struct stores the bindings created by `forge` and
+/// `flux` statements.
+///
+/// # Fields
+///
+/// * `store` - The bindings declared in this scope.
+///
+/// * `outer` - The enclosing scope, consulted when a name is not found in `store`.
+#[derive(Debug, Default)]
+pub struct Environment {
+  store: HashMap<String, Binding>,
+  outer: Option<Env>,
+}
+
+/// A value bound to a name. Only `mutable` bindings can be assigned to, and
+/// `declared` is where the source declared the binding, if it did.
+#[derive(Debug)]
+struct Binding {
+  value: Object,
+  mutable: bool,
+  declared: Option<Span>,
+}
+
+/// Why `Environment::assign` refused to update a binding.
+#[derive(Debug, PartialEq)]
+pub enum AssignError {
+  /// No scope declares the name.
+  Undeclared,
+  /// The nearest binding of the name can't be assigned to.
+  Immutable { declared: Option<Span> },
+}
+
+impl Environment {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn new_enclosed(outer: Env) -> Self {
+    Self {
+      store: HashMap::new(),
+      outer: Some(outer),
+    }
+  }
+
+  /// Looks `name` up in this scope and then through the chain of enclosing
+  /// scopes.
+  pub fn get(&self, name: &str) -> Option<Object> {
+    match self.store.get(name) {
+      Some(binding) => Some(binding.value.clone()),
+      None => self.outer.as_ref()?.borrow().get(name),
+    }
+  }
+
+  /// Binds `name` to `value` in this scope, immutably.
+  pub fn set(&mut self, name: String, value: Object) {
+    self.declare(name, value, false, None);
+  }
+
+  /// Binds `name` to `value` in this scope, replacing any binding of the
+  /// same name in it, mutable or not.
+  pub fn declare(
+    &mut self,
+    name: String,
+    value: Object,
+    mutable: bool,
+    declared: Option<Span>,
+  ) {
+    let binding = Binding {
+      value,
+      mutable,
+      declared,
+    };
+    self.store.insert(name, binding);
+  }
+
+  /// Updates the binding of `name` in the nearest scope that declares it.
+  /// Changes nothing when no scope does, or when that binding is immutable.
+  pub fn assign(
+    &mut self,
+    name: &str,
+    value: Object,
+  ) -> Result<(), AssignError> {
+    match self.store.get_mut(name) {
+      Some(binding) if binding.mutable => {
+        binding.value = value;
+        Ok(())
+      }
+      Some(binding) => Err(AssignError::Immutable {
+        declared: binding.declared,
+      }),
+      None => match &self.outer {
+        Some(outer) => outer.borrow_mut().assign(name, value),
+        None => Err(AssignError::Undeclared),
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::token::Position;
+
+  #[test]
+  fn test_enclosed_environment_lookup() {
+    let outer = Rc::new(RefCell::new(Environment::new()));
+    outer.borrow_mut().set("a".to_owned(), Object::Integer(1));
+    outer.borrow_mut().set("b".to_owned(), Object::Integer(2));
+
+    let mut inner = Environment::new_enclosed(Rc::clone(&outer));
+    inner.set("b".to_owned(), Object::Integer(3));
+
+    assert_eq!(inner.get("a"), Some(Object::Integer(1)));
+    assert_eq!(inner.get("b"), Some(Object::Integer(3)));
+    assert_eq!(outer.borrow().get("b"), Some(Object::Integer(2)));
+    assert_eq!(inner.get("c"), None);
+  }
+
+  #[test]
+  fn test_assign_updates_nearest_binding() {
+    let outer = Rc::new(RefCell::new(Environment::new()));
+    let declare = |env: &mut Environment, name: &str, value| {
+      env.declare(name.to_owned(), Object::Integer(value), true, None)
+    };
+    declare(&mut outer.borrow_mut(), "a", 1);
+    declare(&mut outer.borrow_mut(), "b", 2);
+
+    let mut inner = Environment::new_enclosed(Rc::clone(&outer));
+    declare(&mut inner, "b", 3);
+
+    assert_eq!(inner.assign("a", Object::Integer(10)), Ok(()));
+    assert_eq!(inner.assign("b", Object::Integer(30)), Ok(()));
+    assert_eq!(
+      inner.assign("c", Object::Integer(0)),
+      Err(AssignError::Undeclared)
+    );
+
+    assert_eq!(outer.borrow().get("a"), Some(Object::Integer(10)));
+    assert_eq!(outer.borrow().get("b"), Some(Object::Integer(2)));
+    assert_eq!(inner.get("b"), Some(Object::Integer(30)));
+    assert_eq!(inner.get("c"), None);
+  }
+
+  #[test]
+  fn test_assign_to_immutable_binding() {
+    let declared = Span::new(Position::new(1, 7), Position::new(1, 8));
+    let outer = Rc::new(RefCell::new(Environment::new()));
+    outer.borrow_mut().declare(
+      "a".to_owned(),
+      Object::Integer(1),
+      false,
+      Some(declared),
+    );
+
+    let mut inner = Environment::new_enclosed(Rc::clone(&outer));
+    inner.declare("a".to_owned(), Object::Integer(2), true, None);
+    inner.set("b".to_owned(), Object::Integer(3));
+
+    assert_eq!(inner.assign("a", Object::Integer(20)), Ok(()));
+    assert_eq!(
+      outer.borrow_mut().assign("a", Object::Integer(10)),
+      Err(AssignError::Immutable {
+        declared: Some(declared)
+      })
+    );
+    assert_eq!(
+      inner.assign("b", Object::Integer(30)),
+      Err(AssignError::Immutable { declared: None })
+    );
+
+    assert_eq!(outer.borrow().get("a"), Some(Object::Integer(1)));
+    assert_eq!(inner.get("a"), Some(Object::Integer(20)));
+    assert_eq!(inner.get("b"), Some(Object::Integer(3)));
+  }
+}
@@ -1,17 +1,1090 @@
+//! The tree-walking evaluator. The `pub(crate)` helpers that work on values
+//! that are already evaluated, such as applying operators, indexing and
+//! setting up `for` loops, are what the virtual machine runs too, so the two
+//! engines can't drift apart.
+use crate::{
+  ast::{
+    ArrayLiteral, AssignExpression, BlockStatement, CallExpression, Expression,
+    ForInExpression, ForgeStatement, FunctionLiteral, HashLiteral, Identifier,
+    IfExpression, IgniteStatement, IndexExpression, InfixExpression, Node,
+    PrefixExpression, Program, Statement, WhileExpression,
+  },
+  builtins,
+  environment::{AssignError, Env, Environment},
+  interpreter::DEFAULT_RECURSION_LIMIT,
+  object::{
+    CallSite, Function, HashPair, Object, RuntimeError, BOOLEAN_OBJ,
+    INTEGER_OBJ, RANGE_OBJ, STRING_OBJ,
+  },
+  token::Span,
+};
+use std::{cell::RefCell, collections::HashMap, ops::ControlFlow, rc::Rc};
+
+const TRUE: Object = Object::Boolean(true);
+const FALSE: Object = Object::Boolean(false);
+const DARK_SIDE: Object = Object::DarkSide;
+
+/// The calls to Saber functions in progress on this thread, outermost first,
+/// and how many may be in progress at once. Every nested call uses native
+/// stack, so the limit is what keeps deep recursion from overflowing it.
+struct CallStack {
+  calls: Vec<CallSite>,
+  limit: usize,
+}
+
+thread_local! {
+  static CALL_STACK: RefCell<CallStack> = const {
+    RefCell::new(CallStack {
+      calls: Vec::new(),
+      limit: DEFAULT_RECURSION_LIMIT,
+    })
+  };
+}
+
+/// Sets how deeply calls may nest on this thread before they fail with
+/// "maximum recursion depth exceeded".
+pub(crate) fn set_recursion_limit(limit: usize) {
+  CALL_STACK.with(|stack| stack.borrow_mut().limit = limit);
+}
+
+/// Evaluates any AST `Node` in the scope `env` and returns the resulting
+/// runtime object.
+pub fn eval(node: &Node, env: &Env) -> Object {
+  match node {
+    Node::Program(program) => eval_program(program, env),
+    Node::Statement(stmt) => eval_statement(stmt, env),
+    Node::Expression(exp) => eval_expression(exp, env),
+  }
+}
+
+/// Evaluates every statement of a program in order, stopping at the first
+/// `ignite` (unwrapping its value) or runtime error.
+pub fn eval_program(program: &Program, env: &Env) -> Object {
+  let mut result = DARK_SIDE;
+
+  for stmt in &program.statements {
+    result = eval_statement(stmt, env);
+
+    match result {
+      Object::ReturnValue(value) => return *value,
+      Object::Error(_) => return result,
+      _ => {}
+    }
+  }
+
+  result
+}
+
+/// Evaluates the statements of a block. Unlike `eval_program`, an `ignite`
+/// result is passed up still wrapped so that enclosing blocks stop too, and
+/// so are `break` and `continue` until they reach their loop.
+fn eval_block_statement(block: &BlockStatement, env: &Env) -> Object {
+  let mut result = DARK_SIDE;
+
+  for stmt in &block.statements {
+    result = eval_statement(stmt, env);
+
+    if let Object::ReturnValue(_)
+    | Object::Error(_)
+    | Object::Break
+    | Object::Continue = result
+    {
+      return result;
+    }
+  }
+
+  result
+}
+
+fn eval_statement(stmt: &Statement, env: &Env) -> Object {
+  match stmt {
+    Statement::ExpressionStatement(stmt) => {
+      eval_optional_expression(stmt.expression.as_deref(), env)
+    }
+    Statement::IgniteStatement(stmt) => eval_ignite_statement(stmt, env),
+    Statement::BlockStatement(block) => eval_block_statement(block, env),
+    Statement::ForgeStatement(stmt) => eval_forge_statement(stmt, env),
+    Statement::BreakStatement(_) => Object::Break,
+    Statement::ContinueStatement(_) => Object::Continue,
+  }
+}
+
+fn eval_ignite_statement(stmt: &IgniteStatement, env: &Env) -> Object {
+  let value = eval_optional_expression(stmt.return_value.as_deref(), env);
+  if is_abrupt(&value) {
+    return value;
+  }
+  Object::ReturnValue(Box::new(value))
+}
+
+fn eval_forge_statement(stmt: &ForgeStatement, env: &Env) -> Object {
+  let value = match stmt.value.as_deref() {
+    Some(Expression::FunctionLiteral(lit)) => {
+      eval_function_literal(lit, env, Some(&stmt.name.value))
+    }
+    exp => eval_optional_expression(exp, env),
+  };
+  if is_abrupt(&value) {
+    return value;
+  }
+  env.borrow_mut().declare(
+    stmt.name.value.clone(),
+    value,
+    stmt.is_mutable(),
+    Some(stmt.name.span),
+  );
+  DARK_SIDE
+}
+
+fn eval_optional_expression(exp: Option<&Expression>, env: &Env) -> Object {
+  match exp {
+    Some(exp) => eval_expression(exp, env),
+    None => DARK_SIDE,
+  }
+}
+
+fn eval_expression(exp: &Expression, env: &Env) -> Object {
+  let mut result = eval_expression_kind(exp, env);
+
+  // Errors point at the innermost expression that raised them, so only fill
+  // in a span that nothing deeper has set yet.
+  if let Object::Error(err) = &mut result {
+    if err.span.is_none() {
+      err.span = Some(exp.span());
+    }
+  }
+
+  result
+}
+
+fn eval_expression_kind(exp: &Expression, env: &Env) -> Object {
+  match exp {
+    Expression::IntegerLiteral(lit) => Object::Integer(lit.value),
+    Expression::StringLiteral(lit) => Object::String(lit.value.clone()),
+    Expression::Boolean(lit) => native_bool_to_boolean_object(lit.value),
+    Expression::PrefixExpression(exp) => eval_prefix_operand(exp, env),
+    Expression::InfixExpression(exp) if exp.is_logical() => {
+      eval_logical_expression(exp, env)
+    }
+    Expression::InfixExpression(exp) => eval_infix_operands(exp, env),
+    Expression::IfExpression(exp) => eval_if_expression(exp, env),
+    Expression::Identifier(ident) => eval_identifier(ident, env),
+    Expression::FunctionLiteral(lit) => eval_function_literal(lit, env, None),
+    Expression::CallExpression(call) => eval_call_expression(call, env),
+    Expression::ArrayLiteral(lit) => eval_array_literal(lit, env),
+    Expression::IndexExpression(exp) => eval_index_expression(exp, env),
+    Expression::HashLiteral(lit) => eval_hash_literal(lit, env),
+    Expression::WhileExpression(exp) => eval_while_expression(exp, env),
+    Expression::ForInExpression(exp) => eval_for_in_expression(exp, env),
+    Expression::AssignExpression(exp) => eval_assign_expression(exp, env),
+  }
+}
+
+fn eval_prefix_operand(exp: &PrefixExpression, env: &Env) -> Object {
+  let right = eval_optional_expression(exp.right.as_deref(), env);
+  if is_abrupt(&right) {
+    return right;
+  }
+  eval_prefix_expression(&exp.operator, right)
+}
+
+fn eval_infix_operands(exp: &InfixExpression, env: &Env) -> Object {
+  let left = eval_optional_expression(exp.left.as_deref(), env);
+  if is_abrupt(&left) {
+    return left;
+  }
+  let right = eval_optional_expression(exp.right.as_deref(), env);
+  if is_abrupt(&right) {
+    return right;
+  }
+  eval_infix_expression(&exp.operator, left, right)
+}
+
+/// Evaluates `&&` or `||`. The right side only runs when the left one
+/// doesn't decide the result already, and either way the result is a
+/// boolean, like the one `!` gives.
+fn eval_logical_expression(exp: &InfixExpression, env: &Env) -> Object {
+  let left = eval_optional_expression(exp.left.as_deref(), env);
+  if is_abrupt(&left) {
+    return left;
+  }
+  if is_truthy(&left) == (exp.operator == "||") {
+    return native_bool_to_boolean_object(is_truthy(&left));
+  }
+
+  let right = eval_optional_expression(exp.right.as_deref(), env);
+  if is_abrupt(&right) {
+    return right;
+  }
+  native_bool_to_boolean_object(is_truthy(&right))
+}
+
+/// Applies a prefix operator to an evaluated operand.
+pub(crate) fn eval_prefix_expression(operator: &str, right: Object) -> Object {
+  match operator {
+    "!" => eval_bang_operator_expression(right),
+    "-" => eval_minus_prefix_operator_expression(right),
+    _ => new_error(format!(
+      "unknown operator: {}{}",
+      operator,
+      right.type_name()
+    )),
+  }
+}
+
+fn eval_bang_operator_expression(right: Object) -> Object {
+  native_bool_to_boolean_object(!is_truthy(&right))
+}
+
+fn eval_minus_prefix_operator_expression(right: Object) -> Object {
+  match right {
+    Object::Integer(value) => match value.checked_neg() {
+      Some(value) => Object::Integer(value),
+      None => new_error(format!("integer overflow: -({})", value)),
+    },
+    _ => new_error(format!("unknown operator: -{}", right.type_name())),
+  }
+}
+
+/// Applies an infix operator to evaluated operands.
+pub(crate) fn eval_infix_expression(
+  operator: &str,
+  left: Object,
+  right: Object,
+) -> Object {
+  match (&left, &right) {
+    (Object::Integer(left), Object::Integer(right)) => {
+      eval_integer_infix_expression(operator, *left, *right)
+    }
+    (Object::Boolean(left), Object::Boolean(right)) => {
+      eval_boolean_infix_expression(operator, *left, *right)
+    }
+    (Object::String(left), Object::String(right)) => {
+      eval_string_infix_expression(operator, left, right)
+    }
+    (Object::Range(..), Object::Range(..)) => {
+      eval_range_infix_expression(operator, &left, &right)
+    }
+    _ if left.type_name() != right.type_name() => new_error(format!(
+      "type mismatch: {} {} {}",
+      left.type_name(),
+      operator,
+      right.type_name()
+    )),
+    _ => new_error(format!(
+      "unknown operator: {} {} {}",
+      left.type_name(),
+      operator,
+      right.type_name()
+    )),
+  }
+}
+
+fn eval_integer_infix_expression(
+  operator: &str,
+  left: i64,
+  right: i64,
+) -> Object {
+  let result = match operator {
+    "+" => left.checked_add(right),
+    "-" => left.checked_sub(right),
+    "*" => left.checked_mul(right),
+    "/" | "%" if right == 0 => return new_error("division by zero".to_owned()),
+    "/" => left.checked_div(right),
+    // Like `/`, `%` truncates toward zero, so the remainder has the sign of
+    // the left side: `-7 % 3` is -1 and `7 % -3` is 1. `i64::MIN % -1` is 0,
+    // even though the division behind it overflows.
+    "%" => Some(left.wrapping_rem(right)),
+    "**" if right < 0 => {
+      return new_error(format!("negative exponent: {} ** {}", left, right))
+    }
+    "**" => checked_pow(left, right),
+    "<" => return native_bool_to_boolean_object(left < right),
+    ">" => return native_bool_to_boolean_object(left > right),
+    "<=" => return native_bool_to_boolean_object(left <= right),
+    ">=" => return native_bool_to_boolean_object(left >= right),
+    "==" => return native_bool_to_boolean_object(left == right),
+    "!=" => return native_bool_to_boolean_object(left != right),
+    ".." => return Object::Range(left, right),
+    _ => {
+      return new_error(format!(
+        "unknown operator: {} {} {}",
+        INTEGER_OBJ, operator, INTEGER_OBJ
+      ))
+    }
+  };
+
+  match result {
+    Some(value) => Object::Integer(value),
+    None => {
+      new_error(format!("integer overflow: {} {} {}", left, operator, right))
+    }
+  }
+}
+
+/// Raises `base` to a non-negative `exponent`, or returns `None` when the
+/// result doesn't fit in an integer.
+fn checked_pow(base: i64, exponent: i64) -> Option<i64> {
+  match u32::try_from(exponent) {
+    Ok(exponent) => base.checked_pow(exponent),
+    // Only -1, 0 and 1 survive exponents this large.
+    Err(_) => match base {
+      0 | 1 => Some(base),
+      -1 if exponent % 2 == 0 => Some(1),
+      -1 => Some(-1),
+      _ => None,
+    },
+  }
+}
+
+fn eval_boolean_infix_expression(
+  operator: &str,
+  left: bool,
+  right: bool,
+) -> Object {
+  match operator {
+    "==" => native_bool_to_boolean_object(left == right),
+    "!=" => native_bool_to_boolean_object(left != right),
+    _ => new_error(format!(
+      "unknown operator: {} {} {}",
+      BOOLEAN_OBJ, operator, BOOLEAN_OBJ
+    )),
+  }
+}
+
+fn eval_string_infix_expression(
+  operator: &str,
+  left: &str,
+  right: &str,
+) -> Object {
+  match operator {
+    "+" => Object::String(format!("{}{}", left, right)),
+    "==" => native_bool_to_boolean_object(left == right),
+    "!=" => native_bool_to_boolean_object(left != right),
+    _ => new_error(format!(
+      "unknown operator: {} {} {}",
+      STRING_OBJ, operator, STRING_OBJ
+    )),
+  }
+}
+
+/// Ranges are equal when their bounds are, so `3..1 != 5..2` even though
+/// neither has any elements.
+fn eval_range_infix_expression(
+  operator: &str,
+  left: &Object,
+  right: &Object,
+) -> Object {
+  match operator {
+    "==" => native_bool_to_boolean_object(left == right),
+    "!=" => native_bool_to_boolean_object(left != right),
+    _ => new_error(format!(
+      "unknown operator: {} {} {}",
+      RANGE_OBJ, operator, RANGE_OBJ
+    )),
+  }
+}
+
+fn eval_if_expression(exp: &IfExpression, env: &Env) -> Object {
+  let condition = eval_optional_expression(exp.condition.as_deref(), env);
+  if is_abrupt(&condition) {
+    return condition;
+  }
+
+  if is_truthy(&condition) {
+    match &exp.consequence {
+      Some(consequence) => eval_block_statement(consequence, env),
+      None => DARK_SIDE,
+    }
+  } else {
+    match &exp.alternative {
+      Some(alternative) => eval_block_statement(alternative, env),
+      None => DARK_SIDE,
+    }
+  }
+}
+
+/// Loops evaluate to `DarkSide`, however they end, unless an `ignite` or an
+/// error leaves them.
+fn eval_while_expression(exp: &WhileExpression, env: &Env) -> Object {
+  loop {
+    let condition = eval_optional_expression(exp.condition.as_deref(), env);
+    if is_abrupt(&condition) {
+      return condition;
+    }
+    if !is_truthy(&condition) {
+      return DARK_SIDE;
+    }
+
+    if let ControlFlow::Break(result) = eval_loop_body(exp.body.as_ref(), env) {
+      return result;
+    }
+  }
+}
+
+/// Binds each element of the iterable to the loop variable in turn. Like
+/// `forge` in a block, the variable stays visible after the loop.
+fn eval_for_in_expression(exp: &ForInExpression, env: &Env) -> Object {
+  let iterable = eval_optional_expression(exp.iterable.as_deref(), env);
+  if is_abrupt(&iterable) {
+    return iterable;
+  }
+
+  let sequence = match iteration_sequence(iterable) {
+    Object::Error(mut err) => {
+      err.span = Some(exp.iterable.as_ref().map_or(exp.span, |e| e.span()));
+      return Object::Error(err);
+    }
+    sequence => sequence,
+  };
+
+  let mut index = 0;
+  while let Some(element) = iteration_element(&sequence, index) {
+    env.borrow_mut().declare(
+      exp.variable.value.clone(),
+      element,
+      false,
+      Some(exp.variable.span),
+    );
+    if let ControlFlow::Break(result) = eval_loop_body(exp.body.as_ref(), env) {
+      return result;
+    }
+    index += 1;
+  }
+
+  DARK_SIDE
+}
+
+/// Runs one pass of a loop body, and breaks with the value of the loop when
+/// the body ends it: `DarkSide` for a `break`, or an `ignite` or error that
+/// keeps travelling up.
+fn eval_loop_body(
+  body: Option<&BlockStatement>,
+  env: &Env,
+) -> ControlFlow<Object> {
+  match body.map(|body| eval_block_statement(body, env)) {
+    Some(Object::Break) => ControlFlow::Break(DARK_SIDE),
+    Some(result @ (Object::ReturnValue(_) | Object::Error(_))) => {
+      ControlFlow::Break(result)
+    }
+    _ => ControlFlow::Continue(()),
+  }
+}
+
+/// Prepares a value to be looped over by `for`: arrays and ranges are used as
+/// they are, strings yield their characters and hashes their keys, in the
+/// order `keys` returns them.
+pub(crate) fn iteration_sequence(iterable: Object) -> Object {
+  match iterable {
+    Object::Array(_) | Object::Range(..) => iterable,
+    Object::String(value) => Object::Array(Rc::new(
+      value
+        .chars()
+        .map(|c| Object::String(c.to_string()))
+        .collect(),
+    )),
+    Object::Hash(pairs) => {
+      let mut pairs: Vec<_> = pairs.iter().collect();
+      pairs.sort_by(|a, b| a.0.cmp(b.0));
+      Object::Array(Rc::new(
+        pairs
+          .into_iter()
+          .map(|(_, pair)| pair.key.clone())
+          .collect(),
+      ))
+    }
+    _ => new_error(format!("cannot iterate over {}", iterable.type_name())),
+  }
+}
+
+/// Returns the element at `index` of a sequence made by
+/// `iteration_sequence`, or `None` once the loop is done.
+pub(crate) fn iteration_element(
+  sequence: &Object,
+  index: usize,
+) -> Option<Object> {
+  match sequence {
+    Object::Array(elements) => elements.get(index).cloned(),
+    Object::Range(start, end) => {
+      let value = start.checked_add(i64::try_from(index).ok()?)?;
+      (value < *end).then_some(Object::Integer(value))
+    }
+    _ => None,
+  }
+}
+
+/// Evaluates the indices of the target left to right, then reads the element
+/// they reach for a compound assignment, then evaluates the value and stores
+/// the result. The assignment evaluates to the value stored.
+fn eval_assign_expression(exp: &AssignExpression, env: &Env) -> Object {
+  let place = match exp.target.as_place() {
+    Some(place) => place,
+    None => {
+      return new_error(format!("invalid assignment target: {}", exp.target))
+    }
+  };
+
+  let root = if place.indices.is_empty() && exp.compound_operator().is_none() {
+    DARK_SIDE
+  } else {
+    eval_identifier(place.name, env)
+  };
+  if is_abrupt(&root) {
+    return root;
+  }
+
+  let mut indices = Vec::with_capacity(place.indices.len());
+  for index in place.indices {
+    let index = eval_expression(index, env);
+    if is_abrupt(&index) {
+      return index;
+    }
+    indices.push(index);
+  }
+
+  let current = match exp.compound_operator() {
+    Some(_) => eval_index_path(root.clone(), &indices),
+    None => DARK_SIDE,
+  };
+  if is_abrupt(&current) {
+    return current;
+  }
+
+  let mut value = eval_optional_expression(exp.value.as_deref(), env);
+  if is_abrupt(&value) {
+    return value;
+  }
+  if let Some(operator) = exp.compound_operator() {
+    value = eval_infix_expression(operator, current, value);
+    if is_abrupt(&value) {
+      return value;
+    }
+  }
+
+  let updated = if indices.is_empty() {
+    value.clone()
+  } else {
+    eval_set_index(root, &indices, value.clone())
+  };
+  if is_abrupt(&updated) {
+    return updated;
+  }
+
+  let name = &place.name.value;
+  match env.borrow_mut().assign(name, updated) {
+    Ok(()) => value,
+    Err(AssignError::Undeclared) => undeclared_assignment_error(name),
+    Err(AssignError::Immutable { declared }) => {
+      immutable_assignment_error(name, declared)
+    }
+  }
+}
+
+/// Reads the element reached by indexing `container` with each of `indices`
+/// in turn.
+pub(crate) fn eval_index_path(container: Object, indices: &[Object]) -> Object {
+  let mut result = container;
+
+  for index in indices {
+    result = eval_index(result, index.clone());
+    if is_abrupt(&result) {
+      break;
+    }
+  }
+
+  result
+}
+
+/// Returns a copy of `container` with the element reached through `indices`
+/// replaced by `value`.
+pub(crate) fn eval_set_index(
+  container: Object,
+  indices: &[Object],
+  value: Object,
+) -> Object {
+  let (index, rest) = match indices.split_first() {
+    Some(split) => split,
+    None => return value,
+  };
+
+  let value = if rest.is_empty() {
+    value
+  } else {
+    let element = eval_index(container.clone(), index.clone());
+    if is_abrupt(&element) {
+      return element;
+    }
+    let element = eval_set_index(element, rest, value);
+    if is_abrupt(&element) {
+      return element;
+    }
+    element
+  };
+
+  match (container, index) {
+    (Object::Array(mut elements), Object::Integer(i)) => {
+      let len = elements.len();
+      match usize::try_from(*i).ok().filter(|i| *i < len) {
+        Some(i) => {
+          Rc::make_mut(&mut elements)[i] = value;
+          Object::Array(elements)
+        }
+        None => {
+          new_error(format!("index out of bounds: index {}, length {}", i, len))
+        }
+      }
+    }
+    (Object::Hash(mut pairs), _) => match index.hash_key() {
+      Some(key) => {
+        let pair = HashPair {
+          key: index.clone(),
+          value,
+        };
+        Rc::make_mut(&mut pairs).insert(key, pair);
+        Object::Hash(pairs)
+      }
+      None => unusable_hash_key_error(index),
+    },
+    (container, _) => new_error(format!(
+      "index assignment not supported: {}[{}]",
+      container.type_name(),
+      index.type_name()
+    )),
+  }
+}
+
+pub(crate) fn undeclared_assignment_error(name: &str) -> Object {
+  match builtins::lookup(name) {
+    Some(_) => new_error(format!("cannot assign to builtin: {}", name)),
+    None => new_error(format!("cannot assign to undeclared name: {}", name)),
+  }
+}
+
+pub(crate) fn immutable_assignment_error(
+  name: &str,
+  declared: Option<Span>,
+) -> Object {
+  let mut err =
+    RuntimeError::new(format!("cannot assign to immutable binding: {}", name));
+  err.related =
+    declared.map(|span| Box::new((span, "declared immutable here".to_owned())));
+  Object::Error(Box::new(err))
+}
+
+fn eval_identifier(ident: &Identifier, env: &Env) -> Object {
+  if let Some(value) = env.borrow().get(&ident.value) {
+    return value;
+  }
+
+  match builtins::lookup(&ident.value) {
+    Some(builtin) => Object::Builtin(builtin),
+    None => new_error(format!("identifier not found: {}", ident.value)),
+  }
+}
+
+fn eval_function_literal(
+  lit: &FunctionLiteral,
+  env: &Env,
+  name: Option<&str>,
+) -> Object {
+  let body = lit.body.clone().unwrap_or_else(|| BlockStatement {
+    token: lit.token.clone(),
+    span: lit.span,
+    statements: vec![],
+  });
+
+  Object::Function(Rc::new(Function {
+    parameters: lit.parameters.clone(),
+    body,
+    env: Rc::clone(env),
+    name: name.map(str::to_owned),
+  }))
+}
+
+fn eval_call_expression(call: &CallExpression, env: &Env) -> Object {
+  match eval_tail_call(call, env) {
+    Tail::Call(call) => apply_function(call),
+    Tail::Value(value) => value,
+  }
+}
+
+fn eval_array_literal(lit: &ArrayLiteral, env: &Env) -> Object {
+  match eval_expressions(&lit.elements, env) {
+    Ok(elements) => Object::Array(Rc::new(elements)),
+    Err(err) => err,
+  }
+}
+
+fn eval_index_expression(exp: &IndexExpression, env: &Env) -> Object {
+  let left = eval_optional_expression(exp.left.as_deref(), env);
+  if is_abrupt(&left) {
+    return left;
+  }
+  let index = eval_optional_expression(exp.index.as_deref(), env);
+  if is_abrupt(&index) {
+    return index;
+  }
+
+  eval_index(left, index)
+}
+
+/// Reads `left[index]` from an array or a hash.
+pub(crate) fn eval_index(left: Object, index: Object) -> Object {
+  match (&left, &index) {
+    (Object::Array(elements), Object::Integer(index)) => {
+      eval_array_index_expression(elements, *index)
+    }
+    (Object::Hash(pairs), _) => match index.hash_key() {
+      Some(key) => pairs.get(&key).map_or(DARK_SIDE, |pair| pair.value.clone()),
+      None => unusable_hash_key_error(&index),
+    },
+    _ => new_error(format!(
+      "index operator not supported: {}[{}]",
+      left.type_name(),
+      index.type_name()
+    )),
+  }
+}
+
+/// Reading past either end of an array is an error rather than `DarkSide`, so
+/// off-by-one mistakes surface where they happen.
+fn eval_array_index_expression(elements: &[Object], index: i64) -> Object {
+  match usize::try_from(index).ok().and_then(|i| elements.get(i)) {
+    Some(element) => element.clone(),
+    None => new_error(format!(
+      "index out of bounds: index {}, length {}",
+      index,
+      elements.len()
+    )),
+  }
+}
+
+fn eval_hash_literal(lit: &HashLiteral, env: &Env) -> Object {
+  let mut pairs = HashMap::new();
+
+  for (key_exp, value_exp) in &lit.pairs {
+    let key = eval_expression(key_exp, env);
+    if is_abrupt(&key) {
+      return key;
+    }
+    let hash_key = match key.hash_key() {
+      Some(hash_key) => hash_key,
+      None => return unusable_hash_key_error(&key),
+    };
+    let value = eval_expression(value_exp, env);
+    if is_abrupt(&value) {
+      return value;
+    }
+    pairs.insert(hash_key, HashPair { key, value });
+  }
+
+  Object::Hash(Rc::new(pairs))
+}
+
+pub(crate) fn unusable_hash_key_error(key: &Object) -> Object {
+  new_error(format!("unusable as hash key: {}", key.type_name()))
+}
+
+/// Evaluates `exps` left to right, stopping at the first error.
+fn eval_expressions(
+  exps: &[Expression],
+  env: &Env,
+) -> Result<Vec<Object>, Object> {
+  let mut result = vec![];
+
+  for exp in exps {
+    let evaluated = eval_expression(exp, env);
+    if is_abrupt(&evaluated) {
+      return Err(evaluated);
+    }
+    result.push(evaluated);
+  }
+
+  Ok(result)
+}
+
+/// What evaluating a function body in tail position produced: its value, or
+/// a call whose result is the function's result.
+enum Tail {
+  Value(Object),
+  Call(PendingCall),
+}
+
+/// A call evaluated up to the point of applying it.
+struct PendingCall {
+  function: Object,
+  args: Vec<Object>,
+  span: Span,
+}
+
+/// Applies `call`. Calls in tail position of the function body are returned
+/// to this loop instead of being applied recursively, so deep tail recursion
+/// runs in constant native stack space and only takes up one entry of the
+/// call stack.
+fn apply_function(call: PendingCall) -> Object {
+  let mut call = call;
+  let mut entered = false;
+
+  let result = loop {
+    let span = call.span;
+    let tail = match call.function {
+      Object::Function(function) => {
+        match enter_function(&function, call.args, span, entered) {
+          Ok(env) => {
+            entered = true;
+            eval_tail_block(&function.body, &env)
+          }
+          Err(err) => Tail::Value(err),
+        }
+      }
+      function => Tail::Value(apply_builtin(function, &call.args)),
+    };
+
+    match tail {
+      Tail::Value(value) => {
+        break point_at_call(unwrap_return_value(value), span)
+      }
+      Tail::Call(next) => call = next,
+    }
+  };
+
+  if entered {
+    CALL_STACK.with(|stack| stack.borrow_mut().calls.pop());
+  }
+  result
+}
+
+/// Checks the arguments of a call to `function` and enters it, returning the
+/// scope its body runs in.
+fn enter_function(
+  function: &Function,
+  args: Vec<Object>,
+  span: Span,
+  tail: bool,
+) -> Result<Env, Object> {
+  if function.parameters.len() != args.len() {
+    return Err(new_error(format!(
+      "wrong number of arguments: want={}, got={}",
+      function.parameters.len(),
+      args.len()
+    )));
+  }
+  enter_call(function, span, tail)?;
+
+  Ok(extend_function_env(function, args))
+}
+
+fn apply_builtin(function: Object, args: &[Object]) -> Object {
+  match function {
+    Object::Builtin(builtin) => (builtin.function)(args),
+    Object::Native(native) => native.call(args),
+    _ => new_error(format!("not a function: {}", function.type_name())),
+  }
+}
+
+/// A tail call is applied after its call expression has finished, so an
+/// error raised by applying it still has to be pointed at that expression.
+fn point_at_call(result: Object, span: Span) -> Object {
+  match result {
+    Object::Error(mut err) if err.span.is_none() => {
+      err.span = Some(span);
+      Object::Error(err)
+    }
+    result => result,
+  }
+}
+
+/// Records a call to `function` on the call stack, failing when that would
+/// nest calls deeper than the limit. A tail call takes over the entry of the
+/// call it ends instead.
+fn enter_call(
+  function: &Function,
+  span: Span,
+  tail: bool,
+) -> Result<(), Object> {
+  CALL_STACK.with(|stack| {
+    let mut stack = stack.borrow_mut();
+    let call = CallSite {
+      function: function.name.clone(),
+      span: Some(span),
+    };
+
+    if tail {
+      *stack.calls.last_mut().expect("no call to take over") = call;
+      return Ok(());
+    }
+    if stack.calls.len() >= stack.limit {
+      let mut err =
+        RuntimeError::new("maximum recursion depth exceeded".to_owned());
+      err.trace = stack.calls.iter().rev().cloned().collect();
+      return Err(Object::Error(Box::new(err)));
+    }
+
+    stack.calls.push(call);
+    Ok(())
+  })
+}
+
+/// Evaluates a block whose value is the value of the enclosing function, as
+/// its body or a branch of an `if` in tail position. `ignite` results are
+/// unwrapped since they leave the function either way.
+fn eval_tail_block(block: &BlockStatement, env: &Env) -> Tail {
+  let (last, init) = match block.statements.split_last() {
+    Some(split) => split,
+    None => return Tail::Value(DARK_SIDE),
+  };
+
+  if let Some(value) = eval_leading_statements(init, env) {
+    return Tail::Value(value);
+  }
+
+  match last {
+    Statement::ExpressionStatement(stmt) => {
+      eval_tail_expression(stmt.expression.as_deref(), env)
+    }
+    Statement::IgniteStatement(stmt) => {
+      eval_tail_expression(stmt.return_value.as_deref(), env)
+    }
+    Statement::BlockStatement(block) => eval_tail_block(block, env),
+    Statement::ForgeStatement(_)
+    | Statement::BreakStatement(_)
+    | Statement::ContinueStatement(_) => {
+      Tail::Value(unwrap_return_value(eval_statement(last, env)))
+    }
+  }
+}
+
+/// Evaluates the statements of a function body before its last one, returning
+/// the function's value if one of them leaves it early.
+fn eval_leading_statements(stmts: &[Statement], env: &Env) -> Option<Object> {
+  for stmt in stmts {
+    match eval_statement(stmt, env) {
+      Object::ReturnValue(value) => return Some(*value),
+      err @ Object::Error(_) => return Some(err),
+      _ => {}
+    }
+  }
+
+  None
+}
+
+/// Evaluates an expression in tail position, handing calls back to
+/// `apply_function` rather than applying them here.
+fn eval_tail_expression(exp: Option<&Expression>, env: &Env) -> Tail {
+  match exp {
+    Some(Expression::CallExpression(call)) => eval_tail_call(call, env),
+    Some(Expression::IfExpression(exp)) => eval_tail_if(exp, env),
+    exp => Tail::Value(eval_optional_expression(exp, env)),
+  }
+}
+
+fn eval_tail_call(call: &CallExpression, env: &Env) -> Tail {
+  let function = eval_optional_expression(call.function.as_deref(), env);
+  if is_abrupt(&function) {
+    return Tail::Value(function);
+  }
+
+  match eval_expressions(&call.arguments, env) {
+    Ok(args) => Tail::Call(PendingCall {
+      function,
+      args,
+      span: call.span,
+    }),
+    Err(err) => Tail::Value(err),
+  }
+}
+
+fn eval_tail_if(exp: &IfExpression, env: &Env) -> Tail {
+  let condition = eval_optional_expression(exp.condition.as_deref(), env);
+  if is_abrupt(&condition) {
+    return Tail::Value(condition);
+  }
+
+  let branch = if is_truthy(&condition) {
+    &exp.consequence
+  } else {
+    &exp.alternative
+  };
+  match branch {
+    Some(block) => eval_tail_block(block, env),
+    None => Tail::Value(DARK_SIDE),
+  }
+}
+
+/// Creates the scope a call runs in: enclosed by the environment the function
+/// was defined in, with each parameter bound to its argument.
+fn extend_function_env(function: &Function, args: Vec<Object>) -> Env {
+  let mut env = Environment::new_enclosed(Rc::clone(&function.env));
+
+  for (param, arg) in function.parameters.iter().zip(args) {
+    env.declare(param.value.clone(), arg, false, Some(param.span));
+  }
+
+  Rc::new(RefCell::new(env))
+}
+
+/// An `ignite` only leaves the function it appears in, so the wrapper must
+/// not escape into the caller's blocks.
+fn unwrap_return_value(obj: Object) -> Object {
+  match obj {
+    Object::ReturnValue(value) => *value,
+    _ => obj,
+  }
+}
+
+fn native_bool_to_boolean_object(value: bool) -> Object {
+  if value {
+    TRUE
+  } else {
+    FALSE
+  }
+}
+
+pub(crate) fn new_error(message: String) -> Object {
+  Object::Error(Box::new(RuntimeError::new(message)))
+}
+
+/// Whether `obj` stops the evaluation of whatever it is part of: an error,
+/// or an `ignite`, `break` or `continue` on its way up.
+fn is_abrupt(obj: &Object) -> bool {
+  matches!(
+    obj,
+    Object::Error(_)
+      | Object::ReturnValue(_)
+      | Object::Break
+      | Object::Continue
+  )
+}
+
+/// Only `false` and `DarkSide` are falsy, every other value is truthy.
+pub(crate) fn is_truthy(obj: &Object) -> bool {
+  !matches!(obj, Object::Boolean(false) | Object::DarkSide)
+}
+
 #[cfg(test)]
 mod tests {
-  use crate::{lexer::Lexer, object::ObjectType, parser::Parser};
+  use super::eval;
+  use crate::{
+    ast::Node,
+    environment::Environment,
+    lexer::Lexer,
+    object::Object,
+    parser::Parser,
+    token::{Position, Span},
+  };
+  use std::{cell::RefCell, rc::Rc};
 
-  fn test_eval(input: &str) -> ObjectType {
+  fn test_eval(input: &str) -> Object {
     let l = Lexer::new(input.to_owned());
     let mut p = Parser::new(l);
     let program = p.parse_program().unwrap();
-    program.eval()
+    let env = Rc::new(RefCell::new(Environment::new()));
+    eval(&Node::Program(program), &env)
   }
 
-  fn test_integer_object(obj: ObjectType, expected: i64) -> bool {
+  fn test_integer_object(obj: Object, expected: i64) -> bool {
     match obj {
-      ObjectType::Integer(value) => {
+      Object::Integer(value) => {
         if value != expected {
           panic!("object has wrong value. got={}, want={}", value, expected);
         }
@@ -23,6 +1096,29 @@ mod tests {
     }
   }
 
+  fn test_boolean_object(obj: Object, expected: bool) -> bool {
+    match obj {
+      Object::Boolean(value) => {
+        if value != expected {
+          panic!("object has wrong value. got={}, want={}", value, expected);
+        }
+        true
+      }
+      _ => {
+        panic!("object is not Boolean. got={:?}", obj);
+      }
+    }
+  }
+
+  fn test_dark_side_object(obj: Object) -> bool {
+    match obj {
+      Object::DarkSide => true,
+      _ => {
+        panic!("object is not DarkSide. got={:?}", obj);
+      }
+    }
+  }
+
   #[test]
   fn test_eval_integer_expression() {
     let tests = vec![
@@ -40,7 +1136,297 @@ mod tests {
       ("2 * (5 + 10)", 30),
       ("3 * 3 * 3 + 10", 37),
       ("3 * (3 * 3) + 10", 37),
-      // ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+      ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+      ("7 % 3", 1),
+      ("-7 % 3", -1),
+      ("7 % -3", 1),
+      ("-7 % -3", -1),
+      ("-7 / 3 * 3 + -7 % 3", -7),
+      ("-9223372036854775807 - 1 % -1", -9223372036854775807),
+      ("(-9223372036854775807 - 1) % -1", 0),
+      ("1 + 10 % 4 * 3", 7),
+      ("2 ** 10", 1024),
+      ("2 ** 3 ** 2", 512),
+      ("(2 ** 3) ** 2", 64),
+      ("-2 ** 2", -4),
+      ("(-2) ** 3", -8),
+      ("0 ** 0", 1),
+      ("2 * 3 ** 2", 18),
+      ("-1 ** 9223372036854775807", -1),
+      ("(-1) ** 9223372036854775806", 1),
+      ("1 ** 9223372036854775807", 1),
+      ("2 ** 62 + (2 ** 62 - 1)", 9223372036854775807),
+    ];
+
+    for (input, expected) in tests {
+      let evaluated = test_eval(input);
+      test_integer_object(evaluated, expected);
+    }
+  }
+
+  #[test]
+  fn test_eval_boolean_expression() {
+    let tests = vec![
+      ("true", true),
+      ("false", false),
+      ("1 < 2", true),
+      ("1 > 2", false),
+      ("1 < 1", false),
+      ("1 > 1", false),
+      ("1 == 1", true),
+      ("1 != 1", false),
+      ("1 == 2", false),
+      ("1 != 2", true),
+      ("true == true", true),
+      ("false == false", true),
+      ("true == false", false),
+      ("true != false", true),
+      ("false != true", true),
+      ("(1 < 2) == true", true),
+      ("(1 < 2) == false", false),
+      ("(1 > 2) == true", false),
+      ("(1 > 2) == false", true),
+      ("1 <= 2", true),
+      ("2 <= 2", true),
+      ("3 <= 2", false),
+      ("1 >= 2", false),
+      ("2 >= 2", true),
+      ("3 >= 2", true),
+      ("1 + 1 <= 2 == 2 >= 1 * 2", true),
+      ("1..3 == 1..3", true),
+      ("1..3 == 0..3", false),
+      ("1..3 != 1..4", true),
+      ("3..1 == 5..2", false),
+    ];
+
+    for (input, expected) in tests {
+      let evaluated = test_eval(input);
+      test_boolean_object(evaluated, expected);
+    }
+  }
+
+  #[test]
+  fn test_bang_operator() {
+    let tests = vec![
+      ("!true", false),
+      ("!false", true),
+      ("!5", false),
+      ("!!true", true),
+      ("!!false", false),
+      ("!!5", true),
+    ];
+
+    for (input, expected) in tests {
+      let evaluated = test_eval(input);
+      test_boolean_object(evaluated, expected);
+    }
+  }
+
+  #[test]
+  fn test_logical_operators() {
+    let tests = vec![
+      ("true && true", true),
+      ("true && false", false),
+      ("false && true", false),
+      ("true || false", true),
+      ("false || true", true),
+      ("false || false", false),
+      (r#"1 && "a""#, true),
+      ("0 || false", true),
+      ("if (false) { 1 } || false", false),
+      ("1 < 2 && 2 < 3 || false", true),
+      ("false && 1 / 0", false),
+      ("true || undefined_name", true),
+      (
+        "flux n = 0; forge f = weave() { n += 1; true }; false && f(); true || f(); n == 0",
+        true,
+      ),
+      (
+        "flux n = 0; forge f = weave() { n += 1; true }; true && f(); false || f(); n == 2",
+        true,
+      ),
+    ];
+
+    for (input, expected) in tests {
+      let evaluated = test_eval(input);
+      test_boolean_object(evaluated, expected);
+    }
+  }
+
+  #[test]
+  fn test_if_else_expressions() {
+    let tests = vec![
+      ("if (true) { 10 }", Some(10)),
+      ("if (false) { 10 }", None),
+      ("if (1) { 10 }", Some(10)),
+      ("if (1 < 2) { 10 }", Some(10)),
+      ("if (1 > 2) { 10 }", None),
+      ("if (1 > 2) { 10 } else { 20 }", Some(20)),
+      ("if (1 < 2) { 10 } else { 20 }", Some(10)),
+    ];
+
+    for (input, expected) in tests {
+      let evaluated = test_eval(input);
+      match expected {
+        Some(expected) => test_integer_object(evaluated, expected),
+        None => test_dark_side_object(evaluated),
+      };
+    }
+  }
+
+  #[test]
+  fn test_ignite_statements() {
+    let tests = vec![
+      ("ignite 10;", 10),
+      ("ignite 10; 9;", 10),
+      ("ignite 2 * 5; 9;", 10),
+      ("9; ignite 2 * 5; 9;", 10),
+      (
+        "if (10 > 1) {
+          if (10 > 1) {
+            ignite 10;
+          }
+
+          ignite 1;
+        }",
+        10,
+      ),
+    ];
+
+    for (input, expected) in tests {
+      let evaluated = test_eval(input);
+      test_integer_object(evaluated, expected);
+    }
+  }
+
+  #[test]
+  fn test_forge_statements() {
+    let tests = vec![
+      ("forge a = 5; a;", 5),
+      ("forge a = 5 * 5; a;", 25),
+      ("forge a = 5; forge b = a; b;", 5),
+      ("forge a = 5; forge b = a; forge c = a + b + 5; c;", 15),
+    ];
+
+    for (input, expected) in tests {
+      let evaluated = test_eval(input);
+      test_integer_object(evaluated, expected);
+    }
+  }
+
+  #[test]
+  fn test_forge_in_nested_block_is_visible_outside() {
+    let evaluated = test_eval("if (true) { forge a = 10; } a;");
+    test_integer_object(evaluated, 10);
+  }
+
+  #[test]
+  fn test_loops() {
+    let tests = vec![
+      ("forge i = 0; while (i < 5) { forge i = i + 1; }; i", 5),
+      ("forge n = 0; for (x in [1, 2, 3]) { forge n = n + x; }; n", 6),
+      ("forge n = 0; for (x in 0..10) { forge n = n + x; }; n", 45),
+      ("forge n = 0; for (x in 5..2) { forge n = n + 1; }; n", 0),
+      (r#"forge n = 0; for (c in "héllo") { forge n = n + 1; }; n"#, 5),
+      (
+        r#"forge n = 0; for (k in {3: 0, 1: 0, 2: 0}) { forge n = n * 10 + k; }; n"#,
+        123,
+      ),
+      (
+        "forge n = 0; for (x in 0..10) { if (x == 3) { break; } forge n = n + x; }; n",
+        3,
+      ),
+      (
+        "forge n = 0; for (x in 0..6) { if (x < 4) { continue; } forge n = n + x; }; n",
+        9,
+      ),
+      (
+        "
+        forge n = 0;
+        for (x in 0..3) {
+          for (y in 0..3) {
+            if (y == 1) { break; }
+            forge n = n + 1;
+          }
+        }
+        n
+        ",
+        3,
+      ),
+      ("for (x in [7, 8]) { }; x", 8),
+      (
+        "forge f = weave() { for (x in 1..100) { if (x * x > 50) { ignite x; } } }; f()",
+        8,
+      ),
+      ("forge i = 0; while (true) { forge i = i + 1; if (i > 9) { break; } }; i", 10),
+    ];
+
+    for (input, expected) in tests {
+      test_integer_object(test_eval(input), expected);
+    }
+
+    test_dark_side_object(test_eval("while (false) { 1 }"));
+    test_dark_side_object(test_eval("for (x in [1]) { break; }"));
+    assert_eq!(test_eval("0..3").inspect(), "0..3");
+  }
+
+  #[test]
+  fn test_assignments() {
+    let tests = vec![
+      ("flux x = 1; x = x + 1; x", 2),
+      ("flux x = 1; x = 5", 5),
+      ("flux x = 1; flux y = 2; x = y = 7; x + y", 14),
+      ("flux x = 10; x += 5; x -= 3; x *= 2; x /= 4; x", 6),
+      ("flux a = [1, 2, 3]; a[1] = 20; a[1] + a[2]", 23),
+      ("flux a = [1, [2, 3]]; a[1][0] += 40; a[1][0]", 42),
+      (r#"flux h = {"k": 1}; h["k"] = 5; h["new"] = 6; h["k"] + h["new"]"#, 11),
+      ("flux a = [1]; forge b = a; a[0] = 9; b[0]", 1),
+      ("flux a = [0, 0]; a[1] = 2", 2),
+      (
+        "flux n = 0; forge add = weave(x) { n += x; }; add(3); add(4); n",
+        7,
+      ),
+      (
+        "forge counter = weave() { flux c = 0; weave() { c += 1 } }; forge next = counter(); next(); next()",
+        2,
+      ),
+      ("forge f = weave(x) { flux x = x; x = x * 2; x }; flux x = 3; f(5) + x", 13),
+      ("flux i = 0; while (i < 10) { i += 1; }; i", 10),
+      ("forge x = 1; flux x = x; x += 1; x", 2),
+    ];
+
+    for (input, expected) in tests {
+      test_integer_object(test_eval(input), expected);
+    }
+  }
+
+  #[test]
+  fn test_function_object() {
+    let evaluated = test_eval("weave(x) { x + 2; };");
+
+    match evaluated {
+      Object::Function(function) => {
+        assert_eq!(function.parameters.len(), 1);
+        assert_eq!(function.parameters[0].to_string(), "x");
+        assert_eq!(function.body.to_string(), "(x + 2)");
+      }
+      _ => panic!("object is not Function. got={:?}", evaluated),
+    }
+  }
+
+  #[test]
+  fn test_function_application() {
+    let tests = vec![
+      ("forge identity = weave(x) { x; }; identity(5);", 5),
+      ("forge identity = weave(x) { ignite x; }; identity(5);", 5),
+      ("forge double = weave(x) { x * 2; }; double(5);", 10),
+      ("forge add = weave(x, y) { x + y; }; add(5, 5);", 10),
+      (
+        "forge add = weave(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+        20,
+      ),
+      ("weave(x) { x; }(5)", 5),
+      ("forge early = weave() { ignite 1; 2; }; early() + 10;", 11),
     ];
 
     for (input, expected) in tests {
@@ -48,4 +1434,430 @@ mod tests {
       test_integer_object(evaluated, expected);
     }
   }
+
+  #[test]
+  fn test_closures() {
+    let input = "
+      forge adder = weave(x) { weave(y) { x + y } };
+      forge add_two = adder(2);
+      add_two(3);
+    ";
+
+    test_integer_object(test_eval(input), 5);
+  }
+
+  #[test]
+  fn test_higher_order_functions() {
+    let input = "
+      forge add = weave(a, b) { a + b };
+      forge apply = weave(f, a, b) { f(a, b) };
+      apply(add, 2, 2);
+    ";
+
+    test_integer_object(test_eval(input), 4);
+  }
+
+  #[test]
+  fn test_recursive_function() {
+    let input = "
+      forge fib = weave(n) {
+        if (n < 2) { ignite n; }
+        fib(n - 1) + fib(n - 2)
+      };
+      fib(10);
+    ";
+
+    test_integer_object(test_eval(input), 55);
+  }
+
+  #[test]
+  fn test_tail_calls_run_in_constant_stack() {
+    let tests = vec![
+      (
+        "
+        forge count = weave(n) {
+          if (n == 0) { 0 } else { count(n - 1) }
+        };
+        count(1000000);
+        ",
+        0,
+      ),
+      (
+        "
+        forge sum = weave(n, acc) {
+          if (n == 0) { ignite acc; }
+          ignite sum(n - 1, acc + n);
+        };
+        sum(10000, 0);
+        ",
+        50005000,
+      ),
+      (
+        "
+        forge is_even = weave(n) { if (n == 0) { true } else { is_odd(n - 1) } };
+        forge is_odd = weave(n) { if (n == 0) { false } else { is_even(n - 1) } };
+        if (is_even(10000)) { 1 } else { 0 };
+        ",
+        1,
+      ),
+    ];
+
+    for (input, expected) in tests {
+      test_integer_object(test_eval(input), expected);
+    }
+  }
+
+  #[test]
+  fn test_error_handling() {
+    let tests = vec![
+      ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
+      ("5 + true; 5;", "type mismatch: INTEGER + BOOLEAN"),
+      ("-true", "unknown operator: -BOOLEAN"),
+      ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
+      ("5; true + false; 5", "unknown operator: BOOLEAN + BOOLEAN"),
+      (
+        "if (10 > 1) { true + false; }",
+        "unknown operator: BOOLEAN + BOOLEAN",
+      ),
+      (
+        "
+        if (10 > 1) {
+          if (10 > 1) {
+            ignite true + false;
+          }
+
+          ignite 1;
+        }
+        ",
+        "unknown operator: BOOLEAN + BOOLEAN",
+      ),
+      ("foobar", "identifier not found: foobar"),
+      ("forge a = foo; 5;", "identifier not found: foo"),
+      ("1 / 0", "division by zero"),
+      (
+        "9223372036854775807 + 1",
+        "integer overflow: 9223372036854775807 + 1",
+      ),
+      ("5(1)", "not a function: INTEGER"),
+      (
+        "forge f = weave(x, y) { x }; f(1);",
+        "wrong number of arguments: want=2, got=1",
+      ),
+      (
+        "forge f = weave(x) { x }; f(1, -true);",
+        "unknown operator: -BOOLEAN",
+      ),
+      (r#""Hello" - "World""#, "unknown operator: STRING - STRING"),
+      (r#""1" + 1"#, "type mismatch: STRING + INTEGER"),
+      ("[1, 2, 3][3]", "index out of bounds: index 3, length 3"),
+      ("[1, 2, 3][-1]", "index out of bounds: index -1, length 3"),
+      ("[][0]", "index out of bounds: index 0, length 0"),
+      ("5[0]", "index operator not supported: INTEGER[INTEGER]"),
+      (r#"[1]["a"]"#, "index operator not supported: ARRAY[STRING]"),
+      (
+        r#"{"name": "saber"}[weave(x) { x }];"#,
+        "unusable as hash key: FUNCTION",
+      ),
+      ("{[1]: 2}", "unusable as hash key: ARRAY"),
+      ("{1: -true}", "unknown operator: -BOOLEAN"),
+      ("for (x in 5) { x }", "cannot iterate over INTEGER"),
+      (r#""a".."b""#, "unknown operator: STRING .. STRING"),
+      ("0..1 < 0..2", "unknown operator: RANGE < RANGE"),
+      ("0..1 == [0]", "type mismatch: RANGE == ARRAY"),
+      ("x = 1", "cannot assign to undeclared name: x"),
+      ("len = 1", "cannot assign to builtin: len"),
+      (
+        "forge f = weave() { y = 1 }; f()",
+        "cannot assign to undeclared name: y",
+      ),
+      ("x[0] = 1", "identifier not found: x"),
+      (
+        "flux a = [1]; a[1] = 2",
+        "index out of bounds: index 1, length 1",
+      ),
+      (
+        "flux a = 5; a[0] = 2",
+        "index assignment not supported: INTEGER[INTEGER]",
+      ),
+      ("flux h = {}; h[[]] = 2", "unusable as hash key: ARRAY"),
+      (
+        r#"flux h = {}; h["a"]["b"] = 2"#,
+        "index assignment not supported: DARK_SIDE[STRING]",
+      ),
+      ("flux a = true; a += 1", "type mismatch: BOOLEAN + INTEGER"),
+      (
+        "forge x = 1; x = 2",
+        "cannot assign to immutable binding: x",
+      ),
+      ("5 % 0", "division by zero"),
+      ("2 ** -1", "negative exponent: 2 ** -1"),
+      ("2 ** 63", "integer overflow: 2 ** 63"),
+      (
+        "3 ** 9223372036854775807",
+        "integer overflow: 3 ** 9223372036854775807",
+      ),
+      (r#""a" <= "b""#, "unknown operator: STRING <= STRING"),
+      ("true >= false", "unknown operator: BOOLEAN >= BOOLEAN"),
+      ("1 % true", "type mismatch: INTEGER % BOOLEAN"),
+      ("true && 1 / 0", "division by zero"),
+      ("-true || true", "unknown operator: -BOOLEAN"),
+      (
+        "forge a = [1]; a[0] = 2",
+        "cannot assign to immutable binding: a",
+      ),
+      (
+        "flux n = 0; forge n = n; n += 1",
+        "cannot assign to immutable binding: n",
+      ),
+      (
+        "forge f = weave(x) { x = 1 }; f(0)",
+        "cannot assign to immutable binding: x",
+      ),
+      (
+        "for (i in 0..3) { i += 1 }",
+        "cannot assign to immutable binding: i",
+      ),
+    ];
+
+    for (input, expected) in tests {
+      let evaluated = test_eval(input);
+      match evaluated {
+        Object::Error(err) => assert_eq!(err.message, expected),
+        _ => panic!("no error object returned. got={:?}", evaluated),
+      }
+    }
+  }
+
+  #[test]
+  fn test_error_spans() {
+    let tests = vec![
+      ("5;\n  true + 1", (2, 3), (2, 11)),
+      ("forge f = weave(x) {\n  x + y\n};\nf(1)", (2, 7), (2, 8)),
+      ("1 + -true", (1, 5), (1, 10)),
+      ("forge f = weave() {\n  len(1, 2)\n};\nf()", (2, 3), (2, 12)),
+      (
+        "forge f = weave() {\n  ignite f(1);\n};\nf()",
+        (2, 10),
+        (2, 14),
+      ),
+    ];
+
+    for (input, start, end) in tests {
+      let evaluated = test_eval(input);
+      match evaluated {
+        Object::Error(err) => assert_eq!(
+          err.span,
+          Some(Span::new(
+            Position::new(start.0, start.1),
+            Position::new(end.0, end.1)
+          )),
+          "wrong span for {:?}",
+          err
+        ),
+        _ => panic!("no error object returned. got={:?}", evaluated),
+      }
+    }
+  }
+
+  #[test]
+  fn test_string_literal() {
+    match test_eval(r#""Hello World!""#) {
+      Object::String(value) => assert_eq!(value, "Hello World!"),
+      obj => panic!("object is not String. got={:?}", obj),
+    }
+  }
+
+  #[test]
+  fn test_string_concatenation() {
+    let input = r#"forge greet = weave(name) { "Hello, " + name + "!" }; greet("sabre\u{2694}")"#;
+
+    match test_eval(input) {
+      Object::String(value) => assert_eq!(value, "Hello, sabre\u{2694}!"),
+      obj => panic!("object is not String. got={:?}", obj),
+    }
+  }
+
+  #[test]
+  fn test_string_comparison() {
+    let tests = vec![
+      (r#""a" == "a""#, true),
+      (r#""a" == "b""#, false),
+      (r#""a" != "b""#, true),
+      (r#""a" + "b" == "ab""#, true),
+      (r#""tab\t" == "tab	""#, true),
+    ];
+
+    for (input, expected) in tests {
+      test_boolean_object(test_eval(input), expected);
+    }
+  }
+
+  #[test]
+  fn test_array_literals() {
+    let evaluated = test_eval("[1, 2 * 2, 3 + 3]");
+
+    match evaluated {
+      Object::Array(elements) => {
+        assert_eq!(elements.len(), 3);
+        test_integer_object(elements[0].clone(), 1);
+        test_integer_object(elements[1].clone(), 4);
+        test_integer_object(elements[2].clone(), 6);
+      }
+      _ => panic!("object is not Array. got={:?}", evaluated),
+    }
+  }
+
+  #[test]
+  fn test_array_index_expressions() {
+    let tests = vec![
+      ("[1, 2, 3][0]", 1),
+      ("[1, 2, 3][1]", 2),
+      ("[1, 2, 3][2]", 3),
+      ("forge i = 0; [1][i];", 1),
+      ("[1, 2, 3][1 + 1];", 3),
+      ("forge my_array = [1, 2, 3]; my_array[2];", 3),
+      (
+        "forge my_array = [1, 2, 3]; my_array[0] + my_array[1] + my_array[2];",
+        6,
+      ),
+      ("forge a = [1, 2, 3]; forge i = a[0]; a[i]", 2),
+      ("[[1, 2], [3, 4]][1][0]", 3),
+    ];
+
+    for (input, expected) in tests {
+      test_integer_object(test_eval(input), expected);
+    }
+  }
+
+  #[test]
+  fn test_builtin_functions() {
+    let tests = vec![
+      (r#"len("")"#, Object::Integer(0)),
+      (r#"len("four")"#, Object::Integer(4)),
+      (r#"len("héllo")"#, Object::Integer(5)),
+      ("len([1, 2, 3])", Object::Integer(3)),
+      ("len([])", Object::Integer(0)),
+      ("first([1, 2, 3])", Object::Integer(1)),
+      ("first([])", Object::DarkSide),
+      ("last([1, 2, 3])", Object::Integer(3)),
+      ("last([])", Object::DarkSide),
+      ("rest([1, 2, 3])[0]", Object::Integer(2)),
+      ("len(rest([1, 2, 3]))", Object::Integer(2)),
+      ("rest([])", Object::DarkSide),
+      ("push([], 1)[0]", Object::Integer(1)),
+      ("forge a = [1]; push(a, 2); len(a)", Object::Integer(1)),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(test_eval(input), expected, "input: {}", input);
+    }
+  }
+
+  #[test]
+  fn test_builtin_function_errors() {
+    let tests = vec![
+      ("len(1)", "argument to `len` not supported, got INTEGER"),
+      (
+        r#"len("one", "two")"#,
+        "wrong number of arguments: want=1, got=2",
+      ),
+      ("first(1)", "argument to `first` must be ARRAY, got INTEGER"),
+      ("last()", "wrong number of arguments: want=1, got=0"),
+      (
+        r#"rest("abc")"#,
+        "argument to `rest` must be ARRAY, got STRING",
+      ),
+      (
+        "push(1, 1)",
+        "argument to `push` must be ARRAY, got INTEGER",
+      ),
+    ];
+
+    for (input, expected) in tests {
+      match test_eval(input) {
+        Object::Error(err) => assert_eq!(err.message, expected),
+        obj => panic!("no error object returned. got={:?}", obj),
+      }
+    }
+  }
+
+  #[test]
+  fn test_hash_literals() {
+    let input = r#"
+      forge two = "two";
+      {
+        "one": 10 - 9,
+        two: 1 + 1,
+        "thr" + "ee": 6 / 2,
+        4: 4,
+        true: 5,
+        false: 6
+      }
+    "#;
+
+    let pairs = match test_eval(input) {
+      Object::Hash(pairs) => pairs,
+      obj => panic!("eval didn't return Hash. got={:?}", obj),
+    };
+    let expected = vec![
+      (Object::String("one".to_owned()), 1),
+      (Object::String("two".to_owned()), 2),
+      (Object::String("three".to_owned()), 3),
+      (Object::Integer(4), 4),
+      (Object::Boolean(true), 5),
+      (Object::Boolean(false), 6),
+    ];
+
+    assert_eq!(pairs.len(), expected.len());
+    for (key, value) in expected {
+      let pair = &pairs[&key.hash_key().unwrap()];
+      assert_eq!(pair.key, key);
+      test_integer_object(pair.value.clone(), value);
+    }
+  }
+
+  #[test]
+  fn test_hash_index_expressions() {
+    let tests = vec![
+      (r#"{"foo": 5}["foo"]"#, Object::Integer(5)),
+      (r#"{"foo": 5}["bar"]"#, Object::DarkSide),
+      (r#"forge key = "foo"; {"foo": 5}[key]"#, Object::Integer(5)),
+      (r#"{}["foo"]"#, Object::DarkSide),
+      ("{5: 5}[5]", Object::Integer(5)),
+      ("{true: 5}[true]", Object::Integer(5)),
+      ("{false: 5}[false]", Object::Integer(5)),
+      ("{1: 1, 1: 2}[1]", Object::Integer(2)),
+      (r#"len({"a": 1, "b": 2})"#, Object::Integer(2)),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(test_eval(input), expected, "input: {}", input);
+    }
+  }
+
+  #[test]
+  fn test_builtin_registry() {
+    let tests = vec![
+      (r#"type(len)"#, Object::String("BUILTIN".to_owned())),
+      (r#"int("4" + "2") + 1"#, Object::Integer(43)),
+      (
+        r#"str(1) + str([1, "a"])"#,
+        Object::String(r#"1[1, "a"]"#.to_owned()),
+      ),
+      (
+        r#"keys({"b": 2, "a": 1})[0]"#,
+        Object::String("a".to_owned()),
+      ),
+      (r#"values({"b": 2, "a": 1})[0]"#, Object::Integer(1)),
+      (
+        "forge len = weave(x) { 0 }; len([1, 2])",
+        Object::Integer(0),
+      ),
+      ("forge f = first; f([7])", Object::Integer(7)),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(test_eval(input), expected, "input: {}", input);
+    }
+  }
 }
@@ -0,0 +1,458 @@
+use crate::{
+  ast::Program,
+  compiler::{Bytecode, CompileError, Compiler},
+  diagnostics::{self, Diagnostic},
+  environment::{Env, Environment},
+  evaluator,
+  lexer::Lexer,
+  object::{Native, Object, RuntimeError, Variable},
+  parser::{ParseError, Parser},
+  value::{NativeFunction, Value},
+  vm::{self, Globals},
+};
+use std::{cell::RefCell, error, fmt, rc::Rc};
+
+/// How deeply calls may nest unless `Interpreter::set_recursion_limit` says
+/// otherwise. Each nested call of the tree-walking evaluator takes a few KB of
+/// native stack, more in a debug build or when the call sits deep inside an
+/// expression, so this stays within the 2MB that `std::thread::spawn` gives a
+/// thread. Raise it only when running on a thread with a bigger stack.
+pub const DEFAULT_RECURSION_LIMIT: usize = 300;
+
+/// Why a piece of Saber source could not be evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaberError {
+  Parse(Vec<ParseError>),
+  Compile(CompileError),
+  Runtime(RuntimeError),
+}
+
+impl SaberError {
+  pub fn diagnostics(&self) -> Vec<Diagnostic> {
+    match self {
+      SaberError::Parse(errors) => {
+        errors.iter().map(Diagnostic::from).collect()
+      }
+      SaberError::Compile(err) => vec![Diagnostic::from(err)],
+      SaberError::Runtime(err) => vec![Diagnostic::from(err)],
+    }
+  }
+
+  /// Renders every diagnostic against `source`, as `saber run` prints them.
+  pub fn render(&self, source: &str, file_name: &str) -> String {
+    diagnostics::render_all(&self.diagnostics(), source, file_name)
+  }
+}
+
+impl fmt::Display for SaberError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SaberError::Parse(errors) => {
+        let errors: Vec<String> =
+          errors.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", errors.join("\n"))
+      }
+      SaberError::Compile(err) => write!(f, "{}", err),
+      SaberError::Runtime(err) => write!(f, "{}", err),
+    }
+  }
+}
+
+impl error::Error for SaberError {}
+
+/// Which backend runs the code. Both give the same results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+  /// Walks the syntax tree directly.
+  #[default]
+  Evaluator,
+  /// Compiles to bytecode and runs it on the virtual machine.
+  Vm,
+}
+
+/// The `Interpreter` struct is the entry point for embedding Saber. It keeps
+/// one global scope alive, so bindings made by one call to `eval_str` are
+/// visible to the next.
+///
+/// Calls may nest `DEFAULT_RECURSION_LIMIT` deep, which fits on the stack of
+/// any thread, including a spawned one; `set_recursion_limit` changes that.
+///
+/// ```
+/// use saber::{Interpreter, Value};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_global("answer", Value::Integer(41));
+/// interpreter.eval_str("forge next = answer + 1;").unwrap();
+///
+/// assert_eq!(interpreter.eval_str("next").unwrap(), Value::Integer(42));
+/// ```
+pub struct Interpreter {
+  engine: Engine,
+  env: Env,
+  compiler: Compiler,
+  globals: Globals,
+  recursion_limit: usize,
+}
+
+impl Default for Interpreter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Interpreter {
+  pub fn new() -> Self {
+    Self::with_engine(Engine::default())
+  }
+
+  pub fn with_engine(engine: Engine) -> Self {
+    Self {
+      engine,
+      env: Rc::new(RefCell::new(Environment::new())),
+      compiler: Compiler::new(),
+      globals: vec![],
+      recursion_limit: DEFAULT_RECURSION_LIMIT,
+    }
+  }
+
+  pub fn engine(&self) -> Engine {
+    self.engine
+  }
+
+  pub fn recursion_limit(&self) -> usize {
+    self.recursion_limit
+  }
+
+  /// Sets how deeply calls may nest before they fail with "maximum recursion
+  /// depth exceeded". Tail calls don't count. Lower it when running on a
+  /// thread with a small stack.
+  pub fn set_recursion_limit(&mut self, limit: usize) {
+    self.recursion_limit = limit;
+  }
+
+  /// Lexes, parses and evaluates `source` in the global scope, returning the
+  /// value of its last statement.
+  pub fn eval_str(&mut self, source: &str) -> Result<Value, SaberError> {
+    let program = parse(source)?;
+
+    match self.engine {
+      Engine::Evaluator => {
+        evaluator::set_recursion_limit(self.recursion_limit);
+        match evaluator::eval_program(&program, &self.env) {
+          Object::Error(err) => Err(SaberError::Runtime(*err)),
+          evaluated => Ok(Value::from_object(evaluated)),
+        }
+      }
+      Engine::Vm => {
+        let bytecode = self
+          .compiler
+          .compile(&program)
+          .map_err(SaberError::Compile)?;
+        vm::run(&bytecode, &mut self.globals, self.recursion_limit)
+          .map(Value::from_object)
+          .map_err(SaberError::Runtime)
+      }
+    }
+  }
+
+  /// Binds `name` in the global scope, replacing any previous binding.
+  pub fn set_global(&mut self, name: &str, value: Value) {
+    self.set_global_object(name, value.into_object());
+  }
+
+  fn set_global_object(&mut self, name: &str, value: Object) {
+    match self.engine {
+      Engine::Evaluator => self.env.borrow_mut().set(name.to_owned(), value),
+      Engine::Vm => {
+        let index = self.compiler.define_global(name);
+        if index >= self.globals.len() {
+          self.globals.resize(index + 1, None);
+        }
+        self.globals[index] = Some(Variable {
+          value,
+          mutable: false,
+          declared: None,
+        });
+      }
+    }
+  }
+
+  /// Makes a Rust function callable from Saber as `name`. A native fails
+  /// the call by returning `Err` with the message to report.
+  ///
+  /// ```
+  /// use saber::{Interpreter, Value};
+  ///
+  /// fn double(args: &[Value]) -> Result<Value, String> {
+  ///   match args {
+  ///     [Value::Integer(value)] => Ok(Value::Integer(value * 2)),
+  ///     _ => Err("double expects one integer".to_owned()),
+  ///   }
+  /// }
+  ///
+  /// let mut interpreter = Interpreter::new();
+  /// interpreter.register_native("double", double);
+  ///
+  /// assert_eq!(interpreter.eval_str("double(21)").unwrap(), Value::Integer(42));
+  /// ```
+  pub fn register_native(
+    &mut self,
+    name: &'static str,
+    function: NativeFunction,
+  ) {
+    self.set_global_object(name, Object::Native(Native { name, function }));
+  }
+}
+
+/// Compiles `source` into a standalone program for the virtual machine, e.g.
+/// to save it as a `.sbc` file.
+pub fn compile_str(source: &str) -> Result<Bytecode, SaberError> {
+  let program = parse(source)?;
+  Compiler::new()
+    .compile(&program)
+    .map_err(SaberError::Compile)
+}
+
+fn parse(source: &str) -> Result<Program, SaberError> {
+  let lexer = Lexer::new(source.to_owned());
+  let mut parser = Parser::new(lexer);
+
+  let program = parser.parse_program().unwrap();
+  if !parser.errors.is_empty() {
+    return Err(SaberError::Parse(parser.errors));
+  }
+  Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::object::HashKey;
+  use std::collections::BTreeMap;
+
+  const ENGINES: [Engine; 2] = [Engine::Evaluator, Engine::Vm];
+
+  #[test]
+  fn test_eval_str() {
+    for engine in ENGINES {
+      let mut interpreter = Interpreter::with_engine(engine);
+      let source = "
+        forge double = weave(x) { x * 2 };
+        double(21);
+      ";
+
+      assert_eq!(interpreter.eval_str(source), Ok(Value::Integer(42)));
+      assert_eq!(interpreter.eval_str("double(1)"), Ok(Value::Integer(2)));
+    }
+  }
+
+  #[test]
+  fn test_eval_str_errors() {
+    for engine in ENGINES {
+      let mut interpreter = Interpreter::with_engine(engine);
+
+      match interpreter.eval_str("forge x 5;") {
+        Err(SaberError::Parse(errors)) => assert_eq!(errors.len(), 1),
+        result => panic!("expected a parse error. got={:?}", result),
+      }
+
+      match interpreter.eval_str("forge x = 5;\nx + true;\n10;") {
+        Err(SaberError::Runtime(err)) => {
+          assert_eq!(err.message, "type mismatch: INTEGER + BOOLEAN")
+        }
+        result => panic!("expected a runtime error. got={:?}", result),
+      }
+    }
+  }
+
+  #[test]
+  fn test_closure_reads_later_local() {
+    let tests = vec![
+      (
+        "forge outer = weave() { forge h = weave() { y }; forge y = 2; h() }; outer();",
+        Value::Integer(2),
+      ),
+      (
+        "forge f = weave() { forge h = weave() { z }; if (true) { flux z = 3; }; h() }; f();",
+        Value::Integer(3),
+      ),
+      (
+        "forge f = weave() { forge h = weave() { i }; for (i in 0..2) { }; h() }; f();",
+        Value::Integer(1),
+      ),
+    ];
+
+    for (source, expected) in tests {
+      for engine in ENGINES {
+        let mut interpreter = Interpreter::with_engine(engine);
+        assert_eq!(
+          interpreter.eval_str(source),
+          Ok(expected.clone()),
+          "engine: {:?}, source: {}",
+          engine,
+          source
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_recursion_limit() {
+    let source = "
+      forge down = weave(n) { if (n == 0) { 0 } else { 1 + down(n - 1) } };
+      forge loop = weave(n) { if (n == 0) { 0 } else { loop(n - 1) } };
+    ";
+    let mut errors = vec![];
+
+    for engine in ENGINES {
+      let mut interpreter = Interpreter::with_engine(engine);
+      assert_eq!(interpreter.recursion_limit(), DEFAULT_RECURSION_LIMIT);
+      interpreter.set_recursion_limit(50);
+      interpreter.eval_str(source).unwrap();
+
+      assert_eq!(interpreter.eval_str("down(49)"), Ok(Value::Integer(49)));
+      // Tail calls reuse their caller's slot, so they never hit the limit.
+      assert_eq!(interpreter.eval_str("loop(1000)"), Ok(Value::Integer(0)));
+
+      let err = match interpreter.eval_str("\ndown(50)") {
+        Err(SaberError::Runtime(err)) => err,
+        result => panic!("expected a runtime error. got={:?}", result),
+      };
+      assert_eq!(err.message, "maximum recursion depth exceeded");
+      assert_eq!(err.trace.len(), 50);
+      assert_eq!(err.trace[0].function.as_deref(), Some("down"));
+      assert_eq!(err.trace[49].span.map(|span| span.start.line), Some(2));
+
+      // A failed call must not leave anything behind on the call stack.
+      assert_eq!(interpreter.eval_str("down(49)"), Ok(Value::Integer(49)));
+      errors.push(err);
+    }
+
+    assert_eq!(errors[0], errors[1], "the engines disagree");
+  }
+
+  #[test]
+  fn test_default_recursion_limit_fits_the_stack() {
+    // The stack `std::thread::spawn` gives a thread, a quarter of what the
+    // main thread usually gets.
+    const STACK_SIZE: usize = 2 * 1024 * 1024;
+    let source = "
+      forge down = weave(n) { if (n == 0) { 0 } else { 1 + down(n - 1) } };
+    ";
+
+    for engine in ENGINES {
+      std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+          let mut interpreter = Interpreter::with_engine(engine);
+          interpreter.eval_str(source).unwrap();
+
+          let deepest = format!("down({})", DEFAULT_RECURSION_LIMIT - 1);
+          assert_eq!(
+            interpreter.eval_str(&deepest),
+            Ok(Value::Integer(DEFAULT_RECURSION_LIMIT as i64 - 1))
+          );
+          let too_deep = format!("down({})", DEFAULT_RECURSION_LIMIT);
+          match interpreter.eval_str(&too_deep) {
+            Err(SaberError::Runtime(err)) => {
+              assert_eq!(err.message, "maximum recursion depth exceeded")
+            }
+            result => panic!("expected a runtime error. got={:?}", result),
+          }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    }
+  }
+
+  #[test]
+  fn test_set_global() {
+    for engine in ENGINES {
+      let mut interpreter = Interpreter::with_engine(engine);
+      interpreter.set_global("name", Value::String("saber".to_owned()));
+      interpreter.set_global("len", Value::Integer(3));
+
+      assert_eq!(
+        interpreter.eval_str(r#""hello " + name"#),
+        Ok(Value::String("hello saber".to_owned()))
+      );
+      assert_eq!(interpreter.eval_str("len"), Ok(Value::Integer(3)));
+
+      // Globals handed in by the embedder are immutable, and bindings keep
+      // their mutability from one call to the next.
+      match interpreter.eval_str("name = 1") {
+        Err(SaberError::Runtime(err)) => {
+          assert_eq!(err.message, "cannot assign to immutable binding: name")
+        }
+        result => panic!("expected a runtime error. got={:?}", result),
+      }
+      interpreter
+        .eval_str("flux count = 1; forge total = 0;")
+        .unwrap();
+      assert_eq!(interpreter.eval_str("count += 1"), Ok(Value::Integer(2)));
+      assert!(interpreter.eval_str("total += 1").is_err());
+    }
+  }
+
+  #[test]
+  fn test_values_cross_the_api() {
+    for engine in ENGINES {
+      let mut interpreter = Interpreter::with_engine(engine);
+      interpreter.set_global(
+        "pair",
+        Value::Array(vec![Value::Integer(1), Value::DarkSide]),
+      );
+
+      assert_eq!(
+        interpreter.eval_str(r#"{"first": pair[0], "rest": 1..3}"#),
+        Ok(Value::Hash(BTreeMap::from([
+          (HashKey::String("first".to_owned()), Value::Integer(1)),
+          (HashKey::String("rest".to_owned()), Value::Range(1, 3)),
+        ])))
+      );
+      // A top-level `ignite` yields the plain value.
+      assert_eq!(interpreter.eval_str("ignite 5;"), Ok(Value::Integer(5)));
+
+      // Functions can't be called from Rust, but they can be handed back.
+      let add = interpreter.eval_str("weave(a, b) { a + b }").unwrap();
+      assert!(matches!(add, Value::Function(_)));
+      interpreter.set_global("add", add);
+      assert_eq!(interpreter.eval_str("add(2, 3)"), Ok(Value::Integer(5)));
+    }
+  }
+
+  #[test]
+  fn test_register_native() {
+    fn sum(args: &[Value]) -> Result<Value, String> {
+      let mut total = 0;
+      for arg in args {
+        match arg {
+          Value::Integer(value) => total += value,
+          _ => {
+            return Err(format!("argument to `sum` not supported, got {}", arg))
+          }
+        }
+      }
+      Ok(Value::Integer(total))
+    }
+
+    for engine in ENGINES {
+      let mut interpreter = Interpreter::with_engine(engine);
+      interpreter.register_native("sum", sum);
+
+      assert_eq!(interpreter.eval_str("sum(1, 2, 3)"), Ok(Value::Integer(6)));
+      assert_eq!(
+        interpreter.eval_str("sum").unwrap().to_string(),
+        "builtin function sum"
+      );
+      match interpreter.eval_str("sum(1, true)") {
+        Err(err) => assert_eq!(
+          err.to_string(),
+          "1:1: argument to `sum` not supported, got true"
+        ),
+        result => panic!("expected a runtime error. got={:?}", result),
+      }
+    }
+  }
+}
@@ -1,40 +1,59 @@
 use crate::token::{
-  Token, TokenType, ASSIGN, ASTERISK, BANG, COMMA, EOF, EQ, GT, LBRACE, LPAREN,
-  LT, MINUS, NOT_EQ, PLUS, RBRACE, RPAREN, SEMICOLON, SLASH,
+  Position, Span, Token, TokenType, AMPERSAND, AND, ASSIGN, ASTERISK,
+  ASTERISK_ASSIGN, BACKSLASH, BANG, COLON, COMMA, DOT, DOT_DOT, EOF, EQ, GT,
+  GT_EQ, LBRACE, LBRACKET, LPAREN, LT, LT_EQ, MINUS, MINUS_ASSIGN, NOT_EQ, OR,
+  PERCENT, PIPE, PLUS, PLUS_ASSIGN, POWER, QUOTE, RBRACE, RBRACKET, RPAREN,
+  SEMICOLON, SLASH, SLASH_ASSIGN,
 };
 
 /// The `Lexer` struct is responsible for the lexical analysis of the source code. It breaks down the source code into a sequence of tokens.
 ///
 /// # Fields
 ///
-/// * `input` - The source code to be tokenized.
+/// * `input` - The characters of the source code to be tokenized.
 ///
 /// * `position` - The current position in the `input` (points to the current character).
 ///
 /// * `read_position` - The current reading position in the `input` (points to the character after the current character).
 ///
 /// * `ch` - The current character under examination.
+///
+/// * `line` - The line of `ch` in the `input`, starting at 1.
+///
+/// * `column` - The column of `ch` in its line, starting at 1.
 pub struct Lexer {
-  input: String,
+  input: Vec<char>,
   position: usize,
   read_position: usize,
   ch: char,
+  line: usize,
+  column: usize,
 }
 
 impl Lexer {
   pub fn new(input: String) -> Self {
     let mut l = Self {
-      input,
+      input: input.chars().collect(),
       position: 0,
       read_position: 0,
       ch: '\0',
+      line: 1,
+      column: 0,
     };
     l.read_char();
     l
   }
 
+  /// Returns the next token, with its `span` set to where it was read from.
   pub fn next_token(&mut self) -> Token {
     self.skip_whitespace();
+    let start = self.current_position();
+    let mut tok = self.read_token();
+    tok.span = Span::new(start, self.current_position());
+    tok
+  }
+
+  fn read_token(&mut self) -> Token {
     let tok = match self.ch {
       ASSIGN => {
         if self.peek_char() == ASSIGN {
@@ -48,7 +67,21 @@ impl Lexer {
       LPAREN => Token::new(TokenType::Lparen, LPAREN.to_string()),
       RPAREN => Token::new(TokenType::Rparen, RPAREN.to_string()),
       COMMA => Token::new(TokenType::Comma, COMMA.to_string()),
+      COLON => Token::new(TokenType::Colon, COLON.to_string()),
+      // A lone `.` means nothing yet, so it falls through to `Illegal`.
+      DOT if self.peek_char() == DOT => {
+        self.read_char();
+        Token::new(TokenType::DotDot, DOT_DOT.to_owned())
+      }
+      PLUS if self.peek_char() == ASSIGN => {
+        self.read_char();
+        Token::new(TokenType::PlusAssign, PLUS_ASSIGN.to_owned())
+      }
       PLUS => Token::new(TokenType::Plus, PLUS.to_string()),
+      MINUS if self.peek_char() == ASSIGN => {
+        self.read_char();
+        Token::new(TokenType::MinusAssign, MINUS_ASSIGN.to_owned())
+      }
       MINUS => Token::new(TokenType::Minus, MINUS.to_string()),
       BANG => {
         if self.peek_char() == ASSIGN {
@@ -58,13 +91,57 @@ impl Lexer {
           Token::new(TokenType::Bang, BANG.to_string())
         }
       }
+      ASTERISK if self.peek_char() == ASSIGN => {
+        self.read_char();
+        Token::new(TokenType::AsteriskAssign, ASTERISK_ASSIGN.to_owned())
+      }
+      ASTERISK if self.peek_char() == ASTERISK => {
+        self.read_char();
+        Token::new(TokenType::Power, POWER.to_owned())
+      }
       ASTERISK => Token::new(TokenType::Asterisk, ASTERISK.to_string()),
+      SLASH if self.peek_char() == ASSIGN => {
+        self.read_char();
+        Token::new(TokenType::SlashAssign, SLASH_ASSIGN.to_owned())
+      }
       SLASH => Token::new(TokenType::Slash, SLASH.to_string()),
+      PERCENT => Token::new(TokenType::Percent, PERCENT.to_string()),
+      // Neither a lone `&` nor a lone `|` means anything, so they fall
+      // through to `Illegal` too.
+      AMPERSAND if self.peek_char() == AMPERSAND => {
+        self.read_char();
+        Token::new(TokenType::And, AND.to_owned())
+      }
+      PIPE if self.peek_char() == PIPE => {
+        self.read_char();
+        Token::new(TokenType::Or, OR.to_owned())
+      }
+      LT if self.peek_char() == ASSIGN => {
+        self.read_char();
+        Token::new(TokenType::LtEq, LT_EQ.to_owned())
+      }
       LT => Token::new(TokenType::Lt, LT.to_string()),
+      GT if self.peek_char() == ASSIGN => {
+        self.read_char();
+        Token::new(TokenType::GtEq, GT_EQ.to_owned())
+      }
       GT => Token::new(TokenType::Gt, GT.to_string()),
       LBRACE => Token::new(TokenType::Lbrace, LBRACE.to_string()),
       RBRACE => Token::new(TokenType::Rbrace, RBRACE.to_string()),
-      EOF => Token::new(TokenType::Eof, EOF.to_string()),
+      LBRACKET => Token::new(TokenType::Lbracket, LBRACKET.to_string()),
+      RBRACKET => Token::new(TokenType::Rbracket, RBRACKET.to_string()),
+      QUOTE => {
+        let start = self.position;
+        match self.read_string() {
+          Some(literal) => Token::new(TokenType::String, literal),
+          None => {
+            let literal = self.read_illegal_string(start);
+            return Token::new(TokenType::Illegal, literal);
+          }
+        }
+      }
+      // Stay on EOF so that repeated calls keep reporting the same span.
+      EOF => return Token::new(TokenType::Eof, EOF.to_string()),
       _ => {
         if self.is_letter() {
           let literal = self.read_identifier();
@@ -83,25 +160,28 @@ impl Lexer {
   }
 
   fn read_char(&mut self) {
-    if self.read_position >= self.input.len() {
-      self.ch = EOF;
+    if self.ch == '\n' {
+      self.line += 1;
+      self.column = 1;
     } else {
-      self.ch = self
-        .input
-        .chars()
-        .nth(self.read_position)
-        .expect("Reading char failed");
+      self.column += 1;
     }
+
+    self.ch = self.input.get(self.read_position).copied().unwrap_or(EOF);
     self.position = self.read_position;
     self.read_position += 1;
   }
 
+  fn current_position(&self) -> Position {
+    Position::new(self.line, self.column)
+  }
+
   fn read_identifier(&mut self) -> String {
     let position = self.position;
     while self.is_letter() {
       self.read_char();
     }
-    self.input[position..self.position].to_string()
+    self.input[position..self.position].iter().collect()
   }
 
   fn read_number(&mut self) -> String {
@@ -109,7 +189,72 @@ impl Lexer {
     while self.ch.is_ascii_digit() {
       self.read_char();
     }
-    self.input[position..self.position].to_string()
+    self.input[position..self.position].iter().collect()
+  }
+
+  /// Reads a string literal starting at the opening quote and returns its
+  /// value with the escape sequences resolved, leaving `ch` on the closing
+  /// quote. Returns `None` on an invalid escape or a missing closing quote,
+  /// with `ch` left on the offending character.
+  fn read_string(&mut self) -> Option<String> {
+    let mut value = String::new();
+
+    loop {
+      self.read_char();
+      match self.ch {
+        QUOTE => return Some(value),
+        EOF => return None,
+        BACKSLASH => {
+          self.read_char();
+          let escaped = match self.ch {
+            'n' => '\n',
+            't' => '\t',
+            QUOTE => QUOTE,
+            BACKSLASH => BACKSLASH,
+            'u' => self.read_unicode_escape()?,
+            _ => return None,
+          };
+          value.push(escaped);
+        }
+        ch => value.push(ch),
+      }
+    }
+  }
+
+  /// Reads the `{...}` part of a `\u{...}` escape, holding 1 to 6 hex digits.
+  fn read_unicode_escape(&mut self) -> Option<char> {
+    if self.peek_char() != LBRACE {
+      return None;
+    }
+    self.read_char();
+
+    let mut digits = String::new();
+    loop {
+      self.read_char();
+      match self.ch {
+        RBRACE => break,
+        ch if ch.is_ascii_hexdigit() && digits.len() < 6 => digits.push(ch),
+        _ => return None,
+      }
+    }
+
+    let code = u32::from_str_radix(&digits, 16).ok()?;
+    char::from_u32(code)
+  }
+
+  /// Consumes the rest of a malformed string literal that began at `start`,
+  /// up to its closing quote or the end of the line, and returns its source
+  /// text.
+  fn read_illegal_string(&mut self, start: usize) -> String {
+    while self.ch != QUOTE && self.ch != '\n' && self.ch != EOF {
+      self.read_char();
+    }
+    if self.ch == QUOTE {
+      self.read_char();
+    }
+
+    let end = self.position.min(self.input.len());
+    self.input[start..end].iter().collect()
   }
 
   fn is_letter(&self) -> bool {
@@ -123,26 +268,18 @@ impl Lexer {
   }
 
   fn peek_char(&self) -> char {
-    if self.read_position >= self.input.len() {
-      EOF
-    } else {
-      self
-        .input
-        .chars()
-        .nth(self.read_position)
-        .expect("Peeking char failed")
-    }
+    self.input.get(self.read_position).copied().unwrap_or(EOF)
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::super::lexer::Lexer;
-  use crate::token::TokenType;
+  use crate::token::{Position, Span, TokenType};
 
   #[test]
   fn test_next_token() {
-    let input = "=+(){},;".to_owned();
+    let input = "=+(){}[],;:".to_owned();
     let tests = vec![
       TokenType::Assign,
       TokenType::Plus,
@@ -150,8 +287,11 @@ mod tests {
       TokenType::Rparen,
       TokenType::Lbrace,
       TokenType::Rbrace,
+      TokenType::Lbracket,
+      TokenType::Rbracket,
       TokenType::Comma,
       TokenType::Semicolon,
+      TokenType::Colon,
       TokenType::Eof,
     ];
     let mut l = Lexer::new(input);
@@ -183,6 +323,14 @@ mod tests {
 
         10 == 10;
         10 != 9;
+
+        while (x) { break; continue; }
+        for (c in 0..10) {}
+        x = 1; x += 1; x -= 1; x *= 2; x /= 2;
+        flux y = 1;
+        a && b || !c;
+        a & b | c;
+        1 <= 2 >= 3 % 4 ** 5;
         "
     .to_owned();
 
@@ -260,6 +408,74 @@ mod tests {
       TokenType::NotEq,
       TokenType::Int,
       TokenType::Semicolon,
+      TokenType::While,
+      TokenType::Lparen,
+      TokenType::Ident,
+      TokenType::Rparen,
+      TokenType::Lbrace,
+      TokenType::Break,
+      TokenType::Semicolon,
+      TokenType::Continue,
+      TokenType::Semicolon,
+      TokenType::Rbrace,
+      TokenType::For,
+      TokenType::Lparen,
+      TokenType::Ident,
+      TokenType::In,
+      TokenType::Int,
+      TokenType::DotDot,
+      TokenType::Int,
+      TokenType::Rparen,
+      TokenType::Lbrace,
+      TokenType::Rbrace,
+      TokenType::Ident,
+      TokenType::Assign,
+      TokenType::Int,
+      TokenType::Semicolon,
+      TokenType::Ident,
+      TokenType::PlusAssign,
+      TokenType::Int,
+      TokenType::Semicolon,
+      TokenType::Ident,
+      TokenType::MinusAssign,
+      TokenType::Int,
+      TokenType::Semicolon,
+      TokenType::Ident,
+      TokenType::AsteriskAssign,
+      TokenType::Int,
+      TokenType::Semicolon,
+      TokenType::Ident,
+      TokenType::SlashAssign,
+      TokenType::Int,
+      TokenType::Semicolon,
+      TokenType::Flux,
+      TokenType::Ident,
+      TokenType::Assign,
+      TokenType::Int,
+      TokenType::Semicolon,
+      TokenType::Ident,
+      TokenType::And,
+      TokenType::Ident,
+      TokenType::Or,
+      TokenType::Bang,
+      TokenType::Ident,
+      TokenType::Semicolon,
+      TokenType::Ident,
+      TokenType::Illegal,
+      TokenType::Ident,
+      TokenType::Illegal,
+      TokenType::Ident,
+      TokenType::Semicolon,
+      TokenType::Int,
+      TokenType::LtEq,
+      TokenType::Int,
+      TokenType::GtEq,
+      TokenType::Int,
+      TokenType::Percent,
+      TokenType::Int,
+      TokenType::Power,
+      TokenType::Int,
+      TokenType::Semicolon,
       TokenType::Eof,
     ];
 
@@ -269,4 +485,82 @@ mod tests {
       assert_eq!(tok.token_type, t);
     }
   }
+
+  #[test]
+  fn test_token_spans() {
+    let input = "forge x = 10;\n  x == 5;".to_owned();
+    let tests = vec![
+      ((1, 1), (1, 6)),
+      ((1, 7), (1, 8)),
+      ((1, 9), (1, 10)),
+      ((1, 11), (1, 13)),
+      ((1, 13), (1, 14)),
+      ((2, 3), (2, 4)),
+      ((2, 5), (2, 7)),
+      ((2, 8), (2, 9)),
+      ((2, 9), (2, 10)),
+      ((2, 10), (2, 10)),
+      ((2, 10), (2, 10)),
+    ];
+
+    let mut l = Lexer::new(input);
+    for (start, end) in tests {
+      let tok = l.next_token();
+      assert_eq!(
+        tok.span,
+        Span::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+        "wrong span for {:?}",
+        tok
+      );
+    }
+  }
+
+  #[test]
+  fn test_string_literals() {
+    let input = r#""foobar" "foo bar" "a\nb\tc" "say \"hi\"" "back\\slash" "\u{1F5E1} sabre" "ünï""#;
+    let tests = vec![
+      "foobar",
+      "foo bar",
+      "a\nb\tc",
+      "say \"hi\"",
+      "back\\slash",
+      "\u{1F5E1} sabre",
+      "ünï",
+    ];
+
+    let mut l = Lexer::new(input.to_owned());
+    for expected in tests {
+      let tok = l.next_token();
+      assert_eq!(tok.token_type, TokenType::String);
+      assert_eq!(tok.literal, expected);
+    }
+    assert_eq!(l.next_token().token_type, TokenType::Eof);
+  }
+
+  #[test]
+  fn test_illegal_string_literals() {
+    let tests = vec![
+      (r#""bad \q escape"; 1"#, r#""bad \q escape""#),
+      (r#""\u{110000}" 1"#, r#""\u{110000}""#),
+      (r#""\u{zz}" 1"#, r#""\u{zz}""#),
+      ("\"never closed", "\"never closed"),
+    ];
+
+    for (input, expected) in tests {
+      let mut l = Lexer::new(input.to_owned());
+      let tok = l.next_token();
+      assert_eq!(tok.token_type, TokenType::Illegal);
+      assert_eq!(tok.literal, expected);
+    }
+  }
+
+  #[test]
+  fn test_string_span_counts_characters() {
+    let mut l = Lexer::new("\"ünï\" x".to_owned());
+    assert_eq!(
+      l.next_token().span,
+      Span::new(Position::new(1, 1), Position::new(1, 6))
+    );
+    assert_eq!(l.next_token().span.start, Position::new(1, 7));
+  }
 }
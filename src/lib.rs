@@ -0,0 +1,38 @@
+//! Saber is a small, dynamically typed scripting language.
+//!
+//! The `Interpreter` is the simplest way to run Saber code from Rust. The
+//! `lexer`, `parser` and `ast` modules can also be used on their own, e.g. to
+//! build tooling on top of the syntax tree:
+//!
+//! ```
+//! use saber::{lexer::Lexer, parser::Parser};
+//!
+//! let mut parser = Parser::new(Lexer::new("1 + 2 * 3".to_owned()));
+//! let program = parser.parse_program().unwrap();
+//!
+//! assert!(parser.errors.is_empty());
+//! assert_eq!(program.to_string(), "(1 + (2 * 3))");
+//! ```
+
+#![allow(dead_code)]
+
+pub mod ast;
+mod builtins;
+pub mod code;
+pub mod compiler;
+pub mod diagnostics;
+mod environment;
+mod evaluator;
+mod interpreter;
+pub mod lexer;
+pub mod object;
+pub mod parser;
+pub mod sbc;
+pub mod token;
+mod value;
+pub mod vm;
+
+pub use interpreter::{
+  compile_str, Engine, Interpreter, SaberError, DEFAULT_RECURSION_LIMIT,
+};
+pub use value::{Function, NativeFunction, Value};
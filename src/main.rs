@@ -1,16 +1,52 @@
-#![allow(dead_code)]
-
-use std::env;
-mod ast;
-mod evaluator;
-mod lexer;
-mod object;
-mod parser;
+use std::{env, process, thread};
+mod cli;
 mod repl;
-mod token;
+mod runner;
+
+use cli::Command;
+
+/// Every nested Saber call recurses in the evaluator too, so commands run on a
+/// thread with more stack than the main one gets. That way the recursion limit
+/// is always reached before the stack runs out.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// How deeply calls may nest in scripts run from the command line. The big
+/// stack above leaves room for far more than `DEFAULT_RECURSION_LIMIT`.
+const RECURSION_LIMIT: usize = 1000;
 
 fn main() {
-  let user = env::var("USER").expect("Failed to get the current user");
+  let command = thread::Builder::new()
+    .stack_size(STACK_SIZE)
+    .spawn(run)
+    .expect("failed to spawn the interpreter thread");
+
+  if command.join().is_err() {
+    process::exit(101);
+  }
+}
+
+fn run() {
+  let args: Vec<String> = env::args().skip(1).collect();
+
+  match Command::parse(&args) {
+    Ok(Command::Repl) => start_repl(),
+    Ok(Command::Run { path, engine }) => {
+      process::exit(runner::run_file(&path, engine))
+    }
+    Ok(Command::Build { input, output }) => {
+      process::exit(runner::build_file(&input, &output))
+    }
+    Ok(Command::Disasm { path }) => process::exit(runner::disasm_file(&path)),
+    Ok(Command::Help) => println!("{}", cli::USAGE),
+    Err(msg) => {
+      eprintln!("error: {}\n\n{}", msg, cli::USAGE);
+      process::exit(runner::EXIT_USAGE);
+    }
+  }
+}
+
+fn start_repl() {
+  let user = env::var("USER").unwrap_or_else(|_| "there".to_owned());
 
   println!(
     "💧 Hello {}! Welcome to the Saber programming language!",
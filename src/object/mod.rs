@@ -1,48 +1,454 @@
-#[derive(Debug)]
-pub enum ObjectType {
+use crate::{
+  ast::{BlockStatement, Identifier},
+  code::{self, Disassembly, Instructions},
+  environment::Env,
+  token::Span,
+  value::{NativeFunction, Value},
+};
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+pub const INTEGER_OBJ: &str = "INTEGER";
+pub const BOOLEAN_OBJ: &str = "BOOLEAN";
+pub const STRING_OBJ: &str = "STRING";
+pub const DARK_SIDE_OBJ: &str = "DARK_SIDE";
+pub const RETURN_VALUE_OBJ: &str = "RETURN_VALUE";
+pub const ERROR_OBJ: &str = "ERROR";
+pub const FUNCTION_OBJ: &str = "FUNCTION";
+pub const BUILTIN_OBJ: &str = "BUILTIN";
+pub const ARRAY_OBJ: &str = "ARRAY";
+pub const HASH_OBJ: &str = "HASH";
+pub const COMPILED_FUNCTION_OBJ: &str = "COMPILED_FUNCTION";
+pub const RANGE_OBJ: &str = "RANGE";
+pub const BREAK_OBJ: &str = "BREAK";
+pub const CONTINUE_OBJ: &str = "CONTINUE";
+
+/// Every value a Saber program can produce at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
   Integer(i64),
-  Boolean,
+  Boolean(bool),
+  String(String),
+  /// Saber's null value.
   DarkSide,
+  /// Wraps the value of an `ignite` while it travels up through the enclosing
+  /// blocks.
+  ReturnValue(Box<Object>),
+  /// Signals a `break` while it travels up to the enclosing loop.
+  Break,
+  /// Signals a `continue` while it travels up to the enclosing loop.
+  Continue,
+  /// A runtime failure. It propagates like a `ReturnValue` and aborts the
+  /// program. Boxed, since every value the evaluator passes around would
+  /// otherwise be as big as an error.
+  Error(Box<RuntimeError>),
+  Function(Rc<Function>),
+  Builtin(Builtin),
+  /// A function an embedder registered on an `Interpreter`.
+  Native(Native),
+  /// Arrays share their elements, so passing one around is cheap.
+  Array(Rc<Vec<Object>>),
+  Hash(Rc<HashMap<HashKey, HashPair>>),
+  /// A function prototype in the compiler's constant pool.
+  CompiledFunction(Rc<CompiledFunction>),
+  /// A compiled function together with the variables it captured, as created
+  /// by the virtual machine.
+  Closure(Rc<Closure>),
+  /// The integers from the first bound up to, but not including, the second,
+  /// as created by `start..end`.
+  Range(i64, i64),
 }
 
-trait Object {
-  fn object_type(&self) -> ObjectType;
-  fn inspect(&self) -> String;
+impl Object {
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Object::Integer(_) => INTEGER_OBJ,
+      Object::Boolean(_) => BOOLEAN_OBJ,
+      Object::String(_) => STRING_OBJ,
+      Object::DarkSide => DARK_SIDE_OBJ,
+      Object::ReturnValue(_) => RETURN_VALUE_OBJ,
+      Object::Break => BREAK_OBJ,
+      Object::Continue => CONTINUE_OBJ,
+      Object::Error(_) => ERROR_OBJ,
+      Object::Function(_) => FUNCTION_OBJ,
+      Object::Builtin(_) | Object::Native(_) => BUILTIN_OBJ,
+      Object::Array(_) => ARRAY_OBJ,
+      Object::Hash(_) => HASH_OBJ,
+      Object::CompiledFunction(_) => COMPILED_FUNCTION_OBJ,
+      // Both engines must agree on what `type` says about a function.
+      Object::Closure(_) => FUNCTION_OBJ,
+      Object::Range(..) => RANGE_OBJ,
+    }
+  }
+
+  pub fn inspect(&self) -> String {
+    match self {
+      Object::Integer(value) => value.to_string(),
+      Object::Boolean(value) => value.to_string(),
+      Object::String(value) => value.clone(),
+      Object::DarkSide => "DarkSide".to_owned(),
+      Object::ReturnValue(value) => value.inspect(),
+      Object::Break => "break".to_owned(),
+      Object::Continue => "continue".to_owned(),
+      Object::Error(err) => format!("ERROR: {}", err),
+      Object::Function(function) => function.inspect(),
+      Object::Builtin(builtin) => format!("builtin function {}", builtin.name),
+      Object::Native(native) => format!("builtin function {}", native.name),
+      Object::Array(elements) => {
+        let elements: Vec<String> =
+          elements.iter().map(Object::inspect_nested).collect();
+        format!("[{}]", elements.join(", "))
+      }
+      Object::Hash(pairs) => {
+        let mut pairs: Vec<(&HashKey, &HashPair)> = pairs.iter().collect();
+        pairs.sort_by(|a, b| a.0.cmp(b.0));
+        let pairs: Vec<String> = pairs
+          .iter()
+          .map(|(_, pair)| {
+            format!(
+              "{}: {}",
+              pair.key.inspect_nested(),
+              pair.value.inspect_nested()
+            )
+          })
+          .collect();
+        format!("{{{}}}", pairs.join(", "))
+      }
+      Object::CompiledFunction(function) => {
+        format!("compiled {}", function.display_name())
+      }
+      Object::Closure(closure) => closure.function.display_name(),
+      Object::Range(start, end) => format!("{}..{}", start, end),
+    }
+  }
 }
 
-struct Integer {
-  value: i64,
+impl Object {
+  /// Returns the key this object is stored under in a hash, or `None` when
+  /// the type can't be used as one.
+  pub fn hash_key(&self) -> Option<HashKey> {
+    match self {
+      Object::Integer(value) => Some(HashKey::Integer(*value)),
+      Object::Boolean(value) => Some(HashKey::Boolean(*value)),
+      Object::String(value) => Some(HashKey::String(value.clone())),
+      _ => None,
+    }
+  }
+
+  /// Like `inspect`, but quotes strings so they stand out inside arrays and
+  /// hashes.
+  pub(crate) fn inspect_nested(&self) -> String {
+    match self {
+      Object::String(value) => format!("{:?}", value),
+      _ => self.inspect(),
+    }
+  }
 }
 
-impl Object for Integer {
-  fn inspect(&self) -> String {
-    self.value.to_string()
+impl fmt::Display for Object {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.inspect())
   }
-  fn object_type(&self) -> ObjectType {
-    ObjectType::Integer(self.value)
+}
+
+/// A runtime failure. `span` is filled in by the evaluator with the location
+/// of the innermost expression that raised it. `related` is another location
+/// that explains the error, with a label for it, boxed since it is rare and
+/// errors travel through every `Result` in the VM. `trace` lists the calls that
+/// were in progress, most recent first, for errors where the way there
+/// matters, like running out of recursion depth.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+  pub message: String,
+  pub span: Option<Span>,
+  pub related: Option<Box<(Span, String)>>,
+  pub trace: Vec<CallSite>,
+}
+
+impl RuntimeError {
+  pub fn new(message: String) -> Self {
+    Self {
+      message,
+      span: None,
+      related: None,
+      trace: vec![],
+    }
   }
 }
 
-struct Boolean {
-  value: bool,
+/// A call in progress: the name of the function, if it has one, and the call
+/// expression that started it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+  pub function: Option<String>,
+  pub span: Option<Span>,
 }
 
-impl Object for Boolean {
-  fn inspect(&self) -> String {
-    self.value.to_string()
+impl fmt::Display for RuntimeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.span {
+      Some(span) => write!(f, "{}: {}", span, self.message),
+      None => write!(f, "{}", self.message),
+    }
   }
-  fn object_type(&self) -> ObjectType {
-    ObjectType::Boolean
+}
+
+/// A `weave` literal evaluated into a value. It keeps the environment it was
+/// defined in, which is what makes closures work. `name` is the name it was
+/// forged as, if any, for call traces.
+#[derive(Clone)]
+pub struct Function {
+  pub parameters: Vec<Identifier>,
+  pub body: BlockStatement,
+  pub env: Env,
+  pub name: Option<String>,
+}
+
+impl Function {
+  pub fn inspect(&self) -> String {
+    let params: Vec<String> =
+      self.parameters.iter().map(|p| p.to_string()).collect();
+    format!("weave({}) {{\n{}\n}}", params.join(", "), self.body)
   }
 }
 
-struct DarkSide;
+// The captured environment usually contains the function itself, so it is
+// left out of `Debug` to avoid printing a cycle forever.
+impl fmt::Debug for Function {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Function")
+      .field("parameters", &self.parameters)
+      .field("body", &self.body)
+      .field("name", &self.name)
+      .finish_non_exhaustive()
+  }
+}
+
+// Two functions are equal when they come from the same literal and close over
+// the same environment.
+impl PartialEq for Function {
+  fn eq(&self, other: &Self) -> bool {
+    Rc::ptr_eq(&self.env, &other.env) && self.inspect() == other.inspect()
+  }
+}
+
+/// Where a closure finds a captured variable when it is created: in a local
+/// slot of the enclosing function, or among the enclosing closure's own
+/// captures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+  Local(usize),
+  Free(usize),
+}
+
+/// The `CompiledFunction` struct is the output of compiling a `weave`
+/// literal, or a whole program.
+///
+/// # Fields
+///
+/// * `instructions` - The bytecode of the function body.
+///
+/// * `locals` - The name of each local slot a call needs, parameters first.
+///
+/// * `parameters` - Where each parameter was declared. A call must pass one argument for each.
+///
+/// * `captures` - The variables a closure over this function captures.
+///
+/// * `free` - The name of each captured variable, in `GetFree` index order.
+///
+/// * `positions` - The span each instruction was compiled from, keyed by its offset and sorted.
+///
+/// * `name` - The name the function was bound to, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+  pub instructions: Instructions,
+  pub locals: Vec<String>,
+  pub parameters: Vec<Span>,
+  pub captures: Vec<Capture>,
+  pub free: Vec<String>,
+  pub positions: Vec<(usize, Span)>,
+  pub name: Option<String>,
+}
 
-impl Object for DarkSide {
-  fn inspect(&self) -> String {
-    "DarkSide".to_owned()
+impl CompiledFunction {
+  /// Returns the span of the instruction at `offset`.
+  pub fn span_at(&self, offset: usize) -> Option<Span> {
+    code::span_at(&self.positions, offset)
   }
-  fn object_type(&self) -> ObjectType {
-    ObjectType::DarkSide
+
+  /// Returns a listing of the instructions, with the source line of each.
+  pub fn disassemble(&self) -> Disassembly<'_> {
+    Disassembly::new(&self.instructions).with_positions(&self.positions)
+  }
+
+  fn display_name(&self) -> String {
+    match &self.name {
+      Some(name) => format!("weave {}", name),
+      None => "weave <anonymous>".to_owned(),
+    }
+  }
+}
+
+/// A `CompiledFunction` instantiated at runtime. Captured variables are
+/// shared cells, so a closure sees later changes to them just like the
+/// evaluator's environments do.
+pub struct Closure {
+  pub function: Rc<CompiledFunction>,
+  pub free: Vec<Rc<RefCell<Variable>>>,
+}
+
+/// A variable of the virtual machine. Like a binding of the evaluator, it
+/// can only be assigned to when `mutable`, and `declared` is where the source
+/// declared it, if it did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+  pub value: Object,
+  pub mutable: bool,
+  pub declared: Option<Span>,
+}
+
+// Like `Function`, a closure can capture itself, so its captures are left out.
+impl fmt::Debug for Closure {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Closure")
+      .field("function", &self.function)
+      .finish_non_exhaustive()
+  }
+}
+
+// Closures have identity: two are only equal when they are the same value.
+impl PartialEq for Closure {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
+}
+
+/// The part of a hashable object that identifies it as a hash key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HashKey {
+  Integer(i64),
+  Boolean(bool),
+  String(String),
+}
+
+/// An entry of a hash. The original key object is kept next to the value so
+/// it can be shown again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashPair {
+  pub key: Object,
+  pub value: Object,
+}
+
+pub type BuiltinFunction = fn(&[Object]) -> Object;
+
+/// A function implemented in Rust and available to every Saber program, like
+/// `len`.
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+  pub name: &'static str,
+  pub function: BuiltinFunction,
+}
+
+impl PartialEq for Builtin {
+  fn eq(&self, other: &Self) -> bool {
+    self.name == other.name
+  }
+}
+
+/// A function implemented in Rust by an embedder. Unlike a `Builtin`, it only
+/// sees the public `Value`s, so it is called through a conversion.
+#[derive(Debug, Clone, Copy)]
+pub struct Native {
+  pub name: &'static str,
+  pub function: NativeFunction,
+}
+
+impl Native {
+  pub(crate) fn call(&self, args: &[Object]) -> Object {
+    let args: Vec<Value> =
+      args.iter().cloned().map(Value::from_object).collect();
+    match (self.function)(&args) {
+      Ok(value) => value.into_object(),
+      Err(message) => Object::Error(Box::new(RuntimeError::new(message))),
+    }
+  }
+}
+
+impl PartialEq for Native {
+  fn eq(&self, other: &Self) -> bool {
+    self.name == other.name
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_inspect_and_type_name() {
+    let tests = vec![
+      (Object::Integer(-7), "-7", INTEGER_OBJ),
+      (Object::Boolean(true), "true", BOOLEAN_OBJ),
+      (Object::String("hi".to_owned()), "hi", STRING_OBJ),
+      (Object::DarkSide, "DarkSide", DARK_SIDE_OBJ),
+      (
+        Object::ReturnValue(Box::new(Object::Integer(3))),
+        "3",
+        RETURN_VALUE_OBJ,
+      ),
+      (
+        Object::Error(Box::new(RuntimeError::new("boom".to_owned()))),
+        "ERROR: boom",
+        ERROR_OBJ,
+      ),
+      (
+        Object::Array(Rc::new(vec![
+          Object::Integer(1),
+          Object::String("two".to_owned()),
+        ])),
+        r#"[1, "two"]"#,
+        ARRAY_OBJ,
+      ),
+      (
+        Object::Hash(Rc::new(HashMap::from([
+          (
+            HashKey::String("b".to_owned()),
+            HashPair {
+              key: Object::String("b".to_owned()),
+              value: Object::Integer(2),
+            },
+          ),
+          (
+            HashKey::Integer(1),
+            HashPair {
+              key: Object::Integer(1),
+              value: Object::Boolean(true),
+            },
+          ),
+        ]))),
+        r#"{1: true, "b": 2}"#,
+        HASH_OBJ,
+      ),
+      (Object::Range(-1, 3), "-1..3", RANGE_OBJ),
+    ];
+
+    for (obj, inspected, type_name) in tests {
+      assert_eq!(obj.inspect(), inspected);
+      assert_eq!(obj.type_name(), type_name);
+    }
+  }
+
+  #[test]
+  fn test_hash_key() {
+    let hello1 = Object::String("Hello World".to_owned());
+    let hello2 = Object::String("Hello World".to_owned());
+    let diff = Object::String("My name is johnny".to_owned());
+
+    assert_eq!(hello1.hash_key(), hello2.hash_key());
+    assert_ne!(hello1.hash_key(), diff.hash_key());
+    assert_ne!(
+      Object::Integer(1).hash_key(),
+      Object::Boolean(true).hash_key()
+    );
+    assert_eq!(Object::DarkSide.hash_key(), None);
   }
 }
@@ -1,49 +1,105 @@
 use crate::{
   ast::{
-    BlockStatement, CallExpression, Expression, ExpressionStatement,
-    ForgeStatement, FunctionLiteral, Identifier, IfExpression, IgniteStatement,
-    InfixExpression, IntegerLiteral, PrefixExpression, Program, Statement,
+    ArrayLiteral, AssignExpression, BlockStatement, Boolean, BreakStatement,
+    CallExpression, ContinueStatement, Expression, ExpressionStatement,
+    ForInExpression, ForgeStatement, FunctionLiteral, HashLiteral, Identifier,
+    IfExpression, IgniteStatement, IndexExpression, InfixExpression,
+    IntegerLiteral, PrefixExpression, Program, Statement, StringLiteral,
+    WhileExpression,
   },
   lexer::Lexer,
-  token::Token,
   token::TokenType,
+  token::{Span, Token},
 };
-use std::{collections::HashMap, vec};
+use std::{collections::HashMap, fmt, vec};
 
-type PrefixParseFn = fn(&mut Parser) -> Option<Box<dyn Expression>>;
-type InfixParseFn =
-  fn(&mut Parser, Box<dyn Expression>) -> Option<Box<dyn Expression>>;
+type PrefixParseFn = fn(&mut Parser) -> Option<Expression>;
+type InfixParseFn = fn(&mut Parser, Expression) -> Option<Expression>;
 
 #[derive(Eq, PartialEq, PartialOrd)]
 enum Precedence {
   Lowest,
+  Assign,
+  LogicalOr,
+  LogicalAnd,
   Equals,
   LessGreater,
+  Range,
   Sum,
   Product,
   Prefix,
+  Power,
   Call,
+  Index,
+}
+
+/// What went wrong while parsing, so diagnostics can explain the error
+/// without picking `message` apart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+  UnexpectedToken {
+    expected: TokenType,
+    found: TokenType,
+  },
+  MissingExpression(TokenType),
+  InvalidInteger,
+  IllegalToken(String),
+  /// A `break` or `continue` that isn't inside a loop of the function it
+  /// appears in.
+  OutsideLoop(TokenType),
+  /// The left side of `=` or a compound assignment isn't a name or an
+  /// element of one.
+  InvalidAssignmentTarget,
+}
+
+/// A syntax error, with the span of the token the parser choked on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+  pub kind: ParseErrorKind,
+  pub message: String,
+  pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.span, self.message)
+  }
 }
 
 pub struct Parser {
   lexer: Lexer,
   current_token: Token,
   peek_token: Token,
-  pub errors: Vec<String>,
+  pub errors: Vec<ParseError>,
   prefix_parse_fns: HashMap<TokenType, PrefixParseFn>,
   infix_parse_fns: HashMap<TokenType, InfixParseFn>,
+  /// How many loops enclose the current token within the current function.
+  loop_depth: usize,
 }
 
-const PRECEDENCES: [(TokenType, Precedence); 9] = [
+const PRECEDENCES: [(TokenType, Precedence); 22] = [
+  (TokenType::Assign, Precedence::Assign),
+  (TokenType::PlusAssign, Precedence::Assign),
+  (TokenType::MinusAssign, Precedence::Assign),
+  (TokenType::AsteriskAssign, Precedence::Assign),
+  (TokenType::SlashAssign, Precedence::Assign),
+  (TokenType::Or, Precedence::LogicalOr),
+  (TokenType::And, Precedence::LogicalAnd),
   (TokenType::Eq, Precedence::Equals),
   (TokenType::NotEq, Precedence::Equals),
   (TokenType::Lt, Precedence::LessGreater),
   (TokenType::Gt, Precedence::LessGreater),
+  (TokenType::LtEq, Precedence::LessGreater),
+  (TokenType::GtEq, Precedence::LessGreater),
+  (TokenType::DotDot, Precedence::Range),
   (TokenType::Plus, Precedence::Sum),
   (TokenType::Minus, Precedence::Sum),
   (TokenType::Slash, Precedence::Product),
   (TokenType::Asterisk, Precedence::Product),
+  (TokenType::Percent, Precedence::Product),
+  (TokenType::Power, Precedence::Power),
   (TokenType::Lparen, Precedence::Call),
+  (TokenType::Lbracket, Precedence::Index),
 ];
 
 impl Parser {
@@ -54,6 +110,7 @@ impl Parser {
       HashMap::new();
     prefix_parse_fns.insert(TokenType::Ident, Self::parse_identifier);
     prefix_parse_fns.insert(TokenType::Int, Self::parse_integer_literal);
+    prefix_parse_fns.insert(TokenType::String, Self::parse_string_literal);
     prefix_parse_fns.insert(TokenType::Bang, Self::parse_prefix_expression);
     prefix_parse_fns.insert(TokenType::Minus, Self::parse_prefix_expression);
     prefix_parse_fns.insert(TokenType::True, Self::parse_boolean);
@@ -61,6 +118,10 @@ impl Parser {
     prefix_parse_fns.insert(TokenType::Lparen, Self::parse_grouped_expression);
     prefix_parse_fns.insert(TokenType::If, Self::parse_if_expression);
     prefix_parse_fns.insert(TokenType::Function, Self::parse_function_literal);
+    prefix_parse_fns.insert(TokenType::Lbracket, Self::parse_array_literal);
+    prefix_parse_fns.insert(TokenType::Lbrace, Self::parse_hash_literal);
+    prefix_parse_fns.insert(TokenType::While, Self::parse_while_expression);
+    prefix_parse_fns.insert(TokenType::For, Self::parse_for_in_expression);
 
     let mut infix_parse_fns: HashMap<TokenType, InfixParseFn> = HashMap::new();
     infix_parse_fns.insert(TokenType::Plus, Self::parse_infix_expression);
@@ -71,7 +132,24 @@ impl Parser {
     infix_parse_fns.insert(TokenType::NotEq, Self::parse_infix_expression);
     infix_parse_fns.insert(TokenType::Lt, Self::parse_infix_expression);
     infix_parse_fns.insert(TokenType::Gt, Self::parse_infix_expression);
+    infix_parse_fns.insert(TokenType::LtEq, Self::parse_infix_expression);
+    infix_parse_fns.insert(TokenType::GtEq, Self::parse_infix_expression);
+    infix_parse_fns.insert(TokenType::Percent, Self::parse_infix_expression);
+    infix_parse_fns.insert(TokenType::Power, Self::parse_infix_expression);
+    infix_parse_fns.insert(TokenType::DotDot, Self::parse_infix_expression);
+    infix_parse_fns.insert(TokenType::And, Self::parse_infix_expression);
+    infix_parse_fns.insert(TokenType::Or, Self::parse_infix_expression);
+    for token_type in [
+      TokenType::Assign,
+      TokenType::PlusAssign,
+      TokenType::MinusAssign,
+      TokenType::AsteriskAssign,
+      TokenType::SlashAssign,
+    ] {
+      infix_parse_fns.insert(token_type, Self::parse_assign_expression);
+    }
     infix_parse_fns.insert(TokenType::Lparen, Self::parse_call_expression);
+    infix_parse_fns.insert(TokenType::Lbracket, Self::parse_index_expression);
 
     Self {
       lexer: l,
@@ -80,6 +158,7 @@ impl Parser {
       errors: vec![],
       prefix_parse_fns,
       infix_parse_fns,
+      loop_depth: 0,
     }
   }
 
@@ -99,77 +178,121 @@ impl Parser {
     Some(program)
   }
 
-  fn parse_statement(&mut self) -> Option<Box<dyn Statement>> {
+  fn parse_statement(&mut self) -> Option<Statement> {
     match self.current_token.token_type {
-      TokenType::Forge => self.parse_forge_statement(),
+      TokenType::Forge | TokenType::Flux => self.parse_forge_statement(),
       TokenType::Ignite => self.parse_ignite_statement(),
+      TokenType::Break | TokenType::Continue => self.parse_loop_control(),
       _ => self.parse_expression_statement(),
     }
   }
 
-  fn parse_identifier(&mut self) -> Option<Box<dyn Expression>> {
-    Some(Box::new(Identifier {
-      token: self.current_token.clone(),
-      value: self.current_token.literal.clone(),
-    }))
+  fn parse_identifier(&mut self) -> Option<Expression> {
+    Some(Expression::Identifier(self.current_identifier()))
   }
 
-  fn parse_integer_literal(&mut self) -> Option<Box<dyn Expression>> {
+  fn parse_integer_literal(&mut self) -> Option<Expression> {
     match self.current_token.literal.parse::<i64>() {
-      Ok(value) => Some(Box::new(IntegerLiteral {
+      Ok(value) => Some(Expression::IntegerLiteral(IntegerLiteral {
         token: self.current_token.clone(),
+        span: self.current_token.span,
         value,
       })),
-      Err(_) => None,
+      Err(_) => {
+        let msg = format!(
+          "could not parse {:?} as integer",
+          self.current_token.literal
+        );
+        self.push_error(
+          ParseErrorKind::InvalidInteger,
+          msg,
+          self.current_token.span,
+        );
+        None
+      }
     }
   }
 
-  fn parse_prefix_expression(&mut self) -> Option<Box<dyn Expression>> {
+  fn parse_string_literal(&mut self) -> Option<Expression> {
+    Some(Expression::StringLiteral(StringLiteral {
+      token: self.current_token.clone(),
+      span: self.current_token.span,
+      value: self.current_token.literal.clone(),
+    }))
+  }
+
+  fn parse_prefix_expression(&mut self) -> Option<Expression> {
     let token = self.current_token.clone();
     let operator = self.current_token.literal.clone();
 
     self.next_token();
 
-    if let Some(right) = self.parse_expression(Precedence::Prefix) {
-      Some(Box::new(PrefixExpression {
-        token,
-        operator,
-        right: Some(right),
-      }))
-    } else {
-      None
-    }
+    let right = self.parse_expression(Precedence::Prefix)?;
+    Some(Expression::PrefixExpression(PrefixExpression {
+      span: self.span_from(token.span),
+      token,
+      operator,
+      right: Some(Box::new(right)),
+    }))
   }
 
-  fn parse_infix_expression(
+  fn parse_infix_expression(&mut self, left: Expression) -> Option<Expression> {
+    let token = self.current_token.clone();
+    let operator = self.current_token.literal.clone();
+    let precedence = match token.token_type {
+      // `**` groups to the right, so `2 ** 3 ** 2` is `2 ** (3 ** 2)`.
+      TokenType::Power => Precedence::Prefix,
+      _ => self.current_precedence(),
+    };
+    self.next_token();
+
+    let right = self.parse_expression(precedence)?;
+    Some(Expression::InfixExpression(InfixExpression {
+      token,
+      span: self.span_from(left.span()),
+      left: Some(Box::new(left)),
+      operator,
+      right: Some(Box::new(right)),
+    }))
+  }
+
+  /// Assignments group to the right, so `a = b = 1` assigns 1 to both.
+  fn parse_assign_expression(
     &mut self,
-    left: Box<dyn Expression>,
-  ) -> Option<Box<dyn Expression>> {
+    target: Expression,
+  ) -> Option<Expression> {
     let token = self.current_token.clone();
     let operator = self.current_token.literal.clone();
-    let precedence = self.current_precedence();
+
+    if target.as_place().is_none() {
+      let msg = format!("invalid assignment target: {}", target);
+      self.push_error(
+        ParseErrorKind::InvalidAssignmentTarget,
+        msg,
+        target.span(),
+      );
+    }
+
     self.next_token();
 
-    let right = self.parse_expression(precedence).unwrap();
-    Some(Box::new(InfixExpression {
+    let value = self.parse_expression(Precedence::Lowest)?;
+    Some(Expression::AssignExpression(AssignExpression {
       token,
-      left: Some(left),
+      span: self.span_from(target.span()),
+      target: Box::new(target),
       operator,
-      right: Some(right),
+      value: Some(Box::new(value)),
     }))
   }
 
-  fn parse_forge_statement(&mut self) -> Option<Box<dyn Statement>> {
+  fn parse_forge_statement(&mut self) -> Option<Statement> {
     let token = self.current_token.clone();
 
     if !self.expect_peek(TokenType::Ident) {
       return None;
     }
 
-    let name = Identifier {
-      token: self.current_token.clone(),
-      value: self.current_token.literal.clone(),
-    };
+    let name = self.current_identifier();
 
     if !self.expect_peek(TokenType::Assign) {
       return None;
@@ -177,47 +300,94 @@ impl Parser {
 
     self.next_token();
 
-    Some(Box::new(ForgeStatement {
+    let value = self.parse_expression(Precedence::Lowest).map(Box::new);
+
+    if self.peek_token_is(TokenType::Semicolon) {
+      self.next_token();
+    }
+
+    Some(Statement::ForgeStatement(ForgeStatement {
+      span: self.span_from(token.span),
       token,
       name,
-      value: self.parse_expression(Precedence::Lowest),
+      value,
     }))
   }
 
-  fn parse_ignite_statement(&mut self) -> Option<Box<dyn Statement>> {
+  fn parse_ignite_statement(&mut self) -> Option<Statement> {
     let token = self.current_token.clone();
 
+    if self.peek_token_is(TokenType::Semicolon) {
+      self.next_token();
+      return Some(Statement::IgniteStatement(IgniteStatement {
+        span: self.span_from(token.span),
+        token,
+        return_value: None,
+      }));
+    }
+
     self.next_token();
 
-    if self.current_token_is(TokenType::Semicolon) {
+    let return_value = self.parse_expression(Precedence::Lowest).map(Box::new);
+
+    if self.peek_token_is(TokenType::Semicolon) {
       self.next_token();
     }
 
-    Some(Box::new(IgniteStatement {
+    Some(Statement::IgniteStatement(IgniteStatement {
+      span: self.span_from(token.span),
       token,
-      return_value: self.parse_expression(Precedence::Lowest),
+      return_value,
     }))
   }
 
-  fn parse_expression_statement(&mut self) -> Option<Box<dyn Statement>> {
+  /// Parses `break` and `continue`, which only make sense inside a loop.
+  fn parse_loop_control(&mut self) -> Option<Statement> {
     let token = self.current_token.clone();
-    let expression = self.parse_expression(Precedence::Lowest);
+
+    if self.loop_depth == 0 {
+      let token_type = token.token_type.clone();
+      let msg = format!("{} outside of a loop", token_type.describe());
+      self.push_error(ParseErrorKind::OutsideLoop(token_type), msg, token.span);
+    }
 
     if self.peek_token_is(TokenType::Semicolon) {
       self.next_token();
     }
 
-    Some(Box::new(ExpressionStatement { token, expression }))
+    let span = self.span_from(token.span);
+    Some(match token.token_type {
+      TokenType::Break => {
+        Statement::BreakStatement(BreakStatement { token, span })
+      }
+      _ => Statement::ContinueStatement(ContinueStatement { token, span }),
+    })
   }
 
-  fn parse_boolean(&mut self) -> Option<Box<dyn Expression>> {
-    Some(Box::new(Identifier {
+  fn parse_expression_statement(&mut self) -> Option<Statement> {
+    let token = self.current_token.clone();
+    let expression = self.parse_expression(Precedence::Lowest).map(Box::new);
+
+    if self.peek_token_is(TokenType::Semicolon) {
+      self.next_token();
+    }
+
+    Some(Statement::ExpressionStatement(ExpressionStatement {
+      span: self.span_from(token.span),
+      token,
+      expression,
+    }))
+  }
+
+  fn parse_boolean(&mut self) -> Option<Expression> {
+    Some(Expression::Boolean(Boolean {
       token: self.current_token.clone(),
-      value: self.current_token_is(TokenType::True).to_string(),
+      span: self.current_token.span,
+      value: self.current_token_is(TokenType::True),
     }))
   }
 
-  fn parse_grouped_expression(&mut self) -> Option<Box<dyn Expression>> {
+  fn parse_grouped_expression(&mut self) -> Option<Expression> {
     self.next_token();
 
     let exp = self.parse_expression(Precedence::Lowest);
@@ -229,7 +399,7 @@ impl Parser {
     exp
   }
 
-  fn parse_if_expression(&mut self) -> Option<Box<dyn Expression>> {
+  fn parse_if_expression(&mut self) -> Option<Expression> {
     let token = self.current_token.clone();
 
     if !self.expect_peek(TokenType::Lparen) {
@@ -238,7 +408,7 @@ impl Parser {
 
     self.next_token();
 
-    let condition = self.parse_expression(Precedence::Lowest);
+    let condition = self.parse_expression(Precedence::Lowest).map(Box::new);
 
     if !self.expect_peek(TokenType::Rparen) {
       return None;
@@ -261,7 +431,8 @@ impl Parser {
       None
     };
 
-    Some(Box::new(IfExpression {
+    Some(Expression::IfExpression(IfExpression {
+      span: self.span_from(token.span),
       token,
       condition,
       consequence,
@@ -269,7 +440,83 @@ impl Parser {
     }))
   }
 
-  fn parse_function_literal(&mut self) -> Option<Box<dyn Expression>> {
+  fn parse_while_expression(&mut self) -> Option<Expression> {
+    let token = self.current_token.clone();
+
+    if !self.expect_peek(TokenType::Lparen) {
+      return None;
+    }
+
+    self.next_token();
+
+    let condition = self.parse_expression(Precedence::Lowest).map(Box::new);
+
+    if !self.expect_peek(TokenType::Rparen) {
+      return None;
+    }
+
+    if !self.expect_peek(TokenType::Lbrace) {
+      return None;
+    }
+
+    let body = self.parse_loop_body();
+
+    Some(Expression::WhileExpression(WhileExpression {
+      span: self.span_from(token.span),
+      token,
+      condition,
+      body,
+    }))
+  }
+
+  fn parse_for_in_expression(&mut self) -> Option<Expression> {
+    let token = self.current_token.clone();
+
+    if !self.expect_peek(TokenType::Lparen) {
+      return None;
+    }
+
+    if !self.expect_peek(TokenType::Ident) {
+      return None;
+    }
+
+    let variable = self.current_identifier();
+
+    if !self.expect_peek(TokenType::In) {
+      return None;
+    }
+
+    self.next_token();
+
+    let iterable = self.parse_expression(Precedence::Lowest).map(Box::new);
+
+    if !self.expect_peek(TokenType::Rparen) {
+      return None;
+    }
+
+    if !self.expect_peek(TokenType::Lbrace) {
+      return None;
+    }
+
+    let body = self.parse_loop_body();
+
+    Some(Expression::ForInExpression(ForInExpression {
+      span: self.span_from(token.span),
+      token,
+      variable,
+      iterable,
+      body,
+    }))
+  }
+
+  fn parse_loop_body(&mut self) -> Option<BlockStatement> {
+    self.loop_depth += 1;
+    let body = self.parse_block_statement();
+    self.loop_depth -= 1;
+    body
+  }
+
+  fn parse_function_literal(&mut self) -> Option<Expression> {
     let token = self.current_token.clone();
 
     if !self.expect_peek(TokenType::Lparen) {
@@ -282,9 +529,14 @@ impl Parser {
       return None;
     }
 
+    // A loop around the literal doesn't run again when the body does
+    // `break`, so the body starts outside of any loop.
+    let loop_depth = std::mem::take(&mut self.loop_depth);
     let body = self.parse_block_statement();
+    self.loop_depth = loop_depth;
 
-    Some(Box::new(FunctionLiteral {
+    Some(Expression::FunctionLiteral(FunctionLiteral {
+      span: self.span_from(token.span),
       token,
       parameters,
       body,
@@ -301,23 +553,13 @@ impl Parser {
 
     self.next_token();
 
-    let ident = Identifier {
-      token: self.current_token.clone(),
-      value: self.current_token.literal.clone(),
-    };
-
-    identifiers.push(ident);
+    identifiers.push(self.current_identifier());
 
     while self.peek_token_is(TokenType::Comma) {
       self.next_token();
       self.next_token();
 
-      let ident = Identifier {
-        token: self.current_token.clone(),
-        value: self.current_token.literal.clone(),
-      };
-
-      identifiers.push(ident);
+      identifiers.push(self.current_identifier());
     }
 
     if !self.expect_peek(TokenType::Rparen) {
@@ -330,6 +572,7 @@ impl Parser {
   fn parse_block_statement(&mut self) -> Option<BlockStatement> {
     let mut block = BlockStatement {
       token: self.current_token.clone(),
+      span: self.current_token.span,
       statements: vec![],
     };
 
@@ -344,43 +587,118 @@ impl Parser {
       self.next_token();
     }
 
+    block.span = self.span_from(block.span);
+
     Some(block)
   }
 
   fn parse_call_expression(
     &mut self,
-    function: Box<dyn Expression>,
-  ) -> Option<Box<dyn Expression>> {
+    function: Expression,
+  ) -> Option<Expression> {
     let token = self.current_token.clone();
-    let arguments = self.parse_call_arguments();
+    let arguments = self.parse_expression_list(TokenType::Rparen);
 
-    Some(Box::new(CallExpression {
+    Some(Expression::CallExpression(CallExpression {
       token,
-      function: Some(function),
+      span: self.span_from(function.span()),
+      function: Some(Box::new(function)),
       arguments,
     }))
   }
 
-  fn parse_call_arguments(&mut self) -> Vec<Box<dyn Expression>> {
+  fn parse_array_literal(&mut self) -> Option<Expression> {
+    let token = self.current_token.clone();
+    let elements = self.parse_expression_list(TokenType::Rbracket);
+
+    Some(Expression::ArrayLiteral(ArrayLiteral {
+      span: self.span_from(token.span),
+      token,
+      elements,
+    }))
+  }
+
+  /// Parses `{key: value, ...}`. Blocks never reach the prefix table because
+  /// `if` and `weave` parse their bodies directly, so a `{` that starts an
+  /// expression is always a hash.
+  fn parse_hash_literal(&mut self) -> Option<Expression> {
+    let token = self.current_token.clone();
+    let mut pairs = vec![];
+
+    while !self.peek_token_is(TokenType::Rbrace) {
+      self.next_token();
+      let key = self.parse_expression(Precedence::Lowest)?;
+
+      if !self.expect_peek(TokenType::Colon) {
+        return None;
+      }
+
+      self.next_token();
+      let value = self.parse_expression(Precedence::Lowest)?;
+      pairs.push((key, value));
+
+      if !self.peek_token_is(TokenType::Rbrace)
+        && !self.expect_peek(TokenType::Comma)
+      {
+        return None;
+      }
+    }
+
+    if !self.expect_peek(TokenType::Rbrace) {
+      return None;
+    }
+
+    Some(Expression::HashLiteral(HashLiteral {
+      span: self.span_from(token.span),
+      token,
+      pairs,
+    }))
+  }
+
+  fn parse_index_expression(&mut self, left: Expression) -> Option<Expression> {
+    let token = self.current_token.clone();
+
+    self.next_token();
+    let index = self.parse_expression(Precedence::Lowest)?;
+
+    if !self.expect_peek(TokenType::Rbracket) {
+      return None;
+    }
+
+    Some(Expression::IndexExpression(IndexExpression {
+      token,
+      span: self.span_from(left.span()),
+      left: Some(Box::new(left)),
+      index: Some(Box::new(index)),
+    }))
+  }
+
+  /// Parses comma separated expressions up to the closing `end` token, as
+  /// used by call arguments and array literals.
+  fn parse_expression_list(&mut self, end: TokenType) -> Vec<Expression> {
     let mut args = vec![];
 
-    if self.peek_token_is(TokenType::Rparen) {
+    if self.peek_token_is(end.clone()) {
       self.next_token();
       return args;
     }
 
     self.next_token();
 
-    args.push(self.parse_expression(Precedence::Lowest).unwrap());
+    if let Some(arg) = self.parse_expression(Precedence::Lowest) {
+      args.push(arg);
+    }
 
     while self.peek_token_is(TokenType::Comma) {
       self.next_token();
       self.next_token();
 
-      args.push(self.parse_expression(Precedence::Lowest).unwrap());
+      if let Some(arg) = self.parse_expression(Precedence::Lowest) {
+        args.push(arg);
+      }
     }
 
-    if !self.expect_peek(TokenType::Rparen) {
+    if !self.expect_peek(end) {
       return vec![];
     }
 
@@ -388,22 +706,34 @@ impl Parser {
   }
 
   fn no_prefix_parse_fn_error(&mut self, token_type: TokenType) {
-    let msg = format!("no prefix parse function for {:?} found", token_type);
-    self.errors.push(msg);
-  }
+    if token_type == TokenType::Illegal {
+      let literal = self.current_token.literal.clone();
+      let msg = format!("illegal token {}", literal);
+      let kind = ParseErrorKind::IllegalToken(literal);
+      self.push_error(kind, msg, self.current_token.span);
+      return;
+    }
 
-  fn parse_expression(
-    &mut self,
-    precedence: Precedence,
-  ) -> Option<Box<dyn Expression>> {
-    let prefix = self.prefix_parse_fns.get(&self.current_token.token_type);
+    let msg =
+      format!("expected an expression, found {}", token_type.describe());
+    self.push_error(
+      ParseErrorKind::MissingExpression(token_type),
+      msg,
+      self.current_token.span,
+    );
+  }
 
-    if prefix.is_none() {
-      self.no_prefix_parse_fn_error(self.current_token.token_type.clone());
-      return None;
-    }
+  fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression> {
+    let prefix = match self.prefix_parse_fns.get(&self.current_token.token_type)
+    {
+      Some(prefix) => *prefix,
+      None => {
+        self.no_prefix_parse_fn_error(self.current_token.token_type.clone());
+        return None;
+      }
+    };
 
-    let mut left_exp = prefix.unwrap()(self).unwrap();
+    let mut left_exp = prefix(self)?;
 
     while !self.peek_token_is(TokenType::Semicolon)
       && precedence < self.peek_precedence()
@@ -415,7 +745,7 @@ impl Parser {
       self.next_token();
 
       if let Some(infix_fn) = infix_fn_option {
-        left_exp = infix_fn(self, left_exp).unwrap();
+        left_exp = infix_fn(self, left_exp)?;
       } else {
         return Some(left_exp);
       }
@@ -460,16 +790,44 @@ impl Parser {
     }
   }
 
-  fn errors(&self) -> Vec<String> {
+  fn errors(&self) -> Vec<ParseError> {
     self.errors.clone()
   }
 
   fn peek_error(&mut self, token_type: TokenType) {
     let msg = format!(
-      "expected next token to be {:?}, got {:?} instead",
-      token_type, self.peek_token.token_type
+      "expected {}, found {}",
+      token_type.describe(),
+      self.peek_token.token_type.describe()
     );
-    self.errors.push(msg);
+    let kind = ParseErrorKind::UnexpectedToken {
+      expected: token_type,
+      found: self.peek_token.token_type.clone(),
+    };
+    self.push_error(kind, msg, self.peek_token.span);
+  }
+
+  fn push_error(&mut self, kind: ParseErrorKind, message: String, span: Span) {
+    self.errors.push(ParseError {
+      kind,
+      message,
+      span,
+    });
+  }
+
+  /// Builds an `Identifier` node from the current token.
+  fn current_identifier(&self) -> Identifier {
+    Identifier {
+      token: self.current_token.clone(),
+      span: self.current_token.span,
+      value: self.current_token.literal.clone(),
+    }
+  }
+
+  /// Returns the span from `start` to the end of the current token, which is
+  /// the last token of the node being parsed.
+  fn span_from(&self, start: Span) -> Span {
+    start.to(self.current_token.span)
   }
 
   fn register_prefix(&mut self, token_type: TokenType, func: PrefixParseFn) {
@@ -484,7 +842,7 @@ impl Parser {
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::lexer::Lexer;
+  use crate::{lexer::Lexer, token::Position};
 
   #[test]
   fn test_forge_statement_integers() {
@@ -500,10 +858,10 @@ mod tests {
 
       let program = p.parse_program().unwrap();
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
       assert_eq!(stmt.token_literal(), "forge");
-      assert_eq!(stmt.string(), format!("forge {} = {};", tt.1, tt.2));
+      assert_eq!(stmt.to_string(), format!("forge {} = {};", tt.1, tt.2));
     }
   }
 
@@ -520,10 +878,33 @@ mod tests {
 
       let program = p.parse_program().unwrap();
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
       assert_eq!(stmt.token_literal(), "forge");
-      assert_eq!(stmt.string(), format!("forge {} = {};", tt.1, tt.2));
+      assert_eq!(stmt.to_string(), format!("forge {} = {};", tt.1, tt.2));
+    }
+  }
+
+  #[test]
+  fn test_flux_statement() {
+    let tests = vec![
+      ("flux x = 5;", "flux x = 5;", true),
+      ("forge x = 5;", "forge x = 5;", false),
+    ];
+
+    for (input, expected, mutable) in tests {
+      let l = Lexer::new(input.to_string());
+      let mut p = Parser::new(l);
+
+      let program = p.parse_program().unwrap();
+
+      match &program.statements[0] {
+        Statement::ForgeStatement(stmt) => {
+          assert_eq!(stmt.to_string(), expected);
+          assert_eq!(stmt.is_mutable(), mutable);
+        }
+        stmt => panic!("not a forge statement. got={:?}", stmt),
+      }
     }
   }
 
@@ -541,10 +922,10 @@ mod tests {
 
       let program = p.parse_program().unwrap();
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
       assert_eq!(stmt.token_literal(), "ignite");
-      assert_eq!(stmt.string(), format!("ignite {};", tt.1));
+      assert_eq!(stmt.to_string(), format!("ignite {};", tt.1));
     }
   }
 
@@ -564,14 +945,14 @@ mod tests {
     assert_eq!(p.errors.len(), 4);
 
     let errors = [
-      "expected next token to be Assign, got Int instead",
-      "expected next token to be Ident, got Assign instead",
-      "no prefix parse function for Assign found",
-      "expected next token to be Ident, got Int instead",
+      "expected `=`, found an integer",
+      "expected an identifier, found `=`",
+      "expected an expression, found `=`",
+      "expected an identifier, found an integer",
     ];
 
     for (i, err) in errors.iter().enumerate() {
-      assert_eq!(p.errors[i], err.to_string());
+      assert_eq!(p.errors[i].message, err.to_string());
     }
   }
 
@@ -585,10 +966,10 @@ mod tests {
 
     assert_eq!(program.statements.len(), 1);
 
-    let stmt = program.statements[0].as_ref();
+    let stmt = &program.statements[0];
 
     assert_eq!(stmt.token_literal(), "foobar");
-    assert_eq!(stmt.string(), "foobar");
+    assert_eq!(stmt.to_string(), "foobar");
   }
 
   #[test]
@@ -601,9 +982,9 @@ mod tests {
 
     assert_eq!(program.statements.len(), 1);
 
-    let stmt = program.statements[0].as_ref();
+    let stmt = &program.statements[0];
     assert_eq!(stmt.token_literal(), "5");
-    assert_eq!(stmt.string(), "5");
+    assert_eq!(stmt.to_string(), "5");
   }
 
   #[test]
@@ -617,11 +998,11 @@ mod tests {
 
       assert_eq!(program.statements.len(), 1);
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
       assert_eq!(stmt.token_literal(), tt.1);
 
-      assert_eq!(stmt.string(), format!("({}{})", tt.1, tt.2));
+      assert_eq!(stmt.to_string(), format!("({}{})", tt.1, tt.2));
     }
   }
 
@@ -636,11 +1017,11 @@ mod tests {
 
       assert_eq!(program.statements.len(), 1);
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
       assert_eq!(stmt.token_literal(), tt.1);
 
-      assert_eq!(stmt.string(), format!("({}{})", tt.1, tt.2));
+      assert_eq!(stmt.to_string(), format!("({}{})", tt.1, tt.2));
     }
   }
 
@@ -664,9 +1045,9 @@ mod tests {
 
       assert_eq!(program.statements.len(), 1);
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
-      assert_eq!(stmt.string(), format!("({} {} {})", tt.1, tt.2, tt.3));
+      assert_eq!(stmt.to_string(), format!("({} {} {})", tt.1, tt.2, tt.3));
     }
   }
 
@@ -685,9 +1066,9 @@ mod tests {
 
       assert_eq!(program.statements.len(), 1);
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
-      assert_eq!(stmt.string(), format!("({} {} {})", tt.1, tt.2, tt.3));
+      assert_eq!(stmt.to_string(), format!("({} {} {})", tt.1, tt.2, tt.3));
     }
   }
 
@@ -727,6 +1108,33 @@ mod tests {
         "add(a + b + c * d / f + g)",
         "add((((a + b) + ((c * d) / f)) + g))",
       ),
+      (
+        "a * [1, 2, 3, 4][b * c] * d",
+        "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+      ),
+      (
+        "add(a * b[2], b[1], 2 * [1, 2][1])",
+        "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+      ),
+      ("f(x)[0]", "(f(x)[0])"),
+      ("0..n + 1", "(0 .. (n + 1))"),
+      ("a < 0..2 * b", "(a < (0 .. (2 * b)))"),
+      ("x = y = 1 + 2", "(x = (y = (1 + 2)))"),
+      ("x += a == b", "(x += (a == b))"),
+      ("a[i][j] *= 2", "(((a[i])[j]) *= 2)"),
+      ("a || b && c", "(a || (b && c))"),
+      ("a && b || c && d", "((a && b) || (c && d))"),
+      ("a || b || c", "((a || b) || c)"),
+      ("a == b && c < d", "((a == b) && (c < d))"),
+      ("!a && b", "((!a) && b)"),
+      ("x = a || b", "(x = (a || b))"),
+      ("a <= b == c >= d", "((a <= b) == (c >= d))"),
+      ("a + b % c * d", "(a + ((b % c) * d))"),
+      ("a * b ** c", "(a * (b ** c))"),
+      ("a ** b ** c", "(a ** (b ** c))"),
+      ("-a ** b", "(-(a ** b))"),
+      ("a ** -b", "(a ** (-b))"),
+      ("a ** b[0]", "(a ** (b[0]))"),
     ];
 
     for tt in tests {
@@ -735,14 +1143,11 @@ mod tests {
       let program = p.parse_program().unwrap();
 
       if program.statements.len() > 1 {
-        let combined_statements = format!(
-          "{}{}",
-          program.statements[0].string(),
-          program.statements[1].string()
-        );
+        let combined_statements =
+          format!("{}{}", program.statements[0], program.statements[1]);
         assert_eq!(combined_statements, tt.1);
       } else {
-        assert_eq!(program.statements[0].string(), tt.1);
+        assert_eq!(program.statements[0].to_string(), tt.1);
       }
     }
   }
@@ -758,7 +1163,7 @@ mod tests {
 
       assert_eq!(program.statements.len(), 1);
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
       assert_eq!(stmt.token_literal(), tt.1.to_string());
     }
@@ -774,10 +1179,10 @@ mod tests {
 
     assert_eq!(program.statements.len(), 1);
 
-    let stmt = program.statements[0].as_ref();
+    let stmt = &program.statements[0];
 
     assert_eq!(stmt.token_literal(), "if");
-    assert_eq!(stmt.string(), "if (x < y) x");
+    assert_eq!(stmt.to_string(), "if (x < y) x");
   }
 
   #[test]
@@ -790,10 +1195,10 @@ mod tests {
 
     assert_eq!(program.statements.len(), 1);
 
-    let stmt = program.statements[0].as_ref();
+    let stmt = &program.statements[0];
 
     assert_eq!(stmt.token_literal(), "if");
-    assert_eq!(stmt.string(), "if (x < y) xelse y");
+    assert_eq!(stmt.to_string(), "if (x < y) xelse y");
   }
 
   #[test]
@@ -806,10 +1211,10 @@ mod tests {
 
     assert_eq!(program.statements.len(), 1);
 
-    let stmt = program.statements[0].as_ref();
+    let stmt = &program.statements[0];
 
     assert_eq!(stmt.token_literal(), "weave");
-    assert_eq!(stmt.string(), "weave(x, y)(x + y)");
+    assert_eq!(stmt.to_string(), "weave(x, y)(x + y)");
   }
 
   #[test]
@@ -825,13 +1230,13 @@ mod tests {
       let mut p = Parser::new(l);
       let program = p.parse_program().unwrap();
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
       assert_eq!(stmt.token_literal(), "weave");
       if tt.1.is_empty() {
-        assert_eq!(stmt.string(), "weave()");
+        assert_eq!(stmt.to_string(), "weave()");
       } else {
-        assert_eq!(stmt.string(), format!("weave({})", tt.1.join(", ")));
+        assert_eq!(stmt.to_string(), format!("weave({})", tt.1.join(", ")));
       }
     }
   }
@@ -846,10 +1251,10 @@ mod tests {
 
     assert_eq!(program.statements.len(), 1);
 
-    let stmt = program.statements[0].as_ref();
+    let stmt = &program.statements[0];
 
     assert_eq!(stmt.token_literal(), "add");
-    assert_eq!(stmt.string(), "add(1, (2 * 3), (4 + 5))");
+    assert_eq!(stmt.to_string(), "add(1, (2 * 3), (4 + 5))");
   }
 
   #[test]
@@ -865,14 +1270,340 @@ mod tests {
       let mut p = Parser::new(l);
       let program = p.parse_program().unwrap();
 
-      let stmt = program.statements[0].as_ref();
+      let stmt = &program.statements[0];
 
       assert_eq!(stmt.token_literal(), "add");
       if tt.1.is_empty() {
-        assert_eq!(stmt.string(), "add()");
+        assert_eq!(stmt.to_string(), "add()");
       } else {
-        assert_eq!(stmt.string(), format!("add({})", tt.1.join(", ")));
+        assert_eq!(stmt.to_string(), format!("add({})", tt.1.join(", ")));
       }
     }
   }
+
+  #[test]
+  fn test_statements_consume_trailing_semicolon() {
+    let input = "forge x = 5; ignite x; ignite; x;";
+
+    let l = Lexer::new(input.to_string());
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+    assert_eq!(program.statements.len(), 4);
+    assert_eq!(program.to_string(), "forge x = 5;ignite x;ignite ;x");
+  }
+
+  #[test]
+  fn test_missing_closing_paren_reports_error() {
+    let l = Lexer::new("(1 + 2".to_string());
+    let mut p = Parser::new(l);
+    p.parse_program().unwrap();
+
+    assert_eq!(p.errors.len(), 1);
+    assert_eq!(p.errors[0].message, "expected `)`, found end of input");
+  }
+
+  #[test]
+  fn test_node_spans() {
+    let input = "forge x = 1 +\n  add(2, 3);";
+
+    let l = Lexer::new(input.to_string());
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    let stmt = &program.statements[0];
+    assert_eq!(
+      stmt.span(),
+      Span::new(Position::new(1, 1), Position::new(2, 13))
+    );
+
+    let value = match stmt {
+      Statement::ForgeStatement(stmt) => stmt.value.as_deref().unwrap(),
+      _ => panic!("not a forge statement. got={:?}", stmt),
+    };
+    assert_eq!(
+      value.span(),
+      Span::new(Position::new(1, 11), Position::new(2, 12))
+    );
+  }
+
+  #[test]
+  fn test_parser_error_spans() {
+    let l = Lexer::new("forge x 5;\nforge = 10;".to_string());
+    let mut p = Parser::new(l);
+    p.parse_program().unwrap();
+
+    assert_eq!(p.errors[0].span.start, Position::new(1, 9));
+    assert_eq!(p.errors[1].span.start, Position::new(2, 7));
+    assert_eq!(
+      p.errors[0].to_string(),
+      "1:9: expected `=`, found an integer"
+    );
+  }
+
+  #[test]
+  fn test_string_literal_expression() {
+    let input = r#""hello \"saber\"\n";"#;
+
+    let l = Lexer::new(input.to_string());
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+    match &program.statements[0] {
+      Statement::ExpressionStatement(stmt) => {
+        match stmt.expression.as_deref() {
+          Some(Expression::StringLiteral(lit)) => {
+            assert_eq!(lit.value, "hello \"saber\"\n")
+          }
+          exp => panic!("not a string literal. got={:?}", exp),
+        }
+      }
+      stmt => panic!("not an expression statement. got={:?}", stmt),
+    }
+    assert_eq!(program.to_string(), r#""hello \"saber\"\n""#);
+  }
+
+  #[test]
+  fn test_illegal_token_error() {
+    let l = Lexer::new(r#"forge s = "bad \q";"#.to_string());
+    let mut p = Parser::new(l);
+    p.parse_program().unwrap();
+
+    assert_eq!(p.errors[0].message, r#"illegal token "bad \q""#);
+    assert_eq!(
+      p.errors[0].kind,
+      ParseErrorKind::IllegalToken(r#""bad \q""#.to_owned())
+    );
+  }
+
+  #[test]
+  fn test_parsing_array_literals() {
+    let tests = vec![
+      ("[1, 2 * 2, 3 + 3]", "[1, (2 * 2), (3 + 3)]", 3),
+      ("[]", "[]", 0),
+    ];
+
+    for (input, expected, len) in tests {
+      let l = Lexer::new(input.to_string());
+      let mut p = Parser::new(l);
+      let program = p.parse_program().unwrap();
+
+      assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+      match &program.statements[0] {
+        Statement::ExpressionStatement(stmt) => {
+          match stmt.expression.as_deref() {
+            Some(Expression::ArrayLiteral(array)) => {
+              assert_eq!(array.elements.len(), len)
+            }
+            exp => panic!("not an array literal. got={:?}", exp),
+          }
+        }
+        stmt => panic!("not an expression statement. got={:?}", stmt),
+      }
+      assert_eq!(program.to_string(), expected);
+    }
+  }
+
+  #[test]
+  fn test_parsing_index_expressions() {
+    let l = Lexer::new("my_array[1 + 1]".to_string());
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+    assert_eq!(program.to_string(), "(my_array[(1 + 1)])");
+  }
+
+  #[test]
+  fn test_parsing_hash_literals() {
+    let tests = vec![
+      (
+        r#"{"one": 1, "two": 2, "three": 3}"#,
+        r#"{"one": 1, "two": 2, "three": 3}"#,
+        3,
+      ),
+      ("{}", "{}", 0),
+      (
+        r#"{"one": 0 + 1, true: 10 - 8, 3: 15 / 5}"#,
+        r#"{"one": (0 + 1), true: (10 - 8), 3: (15 / 5)}"#,
+        3,
+      ),
+    ];
+
+    for (input, expected, len) in tests {
+      let l = Lexer::new(input.to_string());
+      let mut p = Parser::new(l);
+      let program = p.parse_program().unwrap();
+
+      assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+      match &program.statements[0] {
+        Statement::ExpressionStatement(stmt) => {
+          match stmt.expression.as_deref() {
+            Some(Expression::HashLiteral(hash)) => {
+              assert_eq!(hash.pairs.len(), len)
+            }
+            exp => panic!("not a hash literal. got={:?}", exp),
+          }
+        }
+        stmt => panic!("not an expression statement. got={:?}", stmt),
+      }
+      assert_eq!(program.to_string(), expected);
+    }
+  }
+
+  #[test]
+  fn test_hash_literal_does_not_clash_with_blocks() {
+    let input = r#"if (x) { {"a": 1}["a"] } else { {} }"#;
+    let l = Lexer::new(input.to_string());
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+    assert_eq!(program.to_string(), r#"if x ({"a": 1}["a"])else {}"#);
+  }
+
+  #[test]
+  fn test_hash_literal_missing_colon() {
+    let l = Lexer::new(r#"{"a" 1}"#.to_string());
+    let mut p = Parser::new(l);
+    p.parse_program().unwrap();
+
+    assert_eq!(p.errors[0].message, "expected `:`, found an integer");
+  }
+
+  #[test]
+  fn test_while_expression() {
+    let input = "while (x < 10) { x; break; }";
+    let l = Lexer::new(input.to_string());
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+    match &program.statements[0] {
+      Statement::ExpressionStatement(stmt) => {
+        match stmt.expression.as_deref() {
+          Some(Expression::WhileExpression(exp)) => {
+            let body = exp.body.as_ref().unwrap();
+            assert_eq!(body.statements.len(), 2);
+            assert!(matches!(body.statements[1], Statement::BreakStatement(_)));
+          }
+          exp => panic!("not a WhileExpression. got={:?}", exp),
+        }
+      }
+      stmt => panic!("not an expression statement. got={:?}", stmt),
+    }
+    assert_eq!(program.to_string(), "while (x < 10) xbreak;");
+  }
+
+  #[test]
+  fn test_for_in_expression() {
+    let input = "for (item in [1, 2]) { if (item) { continue; } }";
+    let l = Lexer::new(input.to_string());
+    let mut p = Parser::new(l);
+    let program = p.parse_program().unwrap();
+
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+    match &program.statements[0] {
+      Statement::ExpressionStatement(stmt) => {
+        match stmt.expression.as_deref() {
+          Some(Expression::ForInExpression(exp)) => {
+            assert_eq!(exp.variable.value, "item");
+            assert_eq!(exp.iterable.as_ref().unwrap().to_string(), "[1, 2]");
+          }
+          exp => panic!("not a ForInExpression. got={:?}", exp),
+        }
+      }
+      stmt => panic!("not an expression statement. got={:?}", stmt),
+    }
+    assert_eq!(program.to_string(), "for item in [1, 2] if item continue;");
+  }
+
+  #[test]
+  fn test_loop_control_outside_loop() {
+    let tests = vec![
+      ("break;", TokenType::Break, (1, 1)),
+      ("if (x) { continue; }", TokenType::Continue, (1, 10)),
+      (
+        "while (x) { weave() { break; } }",
+        TokenType::Break,
+        (1, 23),
+      ),
+    ];
+
+    for (input, token_type, start) in tests {
+      let l = Lexer::new(input.to_string());
+      let mut p = Parser::new(l);
+      p.parse_program().unwrap();
+
+      assert_eq!(p.errors.len(), 1, "{}", input);
+      assert_eq!(
+        p.errors[0].message,
+        format!("{} outside of a loop", token_type.describe())
+      );
+      assert_eq!(p.errors[0].kind, ParseErrorKind::OutsideLoop(token_type));
+      assert_eq!(p.errors[0].span.start, Position::new(start.0, start.1));
+    }
+
+    let l = Lexer::new("for (x in y) { weave() {}; break; }".to_string());
+    let mut p = Parser::new(l);
+    p.parse_program().unwrap();
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+  }
+
+  #[test]
+  fn test_assign_expression() {
+    let tests = vec![
+      ("x = 5;", "x", 0, "="),
+      ("arr[0] -= 1;", "arr", 1, "-="),
+      (r#"h["k"][2] /= 3;"#, "h", 2, "/="),
+    ];
+
+    for (input, name, num_indices, operator) in tests {
+      let l = Lexer::new(input.to_string());
+      let mut p = Parser::new(l);
+      let program = p.parse_program().unwrap();
+
+      assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+      let exp = match &program.statements[0] {
+        Statement::ExpressionStatement(stmt) => stmt.expression.as_deref(),
+        stmt => panic!("not an expression statement. got={:?}", stmt),
+      };
+      match exp {
+        Some(Expression::AssignExpression(exp)) => {
+          let place = exp.target.as_place().unwrap();
+          assert_eq!(place.name.value, name);
+          assert_eq!(place.indices.len(), num_indices);
+          assert_eq!(exp.operator, operator);
+        }
+        exp => panic!("not an AssignExpression. got={:?}", exp),
+      }
+    }
+  }
+
+  #[test]
+  fn test_invalid_assignment_target() {
+    let tests = vec![
+      ("1 = 2", "invalid assignment target: 1", (1, 1)),
+      ("f(x) += 1", "invalid assignment target: f(x)", (1, 1)),
+      ("x + y = 3", "invalid assignment target: (x + y)", (1, 1)),
+      (
+        "a = f()[0] = 1",
+        "invalid assignment target: (f()[0])",
+        (1, 5),
+      ),
+    ];
+
+    for (input, message, start) in tests {
+      let l = Lexer::new(input.to_string());
+      let mut p = Parser::new(l);
+      p.parse_program().unwrap();
+
+      assert_eq!(p.errors.len(), 1, "{}", input);
+      assert_eq!(p.errors[0].message, message);
+      assert_eq!(p.errors[0].kind, ParseErrorKind::InvalidAssignmentTarget);
+      assert_eq!(p.errors[0].span.start, Position::new(start.0, start.1));
+    }
+  }
 }
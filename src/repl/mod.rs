@@ -1,41 +1,153 @@
-use crate::ast::Node;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::RECURSION_LIMIT;
+use saber::diagnostics::{self, Diagnostic};
+use saber::lexer::Lexer;
+use saber::token::TokenType;
+use saber::Interpreter;
 use std::io;
 use std::io::Write;
 
 const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+const FILE_NAME: &str = "<repl>";
 
 pub struct Repl;
 
 impl Repl {
   pub fn start() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_recursion_limit(RECURSION_LIMIT);
+
+    while let Some(buffer) = Repl::read_input() {
+      if buffer.trim().is_empty() {
+        continue;
+      }
+
+      match interpreter.eval_str(&buffer) {
+        Ok(evaluated) => println!("{}", evaluated),
+        Err(err) => Repl::print_diagnostics(&err.diagnostics(), &buffer),
+      }
+    }
+  }
+
+  /// Reads lines from stdin until they form a complete input, showing the
+  /// continuation prompt while braces or parentheses are still open. A blank
+  /// continuation line submits the buffer as is. Returns `None` at the end of
+  /// input.
+  fn read_input() -> Option<String> {
+    let mut buffer = String::new();
+
     loop {
-      print!("{}", PROMPT);
+      let continuing = !buffer.is_empty();
+      print!(
+        "{}",
+        if continuing {
+          CONTINUATION_PROMPT
+        } else {
+          PROMPT
+        }
+      );
       io::stdout().flush().unwrap();
 
-      let mut buffer = String::new();
-      io::stdin().read_line(&mut buffer).unwrap();
+      let mut line = String::new();
+      if io::stdin().read_line(&mut line).unwrap() == 0 {
+        println!();
+        return if continuing { Some(buffer) } else { None };
+      }
 
-      let lexer = Lexer::new(buffer);
-      let mut parser = Parser::new(lexer);
+      let blank = line.trim().is_empty();
+      buffer.push_str(&line);
 
-      let program = parser.parse_program().unwrap();
-      if !parser.errors.is_empty() {
-        Repl::print_parser_errors(parser.errors);
-        continue;
+      if is_complete(&buffer) || (continuing && blank) {
+        return Some(buffer);
       }
-
-      println!("{}", program.string());
     }
   }
-  fn print_parser_errors(errors: Vec<String>) {
+
+  fn print_diagnostics(diagnostics: &[Diagnostic], source: &str) {
     println!("Woops! 🌊 Something went wrong 🌊");
     println!("エラーが発生しました！(An error occurred!)");
-    println!(" parser errors:");
+    print!(
+      "{}",
+      diagnostics::render_all(diagnostics, source, FILE_NAME)
+    );
+  }
+}
+
+/// Reports whether `input` can be handed to the parser, i.e. every `{`, `(`
+/// and `[` is closed and it doesn't end with an operator that still needs
+/// its right side.
+fn is_complete(input: &str) -> bool {
+  let mut lexer = Lexer::new(input.to_owned());
+  let mut depth: i64 = 0;
+  let mut last = TokenType::Eof;
+
+  loop {
+    let tok = lexer.next_token();
+    match tok.token_type {
+      TokenType::Eof => break,
+      TokenType::Lbrace | TokenType::Lparen | TokenType::Lbracket => depth += 1,
+      TokenType::Rbrace | TokenType::Rparen | TokenType::Rbracket => depth -= 1,
+      _ => {}
+    }
+    last = tok.token_type;
+  }
+
+  let dangling = matches!(
+    last,
+    TokenType::Assign
+      | TokenType::PlusAssign
+      | TokenType::MinusAssign
+      | TokenType::AsteriskAssign
+      | TokenType::SlashAssign
+      | TokenType::Plus
+      | TokenType::Minus
+      | TokenType::Bang
+      | TokenType::Asterisk
+      | TokenType::Slash
+      | TokenType::Percent
+      | TokenType::Power
+      | TokenType::Lt
+      | TokenType::Gt
+      | TokenType::LtEq
+      | TokenType::GtEq
+      | TokenType::Eq
+      | TokenType::NotEq
+      | TokenType::Comma
+      | TokenType::Colon
+      | TokenType::DotDot
+      | TokenType::And
+      | TokenType::Or
+  );
+
+  depth <= 0 && !dangling
+}
+
+#[cfg(test)]
+mod tests {
+  use super::is_complete;
+
+  #[test]
+  fn test_is_complete() {
+    let tests = vec![
+      ("5 + 5;\n", true),
+      ("forge add = weave(x, y) {\n", false),
+      ("forge add = weave(x, y) {\n  x + y\n", false),
+      ("forge add = weave(x, y) {\n  x + y\n};\n", true),
+      ("add(1,\n", false),
+      ("add(1,\n 2)\n", true),
+      ("forge x = 5 +\n", false),
+      ("forge x =\n", false),
+      ("if (x) { 1 } else {\n", false),
+      ("1 }\n", true),
+      ("[1,\n 2", false),
+      ("[1,\n 2]\n", true),
+      ("{\"a\":\n", false),
+      ("ok &&\n", false),
+      ("", true),
+    ];
 
-    for msg in errors {
-      println!("\t{}", msg);
+    for (input, expected) in tests {
+      assert_eq!(is_complete(input), expected, "input: {:?}", input);
     }
   }
 }
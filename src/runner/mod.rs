@@ -0,0 +1,205 @@
+use crate::RECURSION_LIMIT;
+use saber::{
+  compile_str, diagnostics::Diagnostic, sbc, vm, Engine, Interpreter,
+};
+use std::{
+  fs,
+  io::{self, Write},
+  path::Path,
+};
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+/// Runs the script at `path` on `engine`, printing any diagnostics to stderr,
+/// and returns the process exit code. Compiled `.sbc` files always run on the
+/// virtual machine.
+pub fn run_file(path: &Path, engine: Engine) -> i32 {
+  let bytes = match fs::read(path) {
+    Ok(bytes) => bytes,
+    Err(err) => {
+      eprintln!("error: could not read `{}`: {}", path.display(), err);
+      return EXIT_FAILURE;
+    }
+  };
+
+  if sbc::is_bytecode(&bytes) || path.extension() == Some("sbc".as_ref()) {
+    return run_bytecode(path, &bytes);
+  }
+
+  let source = match String::from_utf8(bytes) {
+    Ok(source) => source,
+    Err(err) => {
+      eprintln!("error: could not read `{}`: {}", path.display(), err);
+      return EXIT_FAILURE;
+    }
+  };
+
+  let mut interpreter = Interpreter::with_engine(engine);
+  interpreter.set_recursion_limit(RECURSION_LIMIT);
+  match interpreter.eval_str(&source) {
+    Ok(_) => EXIT_SUCCESS,
+    Err(err) => {
+      eprint!("{}", err.render(&source, &path.display().to_string()));
+      EXIT_FAILURE
+    }
+  }
+}
+
+fn run_bytecode(path: &Path, bytes: &[u8]) -> i32 {
+  let bytecode = match sbc::decode(bytes) {
+    Ok(bytecode) => bytecode,
+    Err(err) => {
+      eprintln!("error: could not load `{}`: {}", path.display(), err);
+      return EXIT_FAILURE;
+    }
+  };
+
+  match vm::run(&bytecode, &mut vec![], RECURSION_LIMIT) {
+    Ok(_) => EXIT_SUCCESS,
+    Err(err) => {
+      // The source isn't shipped with the bytecode, so the diagnostic can only
+      // point at a line and column.
+      eprint!(
+        "{}",
+        Diagnostic::from(&err).render("", &path.display().to_string())
+      );
+      EXIT_FAILURE
+    }
+  }
+}
+
+/// Compiles the script at `input` and writes the bytecode to `output`,
+/// returning the process exit code.
+pub fn build_file(input: &Path, output: &Path) -> i32 {
+  let source = match fs::read_to_string(input) {
+    Ok(source) => source,
+    Err(err) => {
+      eprintln!("error: could not read `{}`: {}", input.display(), err);
+      return EXIT_FAILURE;
+    }
+  };
+
+  let bytecode = match compile_str(&source) {
+    Ok(bytecode) => bytecode,
+    Err(err) => {
+      eprint!("{}", err.render(&source, &input.display().to_string()));
+      return EXIT_FAILURE;
+    }
+  };
+
+  match fs::write(output, sbc::encode(&bytecode)) {
+    Ok(()) => EXIT_SUCCESS,
+    Err(err) => {
+      eprintln!("error: could not write `{}`: {}", output.display(), err);
+      EXIT_FAILURE
+    }
+  }
+}
+
+/// Prints the bytecode of the `.sbc` file or script at `path`, compiling it
+/// first in the latter case, and returns the process exit code.
+pub fn disasm_file(path: &Path) -> i32 {
+  let bytes = match fs::read(path) {
+    Ok(bytes) => bytes,
+    Err(err) => {
+      eprintln!("error: could not read `{}`: {}", path.display(), err);
+      return EXIT_FAILURE;
+    }
+  };
+
+  let bytecode = if sbc::is_bytecode(&bytes) {
+    match sbc::decode(&bytes) {
+      Ok(bytecode) => bytecode,
+      Err(err) => {
+        eprintln!("error: could not load `{}`: {}", path.display(), err);
+        return EXIT_FAILURE;
+      }
+    }
+  } else {
+    let source = match String::from_utf8(bytes) {
+      Ok(source) => source,
+      Err(err) => {
+        eprintln!("error: could not read `{}`: {}", path.display(), err);
+        return EXIT_FAILURE;
+      }
+    };
+    match compile_str(&source) {
+      Ok(bytecode) => bytecode,
+      Err(err) => {
+        eprint!("{}", err.render(&source, &path.display().to_string()));
+        return EXIT_FAILURE;
+      }
+    }
+  };
+
+  let mut out = io::stdout().lock();
+  if let Err(err) = write!(out, "{}", bytecode).and_then(|()| out.flush()) {
+    eprintln!("error: could not write the disassembly: {}", err);
+    return EXIT_FAILURE;
+  }
+  EXIT_SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    env,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+  };
+
+  /// Returns a path in the temp directory that no other test, nor another
+  /// run of the test binary, uses at the same time.
+  fn temp_path(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    env::temp_dir().join(format!("saber_{}_{}_{}", process::id(), n, name))
+  }
+
+  #[test]
+  fn test_run_file_exit_codes() {
+    let ok = temp_path("ok.sbr");
+    let failing = temp_path("failing.sbr");
+    fs::write(&ok, "forge a = 1; a + 1;").unwrap();
+    fs::write(&failing, "forge a = 1; a + b;").unwrap();
+
+    for engine in [Engine::Evaluator, Engine::Vm] {
+      assert_eq!(run_file(&ok, engine), EXIT_SUCCESS);
+      assert_eq!(run_file(&failing, engine), EXIT_FAILURE);
+      assert_eq!(run_file(&temp_path("missing.sbr"), engine), EXIT_FAILURE);
+    }
+
+    fs::remove_file(ok).unwrap();
+    fs::remove_file(failing).unwrap();
+  }
+
+  #[test]
+  fn test_build_and_run_bytecode() {
+    let script = temp_path("build.sbr");
+    let compiled = temp_path("build.sbc");
+    let broken = temp_path("build_broken.sbr");
+    fs::write(&script, "forge a = [1, 2]; len(a) + a[1];").unwrap();
+    fs::write(&broken, "forge a = ;").unwrap();
+
+    assert_eq!(build_file(&script, &compiled), EXIT_SUCCESS);
+    assert!(sbc::is_bytecode(&fs::read(&compiled).unwrap()));
+    assert_eq!(run_file(&compiled, Engine::Evaluator), EXIT_SUCCESS);
+    assert_eq!(disasm_file(&compiled), EXIT_SUCCESS);
+    assert_eq!(disasm_file(&script), EXIT_SUCCESS);
+    assert_eq!(disasm_file(&broken), EXIT_FAILURE);
+    assert_eq!(build_file(&broken, &compiled), EXIT_FAILURE);
+
+    // A truncated file must be rejected rather than run.
+    let bytes = fs::read(&compiled).unwrap();
+    fs::write(&compiled, &bytes[..bytes.len() / 2]).unwrap();
+    assert_eq!(run_file(&compiled, Engine::Vm), EXIT_FAILURE);
+
+    for path in [script, compiled, broken] {
+      fs::remove_file(path).unwrap();
+    }
+  }
+}
@@ -0,0 +1,566 @@
+//! The `.sbc` file format, which stores compiled bytecode so it can be run
+//! later without parsing the source again.
+//!
+//! Every number is big-endian, like instruction operands. A file is laid out
+//! as:
+//!
+//! ```text
+//! magic       4 bytes, "SBC\0"
+//! version     u16
+//! checksum    u32, CRC-32 of everything that follows
+//! globals     u32 count, then each name as a string
+//! constants   u32 count, then each constant as a tag byte and its value
+//! main        the function prototype of the top level
+//! ```
+//!
+//! Strings are a u32 byte length followed by UTF-8. A function prototype is
+//! its optional name, the names of its locals, the spans of its parameters,
+//! its captures with their names, instructions and source positions, in that
+//! order.
+use crate::{
+  builtins::BUILTINS,
+  code::{read_operands, Opcode},
+  compiler::Bytecode,
+  object::{Capture, CompiledFunction, Object},
+  token::{Position, Span},
+};
+use std::{convert::TryFrom, fmt, rc::Rc};
+
+pub const MAGIC: &[u8; 4] = b"SBC\0";
+
+/// Bumped whenever the meaning of existing bytes changes. Adding opcodes
+/// doesn't need a bump: older readers reject them as unknown.
+pub const FORMAT_VERSION: u16 = 2;
+
+const HEADER_LEN: usize = 10;
+
+const INTEGER_TAG: u8 = 0;
+const STRING_TAG: u8 = 1;
+const FUNCTION_TAG: u8 = 2;
+
+const LOCAL_CAPTURE: u8 = 0;
+const FREE_CAPTURE: u8 = 1;
+
+/// Why a file could not be loaded as bytecode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+  BadMagic,
+  UnsupportedVersion(u16),
+  Corrupt(String),
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      DecodeError::BadMagic => {
+        write!(f, "not a Saber bytecode file (bad magic header)")
+      }
+      DecodeError::UnsupportedVersion(version) => write!(
+        f,
+        "bytecode format version {} is not supported (expected version {}), rebuild it with `saber build`",
+        version, FORMAT_VERSION
+      ),
+      DecodeError::Corrupt(message) => {
+        write!(f, "corrupt bytecode file: {}", message)
+      }
+    }
+  }
+}
+
+/// Serializes `bytecode` into the `.sbc` format.
+pub fn encode(bytecode: &Bytecode) -> Vec<u8> {
+  let mut payload = Writer::default();
+
+  payload.u32(bytecode.globals.len());
+  for name in &bytecode.globals {
+    payload.string(name);
+  }
+
+  payload.u32(bytecode.constants.len());
+  for constant in &bytecode.constants {
+    payload.constant(constant);
+  }
+
+  payload.function(&bytecode.main);
+
+  let mut out = MAGIC.to_vec();
+  out.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+  out.extend_from_slice(&crc32(&payload.0).to_be_bytes());
+  out.extend(payload.0);
+  out
+}
+
+/// Loads bytecode written by `encode`, checking that it is intact and only
+/// refers to constants, globals and jump targets that exist.
+pub fn decode(bytes: &[u8]) -> Result<Bytecode, DecodeError> {
+  if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+    return Err(DecodeError::BadMagic);
+  }
+  if bytes.len() < HEADER_LEN {
+    return Err(corrupt("the header is truncated"));
+  }
+
+  let version = u16::from_be_bytes([bytes[4], bytes[5]]);
+  if version != FORMAT_VERSION {
+    return Err(DecodeError::UnsupportedVersion(version));
+  }
+
+  let checksum = u32::from_be_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+  let payload = &bytes[HEADER_LEN..];
+  if crc32(payload) != checksum {
+    return Err(corrupt("checksum mismatch"));
+  }
+
+  let mut reader = Reader {
+    bytes: payload,
+    pos: 0,
+  };
+
+  let num_globals = reader.u32()?;
+  let mut globals = Vec::with_capacity(num_globals.min(payload.len()));
+  for _ in 0..num_globals {
+    globals.push(reader.string()?);
+  }
+
+  let num_constants = reader.u32()?;
+  let limits = Limits {
+    constants: num_constants,
+    globals: globals.len(),
+  };
+  let mut constants = Vec::with_capacity(num_constants.min(payload.len()));
+  for _ in 0..num_constants {
+    constants.push(reader.constant(&limits)?);
+  }
+
+  let main = reader.function(&limits)?;
+  if reader.pos != payload.len() {
+    return Err(corrupt("unexpected data after the program"));
+  }
+
+  Ok(Bytecode {
+    main: Rc::new(main),
+    constants,
+    globals,
+  })
+}
+
+/// Reports whether `bytes` start like a `.sbc` file.
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+  bytes.starts_with(MAGIC)
+}
+
+fn corrupt(message: &str) -> DecodeError {
+  DecodeError::Corrupt(message.to_owned())
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+  fn u8(&mut self, value: u8) {
+    self.0.push(value);
+  }
+
+  fn u32(&mut self, value: usize) {
+    self.0.extend_from_slice(&(value as u32).to_be_bytes());
+  }
+
+  fn bytes(&mut self, bytes: &[u8]) {
+    self.u32(bytes.len());
+    self.0.extend_from_slice(bytes);
+  }
+
+  fn string(&mut self, value: &str) {
+    self.bytes(value.as_bytes());
+  }
+
+  fn constant(&mut self, constant: &Object) {
+    match constant {
+      Object::Integer(value) => {
+        self.u8(INTEGER_TAG);
+        self.0.extend_from_slice(&value.to_be_bytes());
+      }
+      Object::String(value) => {
+        self.u8(STRING_TAG);
+        self.string(value);
+      }
+      Object::CompiledFunction(function) => {
+        self.u8(FUNCTION_TAG);
+        self.function(function);
+      }
+      obj => unreachable!("{} can't be a constant", obj.type_name()),
+    }
+  }
+
+  fn function(&mut self, function: &CompiledFunction) {
+    match &function.name {
+      Some(name) => {
+        self.u8(1);
+        self.string(name);
+      }
+      None => self.u8(0),
+    }
+    self.u32(function.locals.len());
+    for name in &function.locals {
+      self.string(name);
+    }
+
+    self.u32(function.parameters.len());
+    for span in &function.parameters {
+      self.span(*span);
+    }
+
+    self.u32(function.captures.len());
+    for (capture, name) in function.captures.iter().zip(&function.free) {
+      let (kind, index) = match capture {
+        Capture::Local(index) => (LOCAL_CAPTURE, index),
+        Capture::Free(index) => (FREE_CAPTURE, index),
+      };
+      self.u8(kind);
+      self.u32(*index);
+      self.string(name);
+    }
+
+    self.bytes(&function.instructions);
+
+    self.u32(function.positions.len());
+    for (offset, span) in &function.positions {
+      self.u32(*offset);
+      self.span(*span);
+    }
+  }
+
+  fn span(&mut self, span: Span) {
+    for position in [span.start, span.end] {
+      self.u32(position.line);
+      self.u32(position.column);
+    }
+  }
+}
+
+/// The sizes the instructions of a file are checked against.
+struct Limits {
+  constants: usize,
+  globals: usize,
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl Reader<'_> {
+  fn take(&mut self, len: usize) -> Result<&[u8], DecodeError> {
+    let end = self
+      .pos
+      .checked_add(len)
+      .filter(|end| *end <= self.bytes.len());
+    let end = end.ok_or_else(|| corrupt("unexpected end of file"))?;
+    let bytes = &self.bytes[self.pos..end];
+    self.pos = end;
+    Ok(bytes)
+  }
+
+  fn u8(&mut self) -> Result<u8, DecodeError> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u32(&mut self) -> Result<usize, DecodeError> {
+    let bytes = self.take(4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+  }
+
+  fn bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+    let len = self.u32()?;
+    Ok(self.take(len)?.to_vec())
+  }
+
+  fn string(&mut self) -> Result<String, DecodeError> {
+    String::from_utf8(self.bytes()?)
+      .map_err(|_| corrupt("a string is not valid UTF-8"))
+  }
+
+  fn constant(&mut self, limits: &Limits) -> Result<Object, DecodeError> {
+    match self.u8()? {
+      INTEGER_TAG => {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(Object::Integer(i64::from_be_bytes(bytes)))
+      }
+      STRING_TAG => Ok(Object::String(self.string()?)),
+      FUNCTION_TAG => {
+        Ok(Object::CompiledFunction(Rc::new(self.function(limits)?)))
+      }
+      tag => Err(DecodeError::Corrupt(format!(
+        "unknown constant tag {}",
+        tag
+      ))),
+    }
+  }
+
+  fn function(
+    &mut self,
+    limits: &Limits,
+  ) -> Result<CompiledFunction, DecodeError> {
+    let name = match self.u8()? {
+      0 => None,
+      _ => Some(self.string()?),
+    };
+    let num_locals = self.u32()?;
+    let mut locals = Vec::with_capacity(num_locals.min(self.bytes.len()));
+    for _ in 0..num_locals {
+      locals.push(self.string()?);
+    }
+
+    let num_parameters = self.u32()?;
+    if num_parameters > num_locals {
+      return Err(corrupt("a function has more parameters than locals"));
+    }
+    let mut parameters = Vec::with_capacity(num_parameters);
+    for _ in 0..num_parameters {
+      parameters.push(self.span()?);
+    }
+
+    let num_captures = self.u32()?;
+    let mut captures = Vec::with_capacity(num_captures.min(self.bytes.len()));
+    let mut free = Vec::with_capacity(num_captures.min(self.bytes.len()));
+    for _ in 0..num_captures {
+      captures.push(match (self.u8()?, self.u32()?) {
+        (LOCAL_CAPTURE, index) => Capture::Local(index),
+        (FREE_CAPTURE, index) => Capture::Free(index),
+        (kind, _) => {
+          return Err(DecodeError::Corrupt(format!(
+            "unknown capture kind {}",
+            kind
+          )))
+        }
+      });
+      free.push(self.string()?);
+    }
+
+    let instructions = self.bytes()?;
+    validate_instructions(&instructions, limits, num_locals, num_captures)?;
+
+    let num_positions = self.u32()?;
+    let mut positions = Vec::with_capacity(num_positions.min(self.bytes.len()));
+    for _ in 0..num_positions {
+      let offset = self.u32()?;
+      positions.push((offset, self.span()?));
+    }
+
+    Ok(CompiledFunction {
+      instructions,
+      locals,
+      parameters,
+      captures,
+      free,
+      positions,
+      name,
+    })
+  }
+
+  fn span(&mut self) -> Result<Span, DecodeError> {
+    let start = Position {
+      line: self.u32()?,
+      column: self.u32()?,
+    };
+    let end = Position {
+      line: self.u32()?,
+      column: self.u32()?,
+    };
+    Ok(Span::new(start, end))
+  }
+}
+
+/// Checks that every instruction is complete and that its operands point at
+/// things that exist, so the virtual machine never has to trust a file.
+fn validate_instructions(
+  instructions: &[u8],
+  limits: &Limits,
+  num_locals: usize,
+  num_free: usize,
+) -> Result<(), DecodeError> {
+  let mut offset = 0;
+
+  while offset < instructions.len() {
+    let op = Opcode::try_from(instructions[offset]).map_err(|byte| {
+      DecodeError::Corrupt(format!(
+        "unknown opcode {} at offset {}",
+        byte, offset
+      ))
+    })?;
+    let def = op.definition();
+    let (operands, read) = read_operands(&def, &instructions[offset + 1..])
+      .ok_or_else(|| {
+        DecodeError::Corrupt(format!(
+          "truncated {} at offset {}",
+          def.name, offset
+        ))
+      })?;
+
+    let limit = match op {
+      Opcode::Constant | Opcode::Closure => Some(limits.constants),
+      Opcode::GetGlobal
+      | Opcode::SetGlobal
+      | Opcode::SetMutableGlobal
+      | Opcode::AssignGlobal => Some(limits.globals),
+      Opcode::GetLocal
+      | Opcode::SetLocal
+      | Opcode::SetMutableLocal
+      | Opcode::AssignLocal => Some(num_locals),
+      Opcode::GetFree | Opcode::SetFree => Some(num_free),
+      Opcode::GetBuiltin => Some(BUILTINS.len()),
+      Opcode::Jump | Opcode::JumpNotTruthy | Opcode::IterNext => {
+        Some(instructions.len() + 1)
+      }
+      _ => None,
+    };
+    if let Some(limit) = limit {
+      if operands[0] >= limit {
+        return Err(DecodeError::Corrupt(format!(
+          "{} at offset {} has out of range operand {}",
+          def.name, offset, operands[0]
+        )));
+      }
+    }
+
+    offset += 1 + read;
+  }
+
+  Ok(())
+}
+
+/// The CRC-32 (IEEE) checksum of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+  let mut crc = !0u32;
+
+  for byte in bytes {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      let mask = (crc & 1).wrapping_neg();
+      crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+    }
+  }
+
+  !crc
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    code::make, compiler::Compiler, interpreter::DEFAULT_RECURSION_LIMIT,
+    lexer::Lexer, parser::Parser, vm,
+  };
+
+  fn compile(input: &str) -> Bytecode {
+    let mut p = Parser::new(Lexer::new(input.to_owned()));
+    let program = p.parse_program().unwrap();
+    Compiler::new().compile(&program).unwrap()
+  }
+
+  /// Re-encodes a payload with a valid header, to check the errors that get
+  /// past the checksum.
+  fn with_header(payload: &[u8]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    out.extend_from_slice(&crc32(payload).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+  }
+
+  #[test]
+  fn test_crc32() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let bytecode = compile(
+      r#"
+      forge greeting = "hi";
+      forge counter = weave(start) {
+        forge step = weave(n) { n + start };
+        step(-1)
+      };
+      [counter(9223372036854775807), greeting, len(greeting)];
+      "#,
+    );
+
+    let decoded = decode(&encode(&bytecode)).unwrap();
+    assert_eq!(decoded, bytecode);
+    assert_eq!(
+      vm::run(&decoded, &mut vec![], DEFAULT_RECURSION_LIMIT)
+        .unwrap()
+        .inspect(),
+      r#"[9223372036854775806, "hi", 2]"#
+    );
+  }
+
+  #[test]
+  fn test_header_errors() {
+    let encoded = encode(&compile("1"));
+
+    assert_eq!(decode(b"forge x = 1;"), Err(DecodeError::BadMagic));
+    assert_eq!(
+      decode(&encoded[..6]),
+      Err(corrupt("the header is truncated"))
+    );
+
+    let mut newer = encoded.clone();
+    newer[5] = 9;
+    let err = decode(&newer).unwrap_err();
+    assert_eq!(err, DecodeError::UnsupportedVersion(9));
+    assert_eq!(
+      err.to_string(),
+      "bytecode format version 9 is not supported (expected version 2), rebuild it with `saber build`"
+    );
+
+    let mut flipped = encoded.clone();
+    *flipped.last_mut().unwrap() ^= 1;
+    assert_eq!(decode(&flipped), Err(corrupt("checksum mismatch")));
+  }
+
+  #[test]
+  fn test_corrupt_payloads() {
+    let encoded = encode(&compile("1"));
+    let payload = &encoded[HEADER_LEN..];
+
+    assert_eq!(
+      decode(&with_header(&payload[..payload.len() - 1])),
+      Err(corrupt("unexpected end of file"))
+    );
+
+    let mut trailing = payload.to_vec();
+    trailing.push(0);
+    assert_eq!(
+      decode(&with_header(&trailing)),
+      Err(corrupt("unexpected data after the program"))
+    );
+
+    let bad_jump = Bytecode {
+      main: Rc::new(CompiledFunction {
+        instructions: make(Opcode::Jump, &[100]),
+        locals: vec![],
+        parameters: vec![],
+        captures: vec![],
+        free: vec![],
+        positions: vec![],
+        name: None,
+      }),
+      constants: vec![],
+      globals: vec![],
+    };
+    assert_eq!(
+      decode(&encode(&bad_jump)).unwrap_err().to_string(),
+      "corrupt bytecode file: OpJump at offset 0 has out of range operand 100"
+    );
+
+    let mut bad_opcode = bad_jump;
+    Rc::make_mut(&mut bad_opcode.main).instructions = vec![200];
+    assert_eq!(
+      decode(&encode(&bad_opcode)),
+      Err(corrupt("unknown opcode 200 at offset 0"))
+    );
+  }
+}
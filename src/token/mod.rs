@@ -1,25 +1,52 @@
+use std::fmt;
+
 pub const WEAVE: &str = "weave";
 pub const FORGE: &str = "forge";
+pub const FLUX: &str = "flux";
 pub const TRUE: &str = "true";
 pub const FALSE: &str = "false";
 pub const IF: &str = "if";
 pub const ELSE: &str = "else";
 pub const IGNITE: &str = "ignite";
+pub const WHILE: &str = "while";
+pub const FOR: &str = "for";
+pub const IN: &str = "in";
+pub const BREAK: &str = "break";
+pub const CONTINUE: &str = "continue";
 
 pub const EQ: &str = "==";
 pub const NOT_EQ: &str = "!=";
+pub const LT_EQ: &str = "<=";
+pub const GT_EQ: &str = ">=";
+pub const POWER: &str = "**";
+pub const DOT_DOT: &str = "..";
+pub const PLUS_ASSIGN: &str = "+=";
+pub const MINUS_ASSIGN: &str = "-=";
+pub const ASTERISK_ASSIGN: &str = "*=";
+pub const SLASH_ASSIGN: &str = "/=";
+pub const AND: &str = "&&";
+pub const OR: &str = "||";
 pub const ASSIGN: char = '=';
 pub const SEMICOLON: char = ';';
 pub const LPAREN: char = '(';
 pub const RPAREN: char = ')';
 pub const LBRACE: char = '{';
 pub const RBRACE: char = '}';
+pub const LBRACKET: char = '[';
+pub const RBRACKET: char = ']';
 pub const COMMA: char = ',';
+pub const COLON: char = ':';
+pub const DOT: char = '.';
+pub const AMPERSAND: char = '&';
+pub const PIPE: char = '|';
 pub const PLUS: char = '+';
 pub const MINUS: char = '-';
 pub const BANG: char = '!';
 pub const ASTERISK: char = '*';
 pub const SLASH: char = '/';
+pub const PERCENT: char = '%';
+pub const QUOTE: char = '"';
+pub const BACKSLASH: char = '\\';
 pub const LT: char = '<';
 pub const GT: char = '>';
 pub const EOF: char = '\0';
@@ -30,29 +57,50 @@ pub enum TokenType {
   Eof,
   Ident,
   Int,
+  String,
   Assign,
+  PlusAssign,
+  MinusAssign,
+  AsteriskAssign,
+  SlashAssign,
   Plus,
   Minus,
   Bang,
   Asterisk,
   Slash,
+  Percent,
+  Power,
   Lt,
   Gt,
+  LtEq,
+  GtEq,
   Comma,
   Semicolon,
+  Colon,
+  DotDot,
   Lparen,
   Rparen,
   Lbrace,
   Rbrace,
+  Lbracket,
+  Rbracket,
   Function,
   Forge,
+  Flux,
   True,
   False,
   If,
   Else,
   Ignite,
+  While,
+  For,
+  In,
+  Break,
+  Continue,
   Eq,
   NotEq,
+  And,
+  Or,
 }
 
 impl TokenType {
@@ -60,20 +108,131 @@ impl TokenType {
     match ident {
       WEAVE => Self::Function,
       FORGE => Self::Forge,
+      FLUX => Self::Flux,
       TRUE => Self::True,
       FALSE => Self::False,
       IF => Self::If,
       ELSE => Self::Else,
       IGNITE => Self::Ignite,
+      WHILE => Self::While,
+      FOR => Self::For,
+      IN => Self::In,
+      BREAK => Self::Break,
+      CONTINUE => Self::Continue,
       _ => Self::Ident,
     }
   }
+
+  /// A human readable name for the token type, as used in diagnostics.
+  pub fn describe(&self) -> String {
+    let symbol = match self {
+      Self::Illegal => return "an illegal character".to_owned(),
+      Self::Eof => return "end of input".to_owned(),
+      Self::Ident => return "an identifier".to_owned(),
+      Self::Int => return "an integer".to_owned(),
+      Self::String => return "a string".to_owned(),
+      Self::Assign => ASSIGN.to_string(),
+      Self::PlusAssign => PLUS_ASSIGN.to_owned(),
+      Self::MinusAssign => MINUS_ASSIGN.to_owned(),
+      Self::AsteriskAssign => ASTERISK_ASSIGN.to_owned(),
+      Self::SlashAssign => SLASH_ASSIGN.to_owned(),
+      Self::Plus => PLUS.to_string(),
+      Self::Minus => MINUS.to_string(),
+      Self::Bang => BANG.to_string(),
+      Self::Asterisk => ASTERISK.to_string(),
+      Self::Slash => SLASH.to_string(),
+      Self::Percent => PERCENT.to_string(),
+      Self::Power => POWER.to_owned(),
+      Self::Lt => LT.to_string(),
+      Self::Gt => GT.to_string(),
+      Self::LtEq => LT_EQ.to_owned(),
+      Self::GtEq => GT_EQ.to_owned(),
+      Self::Comma => COMMA.to_string(),
+      Self::Semicolon => SEMICOLON.to_string(),
+      Self::Colon => COLON.to_string(),
+      Self::DotDot => DOT_DOT.to_owned(),
+      Self::Lparen => LPAREN.to_string(),
+      Self::Rparen => RPAREN.to_string(),
+      Self::Lbrace => LBRACE.to_string(),
+      Self::Rbrace => RBRACE.to_string(),
+      Self::Lbracket => LBRACKET.to_string(),
+      Self::Rbracket => RBRACKET.to_string(),
+      Self::Function => WEAVE.to_owned(),
+      Self::Forge => FORGE.to_owned(),
+      Self::Flux => FLUX.to_owned(),
+      Self::True => TRUE.to_owned(),
+      Self::False => FALSE.to_owned(),
+      Self::If => IF.to_owned(),
+      Self::Else => ELSE.to_owned(),
+      Self::Ignite => IGNITE.to_owned(),
+      Self::While => WHILE.to_owned(),
+      Self::For => FOR.to_owned(),
+      Self::In => IN.to_owned(),
+      Self::Break => BREAK.to_owned(),
+      Self::Continue => CONTINUE.to_owned(),
+      Self::Eq => EQ.to_owned(),
+      Self::NotEq => NOT_EQ.to_owned(),
+      Self::And => AND.to_owned(),
+      Self::Or => OR.to_owned(),
+    };
+    format!("`{}`", symbol)
+  }
+}
+
+/// A location in the source code. Both `line` and `column` start at 1, and
+/// columns count characters, not bytes.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Position {
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Position {
+  pub fn new(line: usize, column: usize) -> Self {
+    Self { line, column }
+  }
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+/// The region of source code a token or AST node was read from. `end` points
+/// just past the last character.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+  pub start: Position,
+  pub end: Position,
+}
+
+impl Span {
+  pub fn new(start: Position, end: Position) -> Self {
+    Self { start, end }
+  }
+
+  /// Returns the span that starts where `self` starts and ends where `other`
+  /// ends.
+  pub fn to(self, other: Span) -> Self {
+    Self {
+      start: self.start,
+      end: other.end,
+    }
+  }
+}
+
+impl fmt::Display for Span {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.start)
+  }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Token {
   pub token_type: TokenType,
   pub literal: String,
+  pub span: Span,
 }
 
 impl Token {
@@ -81,6 +240,7 @@ impl Token {
     Self {
       token_type,
       literal,
+      span: Span::default(),
     }
   }
 }
@@ -0,0 +1,138 @@
+use crate::object::{HashKey, HashPair, Object};
+use std::{collections::BTreeMap, fmt, rc::Rc};
+
+/// A Rust function callable from Saber, as registered with
+/// `Interpreter::register_native`. Returning `Err` fails the call with the
+/// message as a runtime error.
+pub type NativeFunction = fn(&[Value]) -> Result<Value, String>;
+
+/// A value produced by or handed to a Saber program through an
+/// `Interpreter`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  Integer(i64),
+  Boolean(bool),
+  String(String),
+  /// Saber's null value.
+  DarkSide,
+  Array(Vec<Value>),
+  /// The entries of a hash, ordered by key the way Saber prints them.
+  Hash(BTreeMap<HashKey, Value>),
+  /// The integers from the first bound up to, but not including, the second.
+  Range(i64, i64),
+  Function(Function),
+}
+
+/// A Saber function, builtin or native function. It can't be called from
+/// Rust, but it can be handed back to the interpreter it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function(Object);
+
+impl fmt::Display for Function {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0.inspect())
+  }
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.clone().into_object().inspect())
+  }
+}
+
+impl Value {
+  /// Converts a value the evaluator or the virtual machine produced. The
+  /// signals they use internally never make it this far.
+  pub(crate) fn from_object(obj: Object) -> Self {
+    match obj {
+      Object::Integer(value) => Value::Integer(value),
+      Object::Boolean(value) => Value::Boolean(value),
+      Object::String(value) => Value::String(value),
+      Object::DarkSide => Value::DarkSide,
+      Object::Array(elements) => {
+        Value::Array(elements.iter().cloned().map(Value::from_object).collect())
+      }
+      Object::Hash(pairs) => Value::Hash(
+        pairs
+          .iter()
+          .map(|(key, pair)| {
+            (key.clone(), Value::from_object(pair.value.clone()))
+          })
+          .collect(),
+      ),
+      Object::Range(start, end) => Value::Range(start, end),
+      Object::ReturnValue(value) => Value::from_object(*value),
+      function @ (Object::Function(_)
+      | Object::Builtin(_)
+      | Object::Native(_)
+      | Object::CompiledFunction(_)
+      | Object::Closure(_)) => Value::Function(Function(function)),
+      obj @ (Object::Break | Object::Continue | Object::Error(_)) => {
+        unreachable!("{} is not a value", obj.type_name())
+      }
+    }
+  }
+
+  pub(crate) fn into_object(self) -> Object {
+    match self {
+      Value::Integer(value) => Object::Integer(value),
+      Value::Boolean(value) => Object::Boolean(value),
+      Value::String(value) => Object::String(value),
+      Value::DarkSide => Object::DarkSide,
+      Value::Array(elements) => Object::Array(Rc::new(
+        elements.into_iter().map(Value::into_object).collect(),
+      )),
+      Value::Hash(pairs) => Object::Hash(Rc::new(
+        pairs
+          .into_iter()
+          .map(|(key, value)| {
+            let pair = HashPair {
+              key: key_object(&key),
+              value: value.into_object(),
+            };
+            (key, pair)
+          })
+          .collect(),
+      )),
+      Value::Range(start, end) => Object::Range(start, end),
+      Value::Function(function) => function.0,
+    }
+  }
+}
+
+fn key_object(key: &HashKey) -> Object {
+  match key {
+    HashKey::Integer(value) => Object::Integer(*value),
+    HashKey::Boolean(value) => Object::Boolean(*value),
+    HashKey::String(value) => Object::String(value.clone()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_object_round_trip() {
+    let value = Value::Array(vec![
+      Value::Integer(1),
+      Value::Hash(BTreeMap::from([
+        (HashKey::String("a".to_owned()), Value::Boolean(true)),
+        (HashKey::Integer(2), Value::DarkSide),
+      ])),
+      Value::Range(0, 3),
+      Value::String("saber".to_owned()),
+    ]);
+
+    let obj = value.clone().into_object();
+    assert_eq!(
+      obj.inspect(),
+      r#"[1, {2: DarkSide, "a": true}, 0..3, "saber"]"#
+    );
+    assert_eq!(Value::from_object(obj), value);
+    assert_eq!(
+      Value::from_object(Object::ReturnValue(Box::new(Object::Integer(5)))),
+      Value::Integer(5)
+    );
+  }
+}
@@ -0,0 +1,914 @@
+use crate::{
+  builtins::BUILTINS,
+  code::{read_u16, Opcode},
+  compiler::Bytecode,
+  evaluator::{
+    eval_index, eval_index_path, eval_infix_expression, eval_prefix_expression,
+    eval_set_index, immutable_assignment_error, is_truthy, iteration_element,
+    iteration_sequence, new_error, undeclared_assignment_error,
+    unusable_hash_key_error,
+  },
+  object::{
+    CallSite, Capture, Closure, HashPair, Object, RuntimeError, Variable,
+  },
+  token::Span,
+};
+use std::{cell::RefCell, collections::HashMap, convert::TryFrom, rc::Rc};
+
+/// The slots of the globals of a program. They are kept outside the virtual
+/// machine so they survive from one run to the next.
+pub type Globals = Vec<Option<Variable>>;
+
+/// The activation of a function.
+///
+/// # Fields
+///
+/// * `closure` - The function being run.
+///
+/// * `ip` - The offset of the next instruction to run.
+///
+/// * `locals` - The local slots. They are cells so closures created in the frame can share them.
+///
+/// * `stack_base` - The stack height to return to when the call is done.
+///
+/// * `call_site` - The span of the call that started the frame, for call traces.
+struct Frame {
+  closure: Rc<Closure>,
+  ip: usize,
+  locals: Vec<Rc<RefCell<Variable>>>,
+  stack_base: usize,
+  call_site: Option<Span>,
+}
+
+/// Runs `bytecode` and returns the value of its last statement, just like
+/// `evaluator::eval_program` would. Calls may nest `recursion_limit` deep;
+/// tail calls don't count since they replace the frame of their caller.
+pub fn run(
+  bytecode: &Bytecode,
+  globals: &mut Globals,
+  recursion_limit: usize,
+) -> Result<Object, RuntimeError> {
+  if globals.len() < bytecode.globals.len() {
+    globals.resize(bytecode.globals.len(), None);
+  }
+
+  let main = Closure {
+    function: Rc::clone(&bytecode.main),
+    free: vec![],
+  };
+  let mut vm = Vm {
+    constants: &bytecode.constants,
+    global_names: &bytecode.globals,
+    globals,
+    stack: vec![],
+    frames: vec![Frame {
+      closure: Rc::new(main),
+      ip: 0,
+      locals: vec![],
+      stack_base: 0,
+      call_site: None,
+    }],
+    recursion_limit,
+  };
+
+  vm.run()
+}
+
+struct Vm<'a> {
+  constants: &'a [Object],
+  global_names: &'a [String],
+  globals: &'a mut Globals,
+  stack: Vec<Object>,
+  frames: Vec<Frame>,
+  recursion_limit: usize,
+}
+
+impl Vm<'_> {
+  fn run(&mut self) -> Result<Object, RuntimeError> {
+    loop {
+      let offset = self.frame().ip;
+      let result = match self.read_opcode() {
+        Ok(op) => self.execute(op),
+        Err(err) => Err(err),
+      };
+
+      match result {
+        Ok(Some(value)) => return Ok(value),
+        Ok(None) => {}
+        // Like the evaluator, point at the innermost expression, which is
+        // the one the failing instruction was compiled from.
+        Err(mut err) => {
+          if err.span.is_none() {
+            err.span = self.frame().closure.function.span_at(offset);
+          }
+          return Err(err);
+        }
+      }
+    }
+  }
+
+  /// Runs a single instruction. Returns the result of the program once the
+  /// top level returns.
+  fn execute(&mut self, op: Opcode) -> Result<Option<Object>, RuntimeError> {
+    match op {
+      Opcode::Constant => {
+        let index = self.read_u16()?;
+        let constant = self.constant(index)?.clone();
+        self.push(constant);
+      }
+      Opcode::Pop => {
+        self.pop()?;
+      }
+      Opcode::Add
+      | Opcode::Sub
+      | Opcode::Mul
+      | Opcode::Div
+      | Opcode::Mod
+      | Opcode::Pow
+      | Opcode::Equal
+      | Opcode::NotEqual
+      | Opcode::LessThan
+      | Opcode::GreaterThan
+      | Opcode::LessEqual
+      | Opcode::GreaterEqual
+      | Opcode::Range => {
+        let right = self.pop()?;
+        let left = self.pop()?;
+        let result = eval_infix_expression(infix_operator(op), left, right);
+        self.push_result(result)?;
+      }
+      Opcode::Minus => {
+        let right = self.pop()?;
+        self.push_result(eval_prefix_expression("-", right))?;
+      }
+      Opcode::Bang => {
+        let right = self.pop()?;
+        self.push_result(eval_prefix_expression("!", right))?;
+      }
+      Opcode::True => self.push(Object::Boolean(true)),
+      Opcode::False => self.push(Object::Boolean(false)),
+      Opcode::DarkSide => self.push(Object::DarkSide),
+      Opcode::JumpNotTruthy => {
+        let target = self.read_u16()?;
+        if !is_truthy(&self.pop()?) {
+          self.frame_mut().ip = target;
+        }
+      }
+      Opcode::Jump => {
+        let target = self.read_u16()?;
+        self.frame_mut().ip = target;
+      }
+      Opcode::GetGlobal => {
+        let index = self.read_u16()?;
+        match self.globals.get(index) {
+          Some(Some(variable)) => {
+            let value = variable.value.clone();
+            self.push(value);
+          }
+          _ => {
+            let name = self.global_names.get(index).map_or("?", String::as_str);
+            return Err(runtime_error(format!(
+              "identifier not found: {}",
+              name
+            )));
+          }
+        }
+      }
+      Opcode::SetGlobal | Opcode::SetMutableGlobal => {
+        let index = self.read_u16()?;
+        let value = self.pop()?;
+        let variable = self.declare(value, op == Opcode::SetMutableGlobal);
+        if index >= self.globals.len() {
+          self.globals.resize(index + 1, None);
+        }
+        self.globals[index] = Some(variable);
+      }
+      Opcode::AssignGlobal => {
+        let index = self.read_u16()?;
+        let value = self.pop()?;
+        let name = self.global_names.get(index).map_or("?", String::as_str);
+        match self.globals.get_mut(index) {
+          Some(Some(variable)) if variable.mutable => variable.value = value,
+          Some(Some(variable)) => {
+            let err = immutable_assignment_error(name, variable.declared);
+            return Err(into_error(err));
+          }
+          _ => return Err(into_error(undeclared_assignment_error(name))),
+        }
+      }
+      Opcode::GetLocal => {
+        let index = self.read_u8()?;
+        let value = self.local(index)?.borrow().value.clone();
+        self.push(value);
+      }
+      Opcode::SetLocal | Opcode::SetMutableLocal => {
+        let index = self.read_u8()?;
+        let value = self.pop()?;
+        let variable = self.declare(value, op == Opcode::SetMutableLocal);
+        *self.local(index)?.borrow_mut() = variable;
+      }
+      Opcode::AssignLocal => {
+        let index = self.read_u8()?;
+        let value = self.pop()?;
+        let function = &self.frame().closure.function;
+        let name = function.locals.get(index).map_or("?", String::as_str);
+        assign(self.local(index)?, value, name)?;
+      }
+      Opcode::GetBuiltin => {
+        let index = self.read_u8()?;
+        match BUILTINS.get(index) {
+          Some(builtin) => self.push(Object::Builtin(*builtin)),
+          None => return Err(corrupt(format!("unknown builtin {}", index))),
+        }
+      }
+      Opcode::GetFree => {
+        let index = self.read_u8()?;
+        let value = match self.frame().closure.free.get(index) {
+          Some(cell) => cell.borrow().value.clone(),
+          None => {
+            return Err(corrupt(format!("unknown free variable {}", index)))
+          }
+        };
+        self.push(value);
+      }
+      Opcode::SetFree => {
+        let index = self.read_u8()?;
+        let value = self.pop()?;
+        let closure = &self.frame().closure;
+        let name = closure.function.free.get(index).map_or("?", String::as_str);
+        match closure.free.get(index) {
+          Some(cell) => assign(cell, value, name)?,
+          None => {
+            return Err(corrupt(format!("unknown free variable {}", index)))
+          }
+        }
+      }
+      Opcode::Array => {
+        let len = self.read_u16()?;
+        let elements = self.pop_many(len)?;
+        self.push(Object::Array(Rc::new(elements)));
+      }
+      Opcode::Hash => {
+        let len = self.read_u16()?;
+        let items = self.pop_many(len * 2)?;
+        let mut pairs = HashMap::with_capacity(len);
+
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+          match key.hash_key() {
+            Some(hash_key) => pairs.insert(hash_key, HashPair { key, value }),
+            None => return Err(into_error(unusable_hash_key_error(&key))),
+          };
+        }
+        self.push(Object::Hash(Rc::new(pairs)));
+      }
+      Opcode::Index => {
+        let index = self.pop()?;
+        let left = self.pop()?;
+        self.push_result(eval_index(left, index))?;
+      }
+      Opcode::IndexPath => {
+        let count = self.read_u8()?;
+        let len = self.stack.len();
+        if len < self.frame().stack_base + count + 1 {
+          return Err(corrupt("stack underflow".to_owned()));
+        }
+        let container = self.stack[len - count - 1].clone();
+        let element = eval_index_path(container, &self.stack[len - count..]);
+        self.push_result(element)?;
+      }
+      Opcode::SetIndex => {
+        let count = self.read_u8()?;
+        let value = self.pop()?;
+        let indices = self.pop_many(count)?;
+        let container = self.pop()?;
+        let updated = eval_set_index(container, &indices, value.clone());
+        if let Object::Error(err) = updated {
+          return Err(*err);
+        }
+        self.push(value);
+        self.push(updated);
+      }
+      Opcode::Call => {
+        let num_args = self.read_u8()?;
+        self.call(num_args, false)?;
+      }
+      Opcode::TailCall => {
+        let num_args = self.read_u8()?;
+        self.call(num_args, true)?;
+      }
+      Opcode::ReturnValue => {
+        let value = self.pop()?;
+        let frame = self.frames.pop().expect("no frame to return from");
+        if self.frames.is_empty() {
+          return Ok(Some(value));
+        }
+        self.stack.truncate(frame.stack_base);
+        self.push(value);
+      }
+      Opcode::Closure => {
+        let index = self.read_u16()?;
+        let function = match self.constant(index)? {
+          Object::CompiledFunction(function) => Rc::clone(function),
+          obj => {
+            return Err(corrupt(format!("not a function: {}", obj.type_name())))
+          }
+        };
+
+        let mut free = Vec::with_capacity(function.captures.len());
+        for capture in &function.captures {
+          free.push(match *capture {
+            Capture::Local(index) => Rc::clone(self.local(index)?),
+            Capture::Free(index) => {
+              match self.frame().closure.free.get(index) {
+                Some(cell) => Rc::clone(cell),
+                None => {
+                  return Err(corrupt(format!(
+                    "unknown free variable {}",
+                    index
+                  )))
+                }
+              }
+            }
+          });
+        }
+        self.push(Object::Closure(Rc::new(Closure { function, free })));
+      }
+      Opcode::Iterable => {
+        let iterable = self.pop()?;
+        self.push_result(iteration_sequence(iterable))?;
+        self.push(Object::Integer(0));
+      }
+      Opcode::IterNext => {
+        let target = self.read_u16()?;
+        let index = match self.pop()? {
+          Object::Integer(index) => index as usize,
+          obj => {
+            return Err(corrupt(format!(
+              "not a loop index: {}",
+              obj.type_name()
+            )))
+          }
+        };
+        let sequence = self.pop()?;
+
+        let element = iteration_element(&sequence, index);
+        self.push(sequence);
+        self.push(Object::Integer(index as i64 + 1));
+        match element {
+          Some(element) => self.push(element),
+          None => self.frame_mut().ip = target,
+        }
+      }
+    }
+
+    Ok(None)
+  }
+
+  /// Calls the function below the top `num_args` values. A `tail` call to a
+  /// closure replaces the current frame, which is only valid when the caller
+  /// would return the result straight away.
+  fn call(&mut self, num_args: usize, tail: bool) -> Result<(), RuntimeError> {
+    let args = self.pop_many(num_args)?;
+    let callee = self.pop()?;
+
+    match callee {
+      Object::Closure(closure) => {
+        let function = &closure.function;
+        if function.parameters.len() != args.len() {
+          return Err(runtime_error(format!(
+            "wrong number of arguments: want={}, got={}",
+            function.parameters.len(),
+            args.len()
+          )));
+        }
+        // The top level has a frame too, but isn't a call.
+        if !tail && self.frames.len() > self.recursion_limit {
+          return Err(self.recursion_error());
+        }
+
+        let mut locals: Vec<Rc<RefCell<Variable>>> = args
+          .into_iter()
+          .zip(&function.parameters)
+          .map(|(value, declared)| {
+            Rc::new(RefCell::new(Variable {
+              value,
+              mutable: false,
+              declared: Some(*declared),
+            }))
+          })
+          .collect();
+        // The other locals get their mutability once their declaration runs.
+        locals.resize_with(function.locals.len().max(locals.len()), || {
+          Rc::new(RefCell::new(Variable {
+            value: Object::DarkSide,
+            mutable: true,
+            declared: None,
+          }))
+        });
+
+        // The call instruction has been read, so the frame's ip is past it.
+        let caller = self.frame();
+        let call_site = caller.closure.function.span_at(caller.ip - 1);
+
+        let mut stack_base = self.stack.len();
+        if tail {
+          let frame = self.frames.pop().expect("no frame to replace");
+          self.stack.truncate(frame.stack_base);
+          stack_base = frame.stack_base;
+        }
+        self.frames.push(Frame {
+          closure,
+          ip: 0,
+          locals,
+          stack_base,
+          call_site,
+        });
+        Ok(())
+      }
+      Object::Builtin(builtin) => {
+        let result = (builtin.function)(&args);
+        self.push_result(result)
+      }
+      Object::Native(native) => {
+        let result = native.call(&args);
+        self.push_result(result)
+      }
+      _ => Err(runtime_error(format!(
+        "not a function: {}",
+        callee.type_name()
+      ))),
+    }
+  }
+
+  fn recursion_error(&self) -> RuntimeError {
+    let mut err = runtime_error("maximum recursion depth exceeded".to_owned());
+    err.trace = self.frames[1..]
+      .iter()
+      .rev()
+      .map(|frame| CallSite {
+        function: frame.closure.function.name.clone(),
+        span: frame.call_site,
+      })
+      .collect();
+    err
+  }
+
+  fn frame(&self) -> &Frame {
+    self.frames.last().expect("no frame")
+  }
+
+  fn frame_mut(&mut self) -> &mut Frame {
+    self.frames.last_mut().expect("no frame")
+  }
+
+  fn read_opcode(&mut self) -> Result<Opcode, RuntimeError> {
+    let byte = self.read_u8()?;
+    Opcode::try_from(byte as u8)
+      .map_err(|byte| corrupt(format!("unknown opcode {}", byte)))
+  }
+
+  fn read_u8(&mut self) -> Result<usize, RuntimeError> {
+    let frame = self.frame_mut();
+    let byte = *frame
+      .closure
+      .function
+      .instructions
+      .get(frame.ip)
+      .ok_or_else(|| corrupt("unexpected end of instructions".to_owned()))?;
+    frame.ip += 1;
+    Ok(byte as usize)
+  }
+
+  fn read_u16(&mut self) -> Result<usize, RuntimeError> {
+    let frame = self.frame_mut();
+    let bytes = frame
+      .closure
+      .function
+      .instructions
+      .get(frame.ip..frame.ip + 2)
+      .ok_or_else(|| corrupt("unexpected end of instructions".to_owned()))?;
+    let value = read_u16(bytes) as usize;
+    frame.ip += 2;
+    Ok(value)
+  }
+
+  fn constant(&self, index: usize) -> Result<&Object, RuntimeError> {
+    self
+      .constants
+      .get(index)
+      .ok_or_else(|| corrupt(format!("unknown constant {}", index)))
+  }
+
+  /// Makes the variable declared by the instruction being run, which was
+  /// compiled with the span of the declared name.
+  fn declare(&self, value: Object, mutable: bool) -> Variable {
+    let frame = self.frame();
+    Variable {
+      value,
+      mutable,
+      declared: frame.closure.function.span_at(frame.ip - 1),
+    }
+  }
+
+  fn local(
+    &self,
+    index: usize,
+  ) -> Result<&Rc<RefCell<Variable>>, RuntimeError> {
+    self
+      .frame()
+      .locals
+      .get(index)
+      .ok_or_else(|| corrupt(format!("unknown local {}", index)))
+  }
+
+  fn push(&mut self, obj: Object) {
+    self.stack.push(obj);
+  }
+
+  /// Pushes the result of a shared evaluator helper, turning its error
+  /// objects into errors of the virtual machine.
+  fn push_result(&mut self, obj: Object) -> Result<(), RuntimeError> {
+    match obj {
+      Object::Error(err) => Err(*err),
+      obj => {
+        self.push(obj);
+        Ok(())
+      }
+    }
+  }
+
+  fn pop(&mut self) -> Result<Object, RuntimeError> {
+    if self.stack.len() <= self.frame().stack_base {
+      return Err(corrupt("stack underflow".to_owned()));
+    }
+    Ok(self.stack.pop().expect("checked above"))
+  }
+
+  /// Pops the top `count` values, keeping them in the order they were pushed.
+  fn pop_many(&mut self, count: usize) -> Result<Vec<Object>, RuntimeError> {
+    let len = self.stack.len();
+    if len < self.frame().stack_base + count {
+      return Err(corrupt("stack underflow".to_owned()));
+    }
+    Ok(self.stack.split_off(len - count))
+  }
+}
+
+fn infix_operator(op: Opcode) -> &'static str {
+  match op {
+    Opcode::Add => "+",
+    Opcode::Sub => "-",
+    Opcode::Mul => "*",
+    Opcode::Div => "/",
+    Opcode::Mod => "%",
+    Opcode::Pow => "**",
+    Opcode::Equal => "==",
+    Opcode::NotEqual => "!=",
+    Opcode::LessThan => "<",
+    Opcode::GreaterThan => ">",
+    Opcode::LessEqual => "<=",
+    Opcode::GreaterEqual => ">=",
+    Opcode::Range => "..",
+    _ => unreachable!("{:?} is not an infix operator", op),
+  }
+}
+
+fn runtime_error(message: String) -> RuntimeError {
+  into_error(new_error(message))
+}
+
+/// Stores `value` in a variable the program assigns to by `name`, unless the
+/// variable is immutable.
+fn assign(
+  variable: &RefCell<Variable>,
+  value: Object,
+  name: &str,
+) -> Result<(), RuntimeError> {
+  let mut variable = variable.borrow_mut();
+  if !variable.mutable {
+    let err = immutable_assignment_error(name, variable.declared);
+    return Err(into_error(err));
+  }
+  variable.value = value;
+  Ok(())
+}
+
+fn into_error(obj: Object) -> RuntimeError {
+  match obj {
+    Object::Error(err) => *err,
+    obj => unreachable!("not an error: {:?}", obj),
+  }
+}
+
+/// Errors that only bytecode the compiler didn't produce can cause.
+fn corrupt(message: String) -> RuntimeError {
+  RuntimeError::new(format!("invalid bytecode: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    compiler::Compiler, environment::Environment, evaluator::eval_program,
+    interpreter::DEFAULT_RECURSION_LIMIT, lexer::Lexer, parser::Parser,
+  };
+
+  fn run_vm(input: &str) -> Result<Object, RuntimeError> {
+    let mut p = Parser::new(Lexer::new(input.to_owned()));
+    let program = p.parse_program().unwrap();
+    assert!(p.errors.is_empty(), "unexpected errors: {:?}", p.errors);
+
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    run(&bytecode, &mut vec![], DEFAULT_RECURSION_LIMIT)
+  }
+
+  fn run_evaluator(input: &str) -> Result<Object, RuntimeError> {
+    let mut p = Parser::new(Lexer::new(input.to_owned()));
+    let program = p.parse_program().unwrap();
+    let env = Rc::new(RefCell::new(Environment::new()));
+
+    match eval_program(&program, &env) {
+      Object::Error(err) => Err(*err),
+      obj => Ok(obj),
+    }
+  }
+
+  #[test]
+  fn test_integer_arithmetic() {
+    let tests = vec![
+      ("1", 1),
+      ("1 + 2", 3),
+      ("50 / 2 * 2 + 10 - 5", 55),
+      ("5 * (2 + 10)", 60),
+      ("-50 + 100 + -50", 0),
+      ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(run_vm(input), Ok(Object::Integer(expected)), "{}", input);
+    }
+  }
+
+  #[test]
+  fn test_recursive_fibonacci() {
+    let input = "
+      forge fibonacci = weave(x) {
+        if (x < 2) { ignite x; }
+        fibonacci(x - 1) + fibonacci(x - 2)
+      };
+      fibonacci(15);
+    ";
+
+    assert_eq!(run_vm(input), Ok(Object::Integer(610)));
+  }
+
+  #[test]
+  fn test_tail_calls() {
+    let input = "
+      forge count = weave(n, acc) {
+        if (n == 0) { acc } else { count(n - 1, acc + 1) }
+      };
+      count(100000, 0);
+    ";
+
+    assert_eq!(run_vm(input), Ok(Object::Integer(100000)));
+  }
+
+  #[test]
+  fn test_closures_share_variables() {
+    let tests = vec![
+      (
+        "
+        forge new_adder = weave(a, b) {
+          weave(c) { a + b + c };
+        };
+        new_adder(1, 2)(8);
+        ",
+        11,
+      ),
+      (
+        "
+        forge outer = weave() {
+          forge is_even = weave(n) { if (n == 0) { true } else { is_odd(n - 1) } };
+          forge is_odd = weave(n) { if (n == 0) { false } else { is_even(n - 1) } };
+          if (is_even(10)) { 1 } else { 0 }
+        };
+        outer();
+        ",
+        1,
+      ),
+      (
+        "
+        forge f = weave() {
+          forge x = 1;
+          forge get = weave() { x };
+          forge x = 2;
+          get()
+        };
+        f();
+        ",
+        2,
+      ),
+      ("forge g = weave() { h() }; forge h = weave() { 7 }; g();", 7),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(run_vm(input), Ok(Object::Integer(expected)), "{}", input);
+    }
+  }
+
+  #[test]
+  fn test_same_results_as_evaluator() {
+    let tests = vec![
+      "5; true; false; 1 < 2; 1 > 2; 1 == 1; true != false;",
+      "!true; !!5; -(-5); !(if (false) { 5; })",
+      "if (1 > 2) { 10 } else { 20 }",
+      "if (false) { 10 }",
+      "forge a = 1; forge b = a + 1; b * 2",
+      "forge a = 1;",
+      "ignite 5; 10;",
+      "if (10 > 1) { if (10 > 1) { ignite 10; } ignite 1; }",
+      r#""mon" + "key" + "banana""#,
+      r#""a" == "a""#,
+      "[1, 2 * 2, 3 + 3]",
+      "[1, 2, 3][1 + 1]",
+      "[[1, 1, 1]][0][0]",
+      r#"{"one": 1, 2: "two", true: [3]}["one"]"#,
+      r#"{1: 2}[3]"#,
+      r#"len("four") + len([1, 2]) + len({})"#,
+      "first(rest(push([1, 2], 3)))",
+      r#"type(weave() {}) + type(len) + str(12)"#,
+      "forge f = weave() { }; f()",
+      "forge f = weave(x) { forge y = x * 2; y }; f(3) + f(4)",
+      "
+      forge map = weave(arr, f) {
+        forge iter = weave(arr, acc) {
+          if (len(arr) == 0) { acc } else { iter(rest(arr), push(acc, f(first(arr)))) }
+        };
+        iter(arr, [])
+      };
+      map([1, 2, 3], weave(x) { x * x })
+      ",
+      "5 + true;",
+      "5; true + false; 5",
+      "-true",
+      "foobar",
+      "1 / 0",
+      "9223372036854775807 + 1",
+      "5(1)",
+      "forge f = weave(x, y) { x }; f(1);",
+      "forge f = weave(x) { x }; f(1, -true);",
+      r#""Hello" - "World""#,
+      "[1, 2, 3][3]",
+      "5[0]",
+      r#"{"name": "saber"}[weave(x) { x }];"#,
+      "{[1]: 2}",
+      "len(1)",
+      "forge f = weave() {\n  1 + true\n};\nf();",
+      "if (false) { undefined_name }",
+      "forge n = 0; for (x in 0..5) { forge n = n + x; }; n",
+      r#"forge s = ""; for (k in {"b": 1, "a": 2}) { forge s = s + k; }; s"#,
+      r#"forge s = ""; for (c in "abc") { forge s = c + s; }; s"#,
+      "forge i = 0; while (i < 3) { forge i = i + 1; }",
+      "forge n = 0; for (x in 0..10) { if (x > 4) { break; } forge n = n + x; }; n",
+      "forge n = 0; for (x in 0..10) { if (x < 8) { continue; } forge n = n + x; }; n",
+      "forge n = 0; for (x in 0..3) { forge n = n + len([x, if (x == 1) { continue; }]); }; n",
+      "forge n = 0; for (x in 0..3) { forge n = n + len([x, [n, if (x == 1) { break; }]]); }; [n, 1]",
+      "forge f = weave(xs) { for (x in xs) { if (x > 1) { ignite x; } } }; [f([1, 5]), f([])]",
+      "for (x in [1]) { forge f = weave() { x }; f() }",
+      "forge f = weave() { [1, if (true) { ignite 2; }] }; [f(), 3]",
+      "for (x in true) { x }",
+      "for (x in 0..2) { x + true }",
+      "0..3",
+      "[1..3 == 1..3, 1..3 != 0..3, 3..1 == 5..2]",
+      "0..1 < 0..2",
+      "9223372036854775806..9223372036854775807",
+      "flux x = 1; x = x + 1; x",
+      "flux x = 1; flux y = 2; [x = y = 7, x, y]",
+      "flux x = 10; x += 5; x -= 3; x *= 2; x /= 4; x",
+      "flux a = [1, [2, 3]]; a[1][0] += 40; [a[1][0] = 7, a]",
+      r#"flux h = {"k": 1}; h["k"] = 5; h["new"] = 6; h"#,
+      "flux a = [1]; forge b = a; a[0] = 9; [a, b]",
+      "flux n = 0; forge add = weave(x) { n += x; }; add(3); add(4); n",
+      "forge counter = weave() { flux c = 0; [weave() { c += 1 }, weave() { c }] }; forge fs = counter(); fs[0](); fs[0](); fs[1]()",
+      "forge f = weave() { flux a = [0, 0]; for (i in 0..2) { a[i] = i * 10; }; a }; f()",
+      "forge f = weave(x) { flux x = x; x = x * 2; x }; flux x = 3; f(5) + x",
+      "forge x = 1; flux x = x; x += 1; x",
+      "x = 1",
+      "forge f = weave() { y = 1 }; f()",
+      "x[0] = 1",
+      "flux a = [1]; a[1] = 2",
+      "flux a = 5; a[0] = 2",
+      "flux h = {}; h[[]] = 2",
+      r#"flux h = {}; h["a"]["b"] = 2"#,
+      "flux a = true; a += 1",
+      "flux a = [[1]]; a[0][0] += true",
+      "flux a = [1, 2]; a[5] += 1 / 0",
+      "flux a = [1, 2]; a[0][0] = 1 / 0",
+      "flux a = [1, 2]; a[0][0] = 1",
+      "flux a = [1, 2]; a[0][0] += 1",
+      "flux n = 0; flux a = [1, 2]; a[n += 1] += n; [a, n]",
+      "forge f = weave() { later = 1 }; forge later = 0; f(); later",
+      "forge f = weave() { later = 1 }; flux later = 0; f(); later",
+      "forge x = 1; if (false) { flux x = 2; }; x = 3; x",
+      "if (false) { forge y = 1; y = 2 }; 5",
+      "weave() { forge a = 1; a = 2 }; 7",
+      "forge a = [1, 2]; a[0][0] = 1",
+      "forge a = [1, 2]; a[0] += 1 / 0",
+      "forge f = weave(p) { p += 1 }; f(1)",
+      "for (i in 0..3) { forge g = weave() { i = 1 }; g() }",
+      "forge f = weave(c) { forge a = 1; if (c) { flux a = 2 }; a = 3 }; [f(true), f(false)]",
+      "forge f = weave() { flux c = 0; forge g = weave() { c += 1 }; forge c = c; g() }; f()",
+      "flux n = 0;\nforge n = 1;\nn = 2",
+      "[true && 1, 0 && false, false || if (false) { 1 }]",
+      "[1 < 2 && 2 < 3 || false, !true || !false, false && 1 / 0]",
+      "flux n = 0; forge f = weave() { n += 1; true }; [false && f(), true || f(), true && f(), false || f(), n]",
+      "flux n = 0; for (x in 0..5) { if (x > 1 && x < 4) { n += x; } }; n",
+      "forge f = weave(n) { n == 0 || f(n - 1) }; f(10)",
+      "forge f = weave(x) { x > 0 && x < 10 }; [f(5), f(0), f(10)]",
+      "true && 1 / 0",
+      "-true || true",
+      "[7 % 3, -7 % 3, 7 % -3, -7 % -3, (-9223372036854775807 - 1) % -1]",
+      "[2 ** 10, 2 ** 3 ** 2, -2 ** 2, 0 ** 0, (-1) ** 9223372036854775807]",
+      "[1 <= 2, 2 <= 2, 3 <= 2, 1 >= 2, 2 >= 2, 3 >= 2]",
+      "forge f = weave(n) { if (n <= 1) { 1 } else { n * f(n - 1) } }; f(20)",
+      "flux n = 0; for (x in 0..20) { if (x % 3 == 0) { n += x ** 2; } }; n",
+      "5 % 0",
+      "2 ** -1",
+      "2 ** 63",
+      r#""a" >= "b""#,
+    ];
+
+    for input in tests {
+      assert_eq!(run_vm(input), run_evaluator(input), "input: {}", input);
+    }
+  }
+
+  #[test]
+  fn test_assign_to_immutable_binding() {
+    let tests = vec![
+      ("forge x = 1;\nx = 2", "x", (1, 7), (2, 1)),
+      ("forge a = [1];\na[0] += 1", "a", (1, 7), (2, 1)),
+      ("flux n = 0;\nforge n = 1;\nn = 2", "n", (2, 7), (3, 1)),
+      (
+        "forge f = weave(p) {\n  p = 1\n};\nf(0)",
+        "p",
+        (1, 17),
+        (2, 3),
+      ),
+      (
+        "for (i in 0..3) {\n  forge g = weave() { i = 1 };\n  g();\n}",
+        "i",
+        (1, 6),
+        (2, 23),
+      ),
+      (
+        "forge f = weave() { later = 1 };\nforge later = 0;\nf()",
+        "later",
+        (2, 7),
+        (1, 21),
+      ),
+    ];
+
+    for (input, name, declared, assigned) in tests {
+      let err = run_vm(input).unwrap_err();
+      assert_eq!(
+        err.message,
+        format!("cannot assign to immutable binding: {}", name),
+        "input: {}",
+        input
+      );
+      let span = err.span.expect("missing span");
+      assert_eq!(
+        (span.start.line, span.start.column),
+        assigned,
+        "input: {}",
+        input
+      );
+      let (span, label) = *err.related.expect("missing declaration span");
+      assert_eq!((span.start.line, span.start.column), declared);
+      assert_eq!(label, "declared immutable here");
+    }
+  }
+
+  #[test]
+  fn test_globals_survive_between_runs() {
+    let mut compiler = Compiler::new();
+    let mut globals = vec![];
+
+    for (input, expected) in [
+      ("forge a = 40;", Object::DarkSide),
+      ("forge add = weave(b) { a + b };", Object::DarkSide),
+      ("add(2)", Object::Integer(42)),
+    ] {
+      let mut p = Parser::new(Lexer::new(input.to_owned()));
+      let program = p.parse_program().unwrap();
+      let bytecode = compiler.compile(&program).unwrap();
+      assert_eq!(
+        run(&bytecode, &mut globals, DEFAULT_RECURSION_LIMIT),
+        Ok(expected)
+      );
+    }
+  }
+}